@@ -0,0 +1,4 @@
+-- Keep track of how a device has been added to the gateway.
+--
+-- Devices added manually are never removed by a discovery.
+ALTER TABLE devices ADD COLUMN source TEXT NOT NULL DEFAULT 'discovery';
@@ -0,0 +1,2 @@
+-- UTC epoch, in seconds, of the last successful device data retrieval.
+ALTER TABLE devices ADD COLUMN last_retrieved INTEGER;
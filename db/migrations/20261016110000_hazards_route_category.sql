@@ -0,0 +1,3 @@
+-- Associate each hazard with its route and category.
+ALTER TABLE hazards ADD COLUMN route_id INTEGER REFERENCES routes(id) ON DELETE CASCADE;
+ALTER TABLE hazards ADD COLUMN category TEXT;
@@ -0,0 +1,2 @@
+-- Addresses with a higher priority are contacted first.
+ALTER TABLE addresses ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- mDNS full name of discovered devices.
+ALTER TABLE devices ADD COLUMN fullname TEXT;
@@ -0,0 +1,4 @@
+-- Store each address of a device once, so that rediscovered addresses keep
+-- their stored data.
+DELETE FROM addresses WHERE id NOT IN (SELECT MIN(id) FROM addresses GROUP BY device_id, address);
+CREATE UNIQUE INDEX addresses_device_address ON addresses(device_id, address);
@@ -0,0 +1,8 @@
+-- Text inputs of device routes.
+CREATE TABLE IF NOT EXISTS texts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    default_value TEXT NOT NULL,
+    value TEXT NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- Select inputs of device routes.
+--
+-- Allowed options are stored as a JSON array.
+CREATE TABLE IF NOT EXISTS selects (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    options TEXT NOT NULL,
+    default_value TEXT NOT NULL,
+    value TEXT NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
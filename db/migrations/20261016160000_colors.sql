@@ -0,0 +1,8 @@
+-- Color inputs of device routes, stored as `#RRGGBB` strings.
+CREATE TABLE IF NOT EXISTS colors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    default_value TEXT NOT NULL,
+    value TEXT NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
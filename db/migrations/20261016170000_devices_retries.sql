@@ -0,0 +1,6 @@
+-- Keep track of consecutive failed retrievals of a device.
+--
+-- A device is contacted again only after `next_retry`, and deleted after
+-- too many consecutive failures.
+ALTER TABLE devices ADD COLUMN retry_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE devices ADD COLUMN next_retry INTEGER;
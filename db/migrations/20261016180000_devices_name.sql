@@ -0,0 +1,2 @@
+-- Human-friendly name given to a device by the user.
+ALTER TABLE devices ADD COLUMN name TEXT;
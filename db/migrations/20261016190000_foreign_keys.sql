@@ -0,0 +1,172 @@
+-- Rebuild every table referencing a device or a route, so that each of them
+-- declares its foreign keys and is cleared on cascade when a device is
+-- deleted.
+--
+-- SQLite cannot add constraints to an existing table, hence tables are
+-- renamed, created again and filled with their old rows. Rows referencing a
+-- missing device or route are dropped.
+--
+-- Renaming a table updates the references of its child tables, so the old
+-- tables are dropped from the children to the parents, and their implicit
+-- deletions never reach the new tables.
+ALTER TABLE addresses RENAME TO addresses_old;
+ALTER TABLE properties RENAME TO properties_old;
+ALTER TABLE main_routes RENAME TO main_routes_old;
+ALTER TABLE routes RENAME TO routes_old;
+ALTER TABLE hazards RENAME TO hazards_old;
+ALTER TABLE booleans RENAME TO booleans_old;
+ALTER TABLE rangesu64 RENAME TO rangesu64_old;
+ALTER TABLE rangesf64 RENAME TO rangesf64_old;
+ALTER TABLE texts RENAME TO texts_old;
+ALTER TABLE selects RENAME TO selects_old;
+ALTER TABLE colors RENAME TO colors_old;
+
+-- Device addresses.
+CREATE TABLE addresses (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    address TEXT NOT NULL,
+    device_id INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    priority INTEGER NOT NULL DEFAULT 0
+);
+INSERT INTO addresses(id, address, device_id, priority)
+    SELECT id, address, device_id, priority FROM addresses_old
+    WHERE device_id IN (SELECT id FROM devices);
+
+-- Device properties.
+CREATE TABLE properties (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    device_id INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE
+);
+INSERT INTO properties(key, value, device_id)
+    SELECT key, value, device_id FROM properties_old
+    WHERE device_id IN (SELECT id FROM devices);
+
+-- Device main routes.
+CREATE TABLE main_routes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    route TEXT NOT NULL,
+    device_id INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE
+);
+INSERT INTO main_routes(id, route, device_id)
+    SELECT id, route, device_id FROM main_routes_old
+    WHERE device_id IN (SELECT id FROM devices);
+
+-- Device routes, keeping their identifiers since inputs refer to them.
+CREATE TABLE routes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    route TEXT NOT NULL,
+    device_id INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE
+);
+INSERT INTO routes(id, route, device_id)
+    SELECT id, route, device_id FROM routes_old
+    WHERE device_id IN (SELECT id FROM devices);
+
+-- Hazards of device routes.
+CREATE TABLE hazards (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    hazard_id INTEGER NOT NULL,
+    device_id INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    route_id INTEGER REFERENCES routes(id) ON DELETE CASCADE,
+    category TEXT
+);
+INSERT INTO hazards(hazard_id, device_id, route_id, category)
+    SELECT hazard_id, device_id, route_id, category FROM hazards_old
+    WHERE device_id IN (SELECT id FROM devices)
+    AND (route_id IS NULL OR route_id IN (SELECT id FROM routes));
+
+-- Boolean inputs of device routes.
+CREATE TABLE booleans (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    default_value BOOLEAN NOT NULL,
+    value BOOLEAN NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
+INSERT INTO booleans(name, default_value, value, route_id)
+    SELECT name, default_value, value, route_id FROM booleans_old
+    WHERE route_id IN (SELECT id FROM routes);
+
+-- Range inputs of device routes for u64.
+CREATE TABLE rangesu64 (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    min INTEGER NOT NULL,
+    max INTEGER NOT NULL,
+    step INTEGER NOT NULL,
+    default_value INTEGER NOT NULL,
+    value INTEGER NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
+INSERT INTO rangesu64(name, min, max, step, default_value, value, route_id)
+    SELECT name, min, max, step, default_value, value, route_id FROM rangesu64_old
+    WHERE route_id IN (SELECT id FROM routes);
+
+-- Range inputs of device routes for f64.
+CREATE TABLE rangesf64 (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    min REAL NOT NULL,
+    max REAL NOT NULL,
+    step REAL NOT NULL,
+    default_value REAL NOT NULL,
+    value REAL NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
+INSERT INTO rangesf64(name, min, max, step, default_value, value, route_id)
+    SELECT name, min, max, step, default_value, value, route_id FROM rangesf64_old
+    WHERE route_id IN (SELECT id FROM routes);
+
+-- Text inputs of device routes.
+CREATE TABLE texts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    default_value TEXT NOT NULL,
+    value TEXT NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
+INSERT INTO texts(name, default_value, value, route_id)
+    SELECT name, default_value, value, route_id FROM texts_old
+    WHERE route_id IN (SELECT id FROM routes);
+
+-- Select inputs of device routes.
+CREATE TABLE selects (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    options TEXT NOT NULL,
+    default_value TEXT NOT NULL,
+    value TEXT NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
+INSERT INTO selects(name, options, default_value, value, route_id)
+    SELECT name, options, default_value, value, route_id FROM selects_old
+    WHERE route_id IN (SELECT id FROM routes);
+
+-- Color inputs of device routes.
+CREATE TABLE colors (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    default_value TEXT NOT NULL,
+    value TEXT NOT NULL,
+    route_id INTEGER NOT NULL REFERENCES routes(id) ON DELETE CASCADE
+);
+INSERT INTO colors(name, default_value, value, route_id)
+    SELECT name, default_value, value, route_id FROM colors_old
+    WHERE route_id IN (SELECT id FROM routes);
+
+-- Drop the old tables, from the children to the parents.
+DROP TABLE booleans_old;
+DROP TABLE rangesu64_old;
+DROP TABLE rangesf64_old;
+DROP TABLE texts_old;
+DROP TABLE selects_old;
+DROP TABLE colors_old;
+DROP TABLE hazards_old;
+DROP TABLE routes_old;
+DROP TABLE main_routes_old;
+DROP TABLE properties_old;
+DROP TABLE addresses_old;
+
+-- Indexes are dropped together with their tables.
+CREATE UNIQUE INDEX addresses_device_address ON addresses(device_id, address);
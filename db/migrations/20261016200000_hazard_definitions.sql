@@ -0,0 +1,7 @@
+-- Hazard definitions, shared by every route presenting a hazard.
+CREATE TABLE IF NOT EXISTS hazard_definitions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    category_description TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Status and body of the last device response to a control request.
+ALTER TABLE devices ADD COLUMN last_response_status INTEGER;
+ALTER TABLE devices ADD COLUMN last_response_body TEXT;
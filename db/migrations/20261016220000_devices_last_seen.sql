@@ -0,0 +1,2 @@
+-- UTC epoch, in seconds, of the last discovery of a device.
+ALTER TABLE devices ADD COLUMN last_seen INTEGER;
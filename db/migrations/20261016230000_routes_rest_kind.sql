@@ -0,0 +1,4 @@
+-- HTTP method of each device route.
+--
+-- Routes stored before were all invoked through `PUT`.
+ALTER TABLE routes ADD COLUMN rest_kind TEXT NOT NULL DEFAULT 'PUT';
@@ -0,0 +1,2 @@
+-- Description of each device route, when advertised by the device.
+ALTER TABLE routes ADD COLUMN description TEXT;
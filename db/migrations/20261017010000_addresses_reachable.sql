@@ -0,0 +1,4 @@
+-- Whether a device address has answered the last connection attempt.
+--
+-- Addresses stored before are assumed to be reachable.
+ALTER TABLE addresses ADD COLUMN reachable BOOLEAN NOT NULL DEFAULT 1;
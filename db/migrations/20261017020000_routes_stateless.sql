@@ -0,0 +1,2 @@
+-- Whether each device route is stateless, hence its inputs keep no value.
+ALTER TABLE routes ADD COLUMN stateless BOOLEAN NOT NULL DEFAULT FALSE;
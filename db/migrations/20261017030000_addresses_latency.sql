@@ -0,0 +1,2 @@
+-- Milliseconds a device address has taken to answer the last time.
+ALTER TABLE addresses ADD COLUMN latency INTEGER;
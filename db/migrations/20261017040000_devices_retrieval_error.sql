@@ -0,0 +1,2 @@
+-- Error of the last retrieval of a device which has sent invalid data.
+ALTER TABLE devices ADD COLUMN retrieval_error TEXT;
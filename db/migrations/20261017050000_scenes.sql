@@ -0,0 +1,17 @@
+-- Named groups of control settings, applied all at once.
+CREATE TABLE IF NOT EXISTS scenes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+-- Control settings of a scene, applied in their order.
+--
+-- Values are stored as JSON, as sent to the batch endpoint.
+CREATE TABLE IF NOT EXISTS scene_items (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scene_id INTEGER NOT NULL REFERENCES scenes(id) ON DELETE CASCADE,
+    device_id INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    route TEXT NOT NULL,
+    value TEXT,
+    confirm BOOLEAN NOT NULL DEFAULT FALSE
+);
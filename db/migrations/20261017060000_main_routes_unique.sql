@@ -0,0 +1,4 @@
+-- Store a single main route for each device, keeping the latest one, so
+-- that retrievals replace it instead of adding rows.
+DELETE FROM main_routes WHERE id NOT IN (SELECT MAX(id) FROM main_routes GROUP BY device_id);
+CREATE UNIQUE INDEX main_routes_device ON main_routes(device_id);
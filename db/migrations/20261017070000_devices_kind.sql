@@ -0,0 +1,3 @@
+-- Kind of a device, saved at each retrieval so that devices can be sorted
+-- by kind before being retrieved.
+ALTER TABLE devices ADD COLUMN kind TEXT;
@@ -0,0 +1,143 @@
+use rocket::http::uri::Origin;
+
+use rocket_db_pools::Connection;
+
+use crate::config::GatewayConfig;
+use crate::database::query::select_route_hazard_categories;
+use crate::database::Devices;
+use crate::error::{query_error, GatewayError};
+
+// Why a device route cannot be actuated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Refusal {
+    // The route presents a hazard category denied by the gateway.
+    Denied,
+    // The route presents hazards which have not been confirmed.
+    Unconfirmed,
+}
+
+impl Refusal {
+    // Explains why the route cannot be actuated.
+    pub(crate) fn message(&self) -> &'static str {
+        match self {
+            Self::Denied => "Route hazards are denied by the gateway",
+            Self::Unconfirmed => "Route hazards must be confirmed",
+        }
+    }
+
+    // Convert the refusal into the error answered to a request.
+    pub(crate) fn into_gateway_error(self, uri: &Origin<'_>) -> GatewayError {
+        match self {
+            Self::Denied => GatewayError::forbidden(uri, self.message()),
+            Self::Unconfirmed => GatewayError::bad_input(uri, self.message()),
+        }
+    }
+}
+
+// Checks whether a device route can be actuated.
+//
+// Every request sent to a device route goes through this check, so that no
+// path escapes the gateway policies.
+//
+// Routes presenting hazards are actuated only when the request confirms
+// them, so that a single mis-click cannot trigger them.
+//
+// Database failures are returned as errors, while refusals are returned as
+// the reason why the route cannot be actuated.
+pub(crate) async fn check_route(
+    db: &mut Connection<Devices>,
+    config: &GatewayConfig,
+    route_id: u16,
+    hazards_confirmed: bool,
+    uri: &Origin<'_>,
+) -> Result<Result<(), Refusal>, GatewayError> {
+    let categories = query_error(select_route_hazard_categories(db, route_id), uri).await?;
+
+    // Routes presenting a denied hazard category are never actuated.
+    if config.is_denied(categories.iter().map(String::as_str)) {
+        return Ok(Err(Refusal::Denied));
+    }
+
+    if !categories.is_empty() && !hazards_confirmed {
+        return Ok(Err(Refusal::Unconfirmed));
+    }
+
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::database::query::{insert_device, insert_hazard, insert_route};
+    use crate::database::{test_connection, RouteMethod};
+
+    // Store a route presenting a hazard of the given category, returning
+    // its identifier.
+    async fn hazardous_route(db: &mut Connection<Devices>, category: &str) -> u16 {
+        let device_id = insert_device(db, "light", 3000, "http", "/").await.unwrap();
+        let route_id = insert_route(db, "/on", RouteMethod::Put, None, false, device_id)
+            .await
+            .unwrap();
+        insert_hazard(db, 0, category, route_id, device_id)
+            .await
+            .unwrap();
+        route_id
+    }
+
+    fn denying(categories: &[&str]) -> GatewayConfig {
+        GatewayConfig {
+            denied_hazard_categories: categories.iter().map(|c| c.to_string()).collect(),
+            ..GatewayConfig::default()
+        }
+    }
+
+    #[rocket::async_test]
+    async fn denied_categories_are_never_actuated() {
+        let (_client, mut db) = test_connection().await;
+        let route_id = hazardous_route(&mut db, "Safety").await;
+        let uri = Origin::ROOT;
+
+        let outcome = check_route(&mut db, &denying(&["Safety"]), route_id, true, &uri).await;
+
+        assert!(matches!(outcome, Ok(Err(Refusal::Denied))));
+    }
+
+    #[rocket::async_test]
+    async fn other_categories_are_actuated() {
+        let (_client, mut db) = test_connection().await;
+        let route_id = hazardous_route(&mut db, "Safety").await;
+        let uri = Origin::ROOT;
+
+        let outcome = check_route(&mut db, &denying(&["Financial"]), route_id, true, &uri).await;
+
+        assert!(matches!(outcome, Ok(Ok(()))));
+    }
+
+    #[rocket::async_test]
+    async fn hazards_must_be_confirmed() {
+        let (_client, mut db) = test_connection().await;
+        let route_id = hazardous_route(&mut db, "Safety").await;
+        let uri = Origin::ROOT;
+
+        let outcome = check_route(&mut db, &denying(&[]), route_id, false, &uri).await;
+
+        assert!(matches!(outcome, Ok(Err(Refusal::Unconfirmed))));
+    }
+
+    #[rocket::async_test]
+    async fn routes_without_hazards_need_no_confirmation() {
+        let (_client, mut db) = test_connection().await;
+        let device_id = insert_device(&mut db, "light", 3000, "http", "/")
+            .await
+            .unwrap();
+        let route_id = insert_route(&mut db, "/on", RouteMethod::Put, None, false, device_id)
+            .await
+            .unwrap();
+        let uri = Origin::ROOT;
+
+        let outcome = check_route(&mut db, &denying(&[]), route_id, false, &uri).await;
+
+        assert!(matches!(outcome, Ok(Ok(()))));
+    }
+}
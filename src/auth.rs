@@ -0,0 +1,79 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use sha2::{Digest, Sha256};
+
+use rocket::http::{Method, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::config::{AuthConfig, GatewayConfig};
+
+// Private cookie holding the name of the logged in user.
+pub(crate) const SESSION_COOKIE: &str = "session";
+
+// Request allowed to use the gateway.
+//
+// When authentication is configured, requests changing anything must come
+// from a logged in user, either through the session cookie or through HTTP
+// basic authentication. Read-only requests are checked only when
+// configured.
+pub(crate) struct Authenticated;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authenticated {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(auth) = request
+            .rocket()
+            .state::<GatewayConfig>()
+            .and_then(|config| config.auth.as_ref())
+        else {
+            return Outcome::Success(Self);
+        };
+
+        let read_only = matches!(request.method(), Method::Get | Method::Head);
+        if (read_only && !auth.protect_reads) || is_logged_in(request, auth) {
+            Outcome::Success(Self)
+        } else {
+            Outcome::Error((Status::Unauthorized, ()))
+        }
+    }
+}
+
+// Compares two secrets.
+//
+// Both secrets are hashed first, so that digests of the same length are
+// compared. Every byte is compared, so that the time taken reveals neither
+// how much of a secret is right nor its length.
+pub(crate) fn secrets_match(a: &str, b: &str) -> bool {
+    Sha256::digest(a)
+        .iter()
+        .zip(Sha256::digest(b).iter())
+        .fold(0, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+// Checks whether a request comes from the configured user.
+fn is_logged_in(request: &Request<'_>, auth: &AuthConfig) -> bool {
+    if request
+        .cookies()
+        .get_private(SESSION_COOKIE)
+        .is_some_and(|cookie| cookie.value() == auth.username)
+    {
+        return true;
+    }
+
+    // HTTP basic authentication, for clients without cookies.
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .is_some_and(|decoded| {
+            decoded
+                .split_once(':')
+                .is_some_and(|(username, password)| auth.verify(username, password))
+        })
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rocket::fairing::AdHoc;
+
+use serde::Deserialize;
+
+use crate::auth::secrets_match;
+use crate::limiter::RateLimiter;
+use crate::request::DeviceClient;
+use crate::service::ServiceState;
+use crate::text::TextLimits;
+use crate::time::Timezone;
+use crate::SERVICE_TYPE;
+
+// Gateway configuration.
+//
+// Values are read from the `gateway` table of the `Rocket.toml` file.
+// Every missing value falls back to its default.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct GatewayConfig {
+    // Whether debugging routes are enabled.
+    pub(crate) debug: bool,
+    // Timezone used to display timestamps.
+    pub(crate) timezone: Timezone,
+    // Seconds after which device data are considered stale.
+    pub(crate) stale_after: u64,
+    // Hazard categories whose routes cannot be actuated through the gateway.
+    pub(crate) denied_hazard_categories: Vec<String>,
+    // Display limits for texts supplied by devices.
+    pub(crate) text_limits: TextLimits,
+    // Devices discovery configuration.
+    pub(crate) discovery: DiscoveryConfig,
+    // Unreachable devices retry configuration.
+    pub(crate) retry: RetryConfig,
+    // Devices HTTP requests configuration.
+    pub(crate) http: HttpConfig,
+    // Requests to each device rate limit.
+    pub(crate) rate_limit: RateLimitConfig,
+    // Gateway authentication, disabled when missing.
+    pub(crate) auth: Option<AuthConfig>,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            timezone: Timezone::default(),
+            stale_after: 300,
+            denied_hazard_categories: Vec::new(),
+            text_limits: TextLimits::default(),
+            discovery: DiscoveryConfig::default(),
+            retry: RetryConfig::default(),
+            http: HttpConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            auth: None,
+        }
+    }
+}
+
+impl GatewayConfig {
+    // Checks whether any of the given hazard categories is denied.
+    pub(crate) fn is_denied<'a>(&self, mut categories: impl Iterator<Item = &'a str>) -> bool {
+        categories.any(|category| {
+            self.denied_hazard_categories
+                .iter()
+                .any(|denied| denied == category)
+        })
+    }
+}
+
+// Devices discovery configuration.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct DiscoveryConfig {
+    // mDNS service types browsed during a discovery.
+    pub(crate) service_types: Vec<String>,
+    // Milliseconds to wait for a device to answer before ending a discovery.
+    timeout: u64,
+    // Maximum milliseconds a discovery can last.
+    deadline: Option<u64>,
+    // Seconds after which discovered devices not seen again are removed.
+    pub(crate) device_ttl: Option<u64>,
+    // Seconds between two background discoveries, disabled when missing.
+    interval: Option<u64>,
+    // IP family of the device addresses to save.
+    pub(crate) address_filter: AddressFilter,
+    // Network interface discovery is restricted to, all when missing.
+    pub(crate) interface: Option<String>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            service_types: vec![SERVICE_TYPE.into()],
+            timeout: 1000,
+            deadline: None,
+            device_ttl: None,
+            interval: None,
+            address_filter: AddressFilter::default(),
+            interface: None,
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    // Time to wait for a device to answer.
+    #[inline]
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout)
+    }
+
+    // Maximum duration of a discovery.
+    #[inline]
+    pub(crate) fn deadline(&self) -> Option<Duration> {
+        self.deadline.map(Duration::from_millis)
+    }
+
+    // Time between two background discoveries.
+    #[inline]
+    pub(crate) fn interval(&self) -> Option<Duration> {
+        self.interval.map(Duration::from_secs)
+    }
+
+    // Service type of a device, identified by its mDNS full name.
+    pub(crate) fn service_type(&self, fullname: &str) -> Option<&str> {
+        self.service_types
+            .iter()
+            .map(String::as_str)
+            .find(|service_type| fullname.ends_with(&format!(".{service_type}")))
+    }
+}
+
+// Unreachable devices retry configuration.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct RetryConfig {
+    // Consecutive failed retrievals after which a device is deleted.
+    pub(crate) max_retries: u32,
+    // Seconds to wait before contacting again a device after its first
+    // failed retrieval, doubled at each further failure.
+    pub(crate) backoff: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            backoff: 30,
+        }
+    }
+}
+
+// Requests to each device rate limit.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct RateLimitConfig {
+    // Requests a device can receive in a row.
+    pub(crate) burst: u32,
+    // Requests a device can receive each second, once the burst is over.
+    pub(crate) per_second: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            burst: 10,
+            per_second: 2.0,
+        }
+    }
+}
+
+// Devices HTTP requests configuration.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct HttpConfig {
+    // Milliseconds to wait for a connection to a device.
+    connect_timeout: u64,
+    // Milliseconds a device has to answer a request.
+    timeout: u64,
+    // Whether devices presenting an invalid TLS certificate, such as a
+    // self-signed one, are contacted anyway.
+    //
+    // Any certificate is then trusted, so anyone on the network can
+    // impersonate a device and read the data sent to it. Enable it only on
+    // closed networks, and prefer `ca_certificate` whenever possible.
+    pub(crate) accept_invalid_certs: bool,
+    // Path of a PEM certificate trusted in addition to the system ones, such
+    // as the authority which has signed the device certificates.
+    pub(crate) ca_certificate: Option<PathBuf>,
+    // User-Agent header sent to devices.
+    pub(crate) user_agent: String,
+    // Headers sent with every request to devices, such as an API key.
+    pub(crate) headers: HashMap<String, String>,
+    // Credentials of protected devices, by `scheme://host`.
+    //
+    // Credentials are never sent to every device, since any device on the
+    // network can be discovered and collect them.
+    pub(crate) credentials: HashMap<String, Credentials>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 3000,
+            timeout: 3000,
+            accept_invalid_certs: false,
+            ca_certificate: None,
+            user_agent: concat!("ascot-gateway/", env!("CARGO_PKG_VERSION")).into(),
+            headers: HashMap::new(),
+            credentials: HashMap::new(),
+        }
+    }
+}
+
+impl HttpConfig {
+    // Time to wait for a connection to a device.
+    #[inline]
+    pub(crate) fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout)
+    }
+
+    // Time a device has to answer a request.
+    #[inline]
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout)
+    }
+}
+
+// Gateway authentication configuration.
+#[derive(Debug, Deserialize)]
+pub(crate) struct AuthConfig {
+    // Name of the user allowed to use the gateway.
+    pub(crate) username: String,
+    // Password of the user.
+    password: String,
+    // Whether read-only routes require authentication too.
+    #[serde(default)]
+    pub(crate) protect_reads: bool,
+}
+
+impl AuthConfig {
+    // Checks the credentials of the user.
+    pub(crate) fn verify(&self, username: &str, password: &str) -> bool {
+        secrets_match(username, &self.username) & secrets_match(password, &self.password)
+    }
+}
+
+// Credentials sent to a protected device.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Credentials {
+    // HTTP basic authentication.
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    // Bearer token.
+    Bearer(String),
+}
+
+// IP family of the device addresses to save.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub(crate) enum AddressFilter {
+    // Only IPv4 addresses.
+    Ipv4Only,
+    // Only IPv6 addresses.
+    Ipv6Only,
+    // Both IPv4 and IPv6 addresses.
+    #[default]
+    Both,
+}
+
+impl AddressFilter {
+    // Checks whether an address belongs to the allowed family.
+    #[inline]
+    pub(crate) fn allows(self, address: &IpAddr) -> bool {
+        match self {
+            Self::Ipv4Only => address.is_ipv4(),
+            Self::Ipv6Only => address.is_ipv6(),
+            Self::Both => true,
+        }
+    }
+}
+
+// Create a middle layer to read the gateway configuration during server
+// creation.
+pub(crate) fn stage() -> AdHoc {
+    AdHoc::try_on_ignite("Gateway Config", |rocket| async {
+        let config = match rocket.figment().focus("gateway").extract::<GatewayConfig>() {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Invalid gateway configuration: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        // Build the client shared among all requests to devices.
+        let client = match DeviceClient::new(&config.http) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Failed to build the devices HTTP client: {}", e);
+                return Err(rocket);
+            }
+        };
+
+        // Create the mDNS daemon, restricted to the configured interface.
+        match ServiceState::new(config.discovery.interface.clone()) {
+            Ok(state) => {
+                let limiter = RateLimiter::new(&config.rate_limit);
+                Ok(rocket
+                    .manage(config)
+                    .manage(client)
+                    .manage(state)
+                    .manage(limiter))
+            }
+            Err(e) => {
+                error!("Failed to create the mDNS daemon: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn service_types_are_found_through_full_names() {
+        let config = DiscoveryConfig {
+            service_types: vec![SERVICE_TYPE.into(), "_ascot-secure._tcp.local.".into()],
+            ..DiscoveryConfig::default()
+        };
+
+        assert_eq!(
+            config.service_type("light._ascot._tcp.local."),
+            Some(SERVICE_TYPE)
+        );
+        assert_eq!(
+            config.service_type("light._ascot-secure._tcp.local."),
+            Some("_ascot-secure._tcp.local.")
+        );
+        assert_eq!(config.service_type("light._http._tcp.local."), None);
+    }
+
+    #[test]
+    fn discovery_uses_every_interface_by_default() {
+        let config: DiscoveryConfig = rocket::figment::Figment::new().extract().unwrap();
+        assert_eq!(config.interface, None);
+    }
+}
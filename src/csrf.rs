@@ -0,0 +1,99 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use rocket::fairing::AdHoc;
+use rocket::form::Form;
+use rocket::http::{Cookie, Method, SameSite, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::auth::secrets_match;
+
+// Private cookie holding the token of a browser.
+pub(crate) const CSRF_COOKIE: &str = "csrf";
+
+// Form field carrying the token of a form.
+pub(crate) const CSRF_FIELD: &str = "csrf";
+
+// Header carrying the token of a request sent by a script.
+pub(crate) const CSRF_HEADER: &str = "X-CSRF-Token";
+
+// Bytes of a form body read in search of the token.
+//
+// Forms send the token among their first fields, and this is the most
+// Rocket can peek at without consuming the body.
+const FORM_PEEK_LIMIT: usize = 512;
+
+// Token against cross-site request forgery.
+//
+// Pages receive the token stored in a private cookie, which is issued when
+// missing. Requests changing anything have to send the token back, through
+// the `csrf` form field or the `X-CSRF-Token` header, otherwise they are
+// forbidden. Other sites cannot read the token, and browsers do not send
+// them the cookie.
+//
+// The token never travels in URLs, so that it does not end up in logs or in
+// the history of browsers.
+pub(crate) struct CsrfToken(String);
+
+impl CsrfToken {
+    // Token value, for the forms built by templates.
+    pub(crate) fn value(&self) -> &str {
+        &self.0
+    }
+}
+
+// Token sent within the body of a form.
+struct FormToken(Option<String>);
+
+// Looks for the token among the first fields of a form, leaving the body to
+// the route.
+pub(crate) fn stage() -> AdHoc {
+    AdHoc::on_request("CSRF Form Token", |request, data| {
+        Box::pin(async move {
+            if !request.content_type().is_some_and(|ct| ct.is_form()) {
+                return;
+            }
+
+            let token = std::str::from_utf8(data.peek(FORM_PEEK_LIMIT).await)
+                .ok()
+                .and_then(|form| Form::values(form).find(|field| field.name == CSRF_FIELD))
+                .map(|field| field.value.to_owned());
+            request.local_cache(|| FormToken(token));
+        })
+    })
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CsrfToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let stored = request
+            .cookies()
+            .get_private(CSRF_COOKIE)
+            .map(|cookie| cookie.value().to_owned());
+
+        // Read-only requests receive the token.
+        if matches!(request.method(), Method::Get | Method::Head) {
+            let token = stored.unwrap_or_else(|| {
+                let token = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>());
+                request.cookies().add_private(
+                    Cookie::build((CSRF_COOKIE, token.clone())).same_site(SameSite::Strict),
+                );
+                token
+            });
+            return Outcome::Success(Self(token));
+        }
+
+        let sent = request
+            .headers()
+            .get_one(CSRF_HEADER)
+            .or_else(|| request.local_cache(|| FormToken(None)).0.as_deref());
+        match (stored, sent) {
+            (Some(stored), Some(sent)) if secrets_match(&stored, sent) => {
+                Outcome::Success(Self(stored))
+            }
+            _ => Outcome::Error((Status::Forbidden, ())),
+        }
+    }
+}
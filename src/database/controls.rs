@@ -1,13 +1,18 @@
 use ascot_library::input::Range;
 
-use rocket_db_pools::Connection;
+use rocket_db_pools::sqlx::SqliteConnection;
 
 use serde::Serialize;
 
-use crate::form::{Button, CheckBox, Slider};
+use crate::form::{
+    Button, CheckBox, ColorInput, EnumInput, MarkHazardous, Restrict, Slider, TextField,
+};
 
-use super::query::{insert_boolean_input, insert_rangef64_input, insert_rangeu64_input};
-use super::{Devices, RangeInputF64, RangeInputU64};
+use super::query::{
+    insert_boolean_input, insert_color_input, insert_enum_input, insert_rangef64_input,
+    insert_rangeu64_input, insert_text_input,
+};
+use super::{RangeInputF64, RangeInputU64, RouteInputs};
 
 #[derive(Debug, Serialize, Default)]
 pub(crate) struct StateControls {
@@ -17,43 +22,226 @@ pub(crate) struct StateControls {
     sliders_f64: Vec<Slider<f64>>,
     // Checkboxes.
     checkboxes: Vec<CheckBox>,
+    // Text fields.
+    texts: Vec<TextField>,
+    // Selects.
+    selects: Vec<EnumInput>,
+    // Color pickers.
+    colors: Vec<ColorInput>,
     // Buttons.
     buttons: Vec<Button>,
 }
 
 impl StateControls {
+    // Restrict the controls associated with the given routes.
+    pub(crate) fn restrict(&mut self, route_ids: &[u16]) {
+        fn restrict_controls<C: Restrict>(controls: &mut [C], route_ids: &[u16]) {
+            controls
+                .iter_mut()
+                .filter(|control| route_ids.contains(&control.route_id()))
+                .for_each(|control| control.restrict());
+        }
+
+        restrict_controls(&mut self.sliders_u64, route_ids);
+        restrict_controls(&mut self.sliders_f64, route_ids);
+        restrict_controls(&mut self.checkboxes, route_ids);
+        restrict_controls(&mut self.texts, route_ids);
+        restrict_controls(&mut self.selects, route_ids);
+        restrict_controls(&mut self.colors, route_ids);
+        restrict_controls(&mut self.buttons, route_ids);
+    }
+
+    // Mark the controls associated with the given routes as hazardous, so
+    // that they ask for a confirmation before being used.
+    pub(crate) fn mark_hazardous(&mut self, route_ids: &[u16]) {
+        fn mark_controls<C: MarkHazardous>(controls: &mut [C], route_ids: &[u16]) {
+            controls
+                .iter_mut()
+                .filter(|control| route_ids.contains(&control.route_id()))
+                .for_each(|control| control.mark_hazardous());
+        }
+
+        mark_controls(&mut self.sliders_u64, route_ids);
+        mark_controls(&mut self.sliders_f64, route_ids);
+        mark_controls(&mut self.checkboxes, route_ids);
+        mark_controls(&mut self.texts, route_ids);
+        mark_controls(&mut self.selects, route_ids);
+        mark_controls(&mut self.colors, route_ids);
+        mark_controls(&mut self.buttons, route_ids);
+    }
+
+    // Rebuild the controls of a stored route, showing the stored values of
+    // its inputs.
+    pub(crate) fn rebuild(
+        &mut self,
+        route_name: &str,
+        cleaned_route_name: String,
+        description: Option<String>,
+        stateless: bool,
+        route_id: u16,
+        inputs: RouteInputs,
+    ) {
+        // The boolean named after the route holds the state of its button.
+        let mut state = false;
+        for input in inputs.booleans {
+            if input.name == route_name {
+                state = input.value;
+                continue;
+            }
+            self.checkboxes.push(if input.value {
+                CheckBox::checked(route_id, input.name)
+            } else {
+                CheckBox::init(route_id, input.name)
+            });
+        }
+
+        // Sliders start from the stored values, so that a reload keeps the
+        // last adjustment.
+        for input in inputs.rangesu64 {
+            self.sliders_u64.push(Slider::<u64>::new(
+                route_id,
+                input.name,
+                input.min,
+                input.max,
+                input.step,
+                input.value,
+            ));
+        }
+
+        for input in inputs.rangesf64 {
+            self.sliders_f64.push(Slider::<f64>::new(
+                route_id,
+                input.name,
+                input.min,
+                input.max,
+                input.step,
+                input.value,
+            ));
+        }
+
+        for input in inputs.texts {
+            self.texts
+                .push(TextField::new(route_id, input.name, input.value));
+        }
+
+        for input in inputs.selects {
+            // Options which cannot be decoded are shown as none.
+            let options = serde_json::from_str(&input.options).unwrap_or_default();
+            self.selects
+                .push(EnumInput::new(route_id, input.name, options, input.value));
+        }
+
+        for input in inputs.colors {
+            self.colors
+                .push(ColorInput::new(route_id, input.name, input.value));
+        }
+
+        self.push_button(route_id, cleaned_route_name, description, stateless, state);
+    }
+
     #[inline]
     pub(crate) async fn init_button(
         &mut self,
-        db: &mut Connection<Devices>,
+        db: &mut SqliteConnection,
         route_name: &str,
         cleaned_route_name: String,
+        description: Option<String>,
+        stateless: bool,
         route_id: u16,
     ) -> Result<(), sqlx::Error> {
-        insert_boolean_input(db, route_name, false, false, route_id).await?;
+        // Only stateful routes keep the state of their button.
+        if !stateless {
+            insert_boolean_input(db, route_name, false, false, route_id).await?;
+        }
 
-        self.buttons
-            .push(Button::init(route_id, cleaned_route_name));
+        self.push_button(route_id, cleaned_route_name, description, stateless, false);
         Ok(())
     }
 
+    // Stateful routes show their current state on their buttons.
+    fn push_button(
+        &mut self,
+        route_id: u16,
+        cleaned_route_name: String,
+        description: Option<String>,
+        stateless: bool,
+        state: bool,
+    ) {
+        self.buttons.push(if stateless {
+            Button::init(route_id, cleaned_route_name, description)
+        } else {
+            Button::with_state(route_id, cleaned_route_name, description, state)
+        });
+    }
+
     #[inline]
     pub(crate) async fn init_checkbox(
         &mut self,
-        db: &mut Connection<Devices>,
+        db: &mut SqliteConnection,
         default: bool,
         route_id: u16,
         input_name: String,
     ) -> Result<(), sqlx::Error> {
         insert_boolean_input(db, &input_name, default, default, route_id).await?;
-        self.checkboxes.push(CheckBox::init(route_id, input_name));
+        self.checkboxes.push(if default {
+            CheckBox::checked(route_id, input_name)
+        } else {
+            CheckBox::init(route_id, input_name)
+        });
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) async fn init_text(
+        &mut self,
+        db: &mut SqliteConnection,
+        default: &str,
+        route_id: u16,
+        input_name: String,
+    ) -> Result<(), sqlx::Error> {
+        insert_text_input(db, &input_name, default, default, route_id).await?;
+        self.texts
+            .push(TextField::new(route_id, input_name, default.into()));
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) async fn init_select(
+        &mut self,
+        db: &mut SqliteConnection,
+        options: &[String],
+        default: &str,
+        route_id: u16,
+        input_name: String,
+    ) -> Result<(), sqlx::Error> {
+        insert_enum_input(db, &input_name, options, default, default, route_id).await?;
+        self.selects.push(EnumInput::new(
+            route_id,
+            input_name,
+            options.to_vec(),
+            default.into(),
+        ));
+        Ok(())
+    }
+
+    #[inline]
+    pub(crate) async fn init_color(
+        &mut self,
+        db: &mut SqliteConnection,
+        default: &str,
+        route_id: u16,
+        input_name: String,
+    ) -> Result<(), sqlx::Error> {
+        insert_color_input(db, &input_name, default, default, route_id).await?;
+        self.colors
+            .push(ColorInput::new(route_id, input_name, default.into()));
         Ok(())
     }
 
     #[inline]
     pub(crate) async fn init_slider_u64(
         &mut self,
-        db: &mut Connection<Devices>,
+        db: &mut SqliteConnection,
         route_id: u16,
         input_name: String,
         range: &Range<u64>,
@@ -82,7 +270,7 @@ impl StateControls {
     #[inline]
     pub(crate) async fn init_slider_f64(
         &mut self,
-        db: &mut Connection<Devices>,
+        db: &mut SqliteConnection,
         route_id: u16,
         input_name: String,
         range: &Range<f64>,
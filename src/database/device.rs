@@ -1,27 +1,103 @@
-use std::net::IpAddr;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
 
 use ascot_library::device::DeviceData;
 use ascot_library::input::InputType;
 
-use rocket_db_pools::{sqlx, Connection};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, StatusCode};
+
+use rocket::futures::future::join_all;
+
+use rocket_db_pools::sqlx::{self, Connection as _, SqliteConnection};
 
 use serde::{Deserialize, Serialize};
 
 use tracing::debug;
 
-use super::{Address, Devices, Metadata};
+use crate::config::RetryConfig;
+use crate::inputs::{DeviceFilter, Page, Sort};
+use crate::logs::LogBuffer;
+use crate::metrics::Metrics;
+use crate::request::DeviceClient;
+use crate::time::now;
+
+use super::{Address, Metadata, Property};
 
 use super::controls::StateControls;
 use super::query::{
-    delete_device, insert_hazard, insert_main_route, insert_route, select_device_addresses,
-    select_device_metadata,
+    delete_device, insert_address, insert_device, insert_hazard, insert_hazard_definition,
+    insert_main_route, insert_route, promote_address, record_retrieval_failure,
+    select_device_addresses, select_device_metadata, select_device_metadata_paginated,
+    select_device_properties, select_route_by_name, select_route_inputs, update_address_latency,
+    update_address_reachable, update_device_kind, update_last_retrieved, update_retrieval_error,
 };
 
+// JSON content type.
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+// CBOR content type.
+const CBOR_CONTENT_TYPE: &str = "application/cbor";
+
+// Maximum number of characters of an invalid body kept to report it.
+const BODY_SNIPPET_LENGTH: usize = 200;
+
+// Device data retrieval errors.
+#[derive(Debug)]
+enum RetrieveError {
+    // No address has answered with device data.
+    Unreachable,
+    // The device has refused the gateway credentials.
+    Unauthorized,
+    // The device has answered with an unexpected content type.
+    UnexpectedContent(String),
+    // The device has answered, but its data cannot be decoded.
+    Decode {
+        // Deserialization error.
+        error: String,
+        // Beginning of the offending body.
+        body: String,
+    },
+}
+
+impl RetrieveError {
+    fn decode(error: impl fmt::Display, body: &[u8]) -> Self {
+        Self::Decode {
+            error: error.to_string(),
+            body: String::from_utf8_lossy(body)
+                .chars()
+                .take(BODY_SNIPPET_LENGTH)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for RetrieveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreachable => write!(f, "Unreachable device"),
+            Self::Unauthorized => write!(f, "The device has refused the gateway credentials"),
+            Self::UnexpectedContent(content_type) => {
+                write!(f, "Unexpected content type {}", content_type)
+            }
+            Self::Decode { error, body } => {
+                write!(f, "Invalid device data: {} (body: {:?})", error, body)
+            }
+        }
+    }
+}
+
 // Device addresses.
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct DeviceAddress {
     // Whether the address is reachable.
     recheable: bool,
+    // Whether the address has been contacted.
+    #[serde(skip)]
+    attempted: bool,
+    // Milliseconds the address has taken to answer the last time.
+    latency: Option<u32>,
     // Address.
     pub(crate) address: IpAddr,
     // Request.
@@ -29,9 +105,11 @@ pub(crate) struct DeviceAddress {
 }
 
 impl DeviceAddress {
-    fn new(request: String, address: IpAddr) -> Self {
+    fn new(request: String, address: IpAddr, recheable: bool, latency: Option<u32>) -> Self {
         Self {
-            recheable: true,
+            recheable,
+            attempted: false,
+            latency,
             address,
             request,
         }
@@ -44,10 +122,14 @@ impl DeviceAddress {
                 a.address.parse().ok().map(|address| {
                     DeviceAddress::new(
                         format!(
-                            "{}://{}:{}{}",
-                            metadata.scheme, address, metadata.port, metadata.path
+                            "{}://{}{}",
+                            metadata.scheme,
+                            SocketAddr::new(address, metadata.port),
+                            metadata.path
                         ),
                         address,
+                        a.reachable,
+                        a.latency,
                     )
                 })
             })
@@ -55,31 +137,66 @@ impl DeviceAddress {
     }
 }
 
+// Outcome of contacting a device address.
+#[derive(Debug, Serialize)]
+pub(crate) struct Ping {
+    // Address.
+    pub(crate) address: IpAddr,
+    // Whether the address has answered with device data.
+    pub(crate) reachable: bool,
+    // Milliseconds the address has taken to answer.
+    pub(crate) latency: Option<u32>,
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) struct Device {
     // Metadata.
     pub(crate) metadata: Metadata,
     // Addresses.
     pub(crate) addresses: Vec<DeviceAddress>,
+    // Properties advertised through mDNS.
+    pub(crate) properties: Vec<Property>,
     // Device data.
     //
     // Hazards and routes are all here.
     pub(crate) data: DeviceData,
     // Device controls with states.
     pub(crate) state_controls: StateControls,
+    // Whether device data have not been retrieved recently.
+    pub(crate) stale: bool,
+    // Whether any device address is reachable.
+    pub(crate) reachable: bool,
+    // Time of the last discovery, in the configured timezone.
+    pub(crate) last_seen: Option<String>,
 }
 
 impl Device {
-    async fn new(metadata: Metadata, mut addresses: Vec<DeviceAddress>) -> Option<Self> {
-        if let Some(data) = Self::retrieve(&mut addresses).await {
-            Some(Self {
-                metadata,
-                addresses,
-                data,
-                state_controls: StateControls::default(),
-            })
-        } else {
-            None
+    // Retrieve device data, building the device.
+    //
+    // When no data can be retrieved, the contacted addresses are returned
+    // together with the error.
+    async fn new(
+        client: &DeviceClient,
+        metadata: Metadata,
+        mut addresses: Vec<DeviceAddress>,
+        properties: Vec<Property>,
+    ) -> Result<Self, (Vec<DeviceAddress>, RetrieveError)> {
+        match Self::retrieve(client, &mut addresses).await {
+            Ok(data) => {
+                let mut device = Self {
+                    metadata,
+                    addresses,
+                    properties,
+                    data,
+                    state_controls: StateControls::default(),
+                    stale: false,
+                    reachable: false,
+                    last_seen: None,
+                };
+                device.reachable = device.is_recheable();
+                Ok(device)
+            }
+            Err(e) => Err((addresses, e)),
         }
     }
 
@@ -87,13 +204,37 @@ impl Device {
         self.addresses.iter().any(|address| address.recheable)
     }
 
-    // Retrieve all devices for the first time.
+    // Mark device data as stale when they have not been retrieved within
+    // the given number of seconds.
+    pub(crate) fn check_staleness(&mut self, stale_after: u64, now: i64) {
+        self.stale = self.metadata.is_stale(stale_after, now);
+    }
+
+    // Retrieve the devices matching a filter, or only a page of them, for
+    // the first time, in the given order.
+    //
+    // Devices waiting for their next retry are skipped. Devices answering
+    // with invalid data are logged, and their error is saved.
     pub(crate) async fn search_for_devices(
-        db: &mut Connection<Devices>,
+        db: &mut SqliteConnection,
+        client: &DeviceClient,
+        retry: &RetryConfig,
+        metrics: &Metrics,
+        logs: &LogBuffer,
+        filter: &DeviceFilter,
+        page: Option<Page>,
+        sort: Sort,
     ) -> Result<Vec<Self>, sqlx::Error> {
-        let devices_metadata = select_device_metadata(db).await?;
+        let devices_metadata = match page {
+            Some(page) => {
+                select_device_metadata_paginated(db, filter, now(), page.size, page.offset(), sort)
+                    .await?
+            }
+            None => select_device_metadata(db, filter, now(), sort).await?,
+        };
 
-        let mut devices = Vec::new();
+        // Load from the database what is needed to contact each device.
+        let mut candidates = Vec::new();
         for device_metadata in devices_metadata {
             // Device id.
             let device_id = device_metadata.id;
@@ -104,26 +245,195 @@ impl Device {
             // Construct device addresses.
             let device_addresses = DeviceAddress::addresses(&device_metadata, db_addresses);
 
-            // If some data are retrieved, complete device creation.
-            if let Some(mut device) = Device::new(device_metadata, device_addresses).await {
-                // Insert routes.
-                device.insert_routes(db).await?;
+            // Retrieve properties from database.
+            let properties = select_device_properties(db, device_id).await?;
+
+            candidates.push((device_metadata, device_addresses, properties));
+        }
+
+        // Contact devices concurrently, so that slow devices do not add up
+        // their timeouts.
+        let retrieved = join_all(candidates.into_iter().map(
+            |(device_metadata, device_addresses, properties)| async move {
+                let device_id = device_metadata.id;
+                let device =
+                    Device::new(client, device_metadata, device_addresses, properties).await;
+                (device_id, device)
+            },
+        ))
+        .await;
 
-                // Save device.
-                devices.push(device);
-            } else {
-                // Delete a device when it is not reachable
-                delete_device(db, device_id).await?;
+        // Save results through the database connection, one device at a time.
+        let mut devices = Vec::new();
+        for (device_id, device) in retrieved {
+            // Save whether the contacted addresses have answered.
+            let addresses = match &device {
+                Ok(device) => &device.addresses,
+                Err((addresses, _)) => addresses,
+            };
+            for address in addresses.iter().filter(|address| address.attempted) {
+                update_address_reachable(
+                    db,
+                    &address.address.to_string(),
+                    address.recheable,
+                    device_id,
+                )
+                .await?;
+                if let Some(latency) = address.latency {
+                    update_address_latency(db, &address.address.to_string(), latency, device_id)
+                        .await?;
+                }
             }
+
+            let mut device = match device {
+                Ok(device) => device,
+                Err((_, e)) => {
+                    // A device answering without data is running, hence it
+                    // is not deleted, but its error is reported.
+                    let invalid = match e {
+                        RetrieveError::Decode { .. } => {
+                            logs.warn(format!("Device {} has sent invalid data: {}", device_id, e));
+                            true
+                        }
+                        RetrieveError::Unauthorized => {
+                            logs.warn(format!(
+                                "Device {} has refused the gateway credentials",
+                                device_id
+                            ));
+                            true
+                        }
+                        RetrieveError::UnexpectedContent(_) => {
+                            logs.warn(format!("Device {} has sent {}", device_id, e));
+                            true
+                        }
+                        _ => false,
+                    };
+                    update_retrieval_error(db, device_id, invalid.then(|| e.to_string())).await?;
+
+                    // Delete a device only when it has not been reachable for
+                    // several consecutive retrievals, so that a device which
+                    // is briefly offline keeps its data.
+                    let failures =
+                        record_retrieval_failure(db, device_id, now(), retry.backoff).await?;
+                    if !invalid && failures >= retry.max_retries {
+                        delete_device(db, device_id).await?;
+                        metrics.device_deleted();
+                    }
+                    continue;
+                }
+            };
+
+            // Save retrieval time.
+            let last_retrieved = now();
+            update_last_retrieved(db, device_id, last_retrieved).await?;
+            device.metadata.last_retrieved = Some(last_retrieved);
+
+            // Save the kind, so that devices can be sorted by it.
+            update_device_kind(db, device_id, &device.kind_name()).await?;
+
+            // Promote the address which has answered, so that it is
+            // contacted first the next time.
+            if let Some(address) = device.addresses.first() {
+                promote_address(db, address.address.to_string(), last_retrieved, device_id).await?;
+            }
+
+            // Insert routes atomically, so that a failure midway leaves
+            // no half-inserted route.
+            let mut tx = db.begin().await?;
+            device.insert_routes(&mut tx).await?;
+            tx.commit().await?;
+
+            // Save device.
+            devices.push(device);
         }
 
         Ok(devices)
     }
 
+    // Contact every address of a device, checking whether it answers with
+    // device data.
+    //
+    // Addresses are contacted concurrently, so that dead addresses do not
+    // add up their timeouts.
+    pub(crate) async fn ping(
+        client: &DeviceClient,
+        metadata: &Metadata,
+        addresses: Vec<Address>,
+    ) -> Vec<Ping> {
+        join_all(
+            DeviceAddress::addresses(metadata, addresses)
+                .into_iter()
+                .map(|address| async move {
+                    let start = Instant::now();
+                    let data = match client.request(Method::GET, &address.request).send().await {
+                        Ok(response) => Self::decode(response).await.ok(),
+                        Err(_) => None,
+                    };
+                    Ping {
+                        address: address.address,
+                        reachable: data.is_some(),
+                        latency: data.map(|_| elapsed_millis(start)),
+                    }
+                }),
+        )
+        .await
+    }
+
+    // Name of the device kind.
+    fn kind_name(&self) -> String {
+        // Kinds have no fields, hence their debug names are their names.
+        format!("{:?}", self.data.kind)
+    }
+
+    // Insert a device together with its addresses and routes, returning the
+    // device identifier.
+    //
+    // Everything is inserted in a single transaction, so that a failure
+    // midway leaves no half-inserted device.
+    pub(crate) async fn insert(
+        &mut self,
+        db: &mut SqliteConnection,
+        fullname: &str,
+    ) -> Result<u16, sqlx::Error> {
+        // The transaction is rolled back when dropped before being committed.
+        let mut tx = db.begin().await?;
+        let id = self.insert_rows(&mut tx, fullname).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    // Insert device rows, without beginning a transaction.
+    async fn insert_rows(
+        &mut self,
+        db: &mut SqliteConnection,
+        fullname: &str,
+    ) -> Result<u16, sqlx::Error> {
+        let id = insert_device(
+            db,
+            fullname,
+            self.metadata.port,
+            &self.metadata.scheme,
+            &self.metadata.path,
+        )
+        .await?;
+        self.metadata.id = id;
+
+        // Save addresses
+        for address in self.addresses.iter() {
+            insert_address(db, address.address.to_string(), id).await?;
+        }
+
+        self.insert_routes(db).await?;
+        Ok(id)
+    }
+
     // Insert routes.
+    //
+    // No transaction is begun, so that insertions can be part of a larger
+    // one.
     pub(crate) async fn insert_routes(
         &mut self,
-        db: &mut Connection<Devices>,
+        db: &mut SqliteConnection,
     ) -> Result<(), sqlx::Error> {
         let device_id = self.metadata.id;
 
@@ -131,12 +441,58 @@ impl Device {
         insert_main_route(db, self.data.main_route.as_str(), device_id).await?;
 
         for route in self.data.routes.iter() {
+            let description = route
+                .data
+                .description
+                .as_ref()
+                .map(|description| description.as_str());
+
+            // Keep the routes already stored, so that the values of their
+            // inputs are shown instead of the defaults.
+            if let Some(stored) =
+                select_route_by_name(db, route.data.name.as_str(), device_id).await?
+            {
+                let inputs = select_route_inputs(db, stored.id).await?;
+                self.state_controls.rebuild(
+                    route.data.name.as_str(),
+                    Self::clean_route(route.data.name.as_str()),
+                    description.map(Into::into),
+                    route.data.stateless,
+                    stored.id,
+                    inputs,
+                );
+                continue;
+            }
+
             // Save device routes into database.
-            let route_id = insert_route(db, route.data.name.as_str(), device_id).await?;
+            let route_id = insert_route(
+                db,
+                route.data.name.as_str(),
+                route.rest_kind.into(),
+                description,
+                route.data.stateless,
+                device_id,
+            )
+            .await?;
 
             for hazard in route.hazards.iter() {
                 // Save device hazards into database.
-                insert_hazard(db, hazard.id, device_id).await?;
+                insert_hazard(
+                    db,
+                    hazard.id,
+                    hazard.category.name.as_str(),
+                    route_id,
+                    device_id,
+                )
+                .await?;
+                insert_hazard_definition(
+                    db,
+                    hazard.id,
+                    hazard.name.as_str(),
+                    hazard.description.as_str(),
+                    hazard.category.description.as_str(),
+                )
+                .await?;
             }
 
             // Save device inputs into database.
@@ -157,6 +513,27 @@ impl Device {
                             .init_checkbox(db, *default, route_id, input.name.as_str().to_string())
                             .await?
                     }
+                    InputType::Text(default) => {
+                        self.state_controls
+                            .init_text(db, default, route_id, input.name.as_str().to_string())
+                            .await?
+                    }
+                    InputType::Enum { options, default } => {
+                        self.state_controls
+                            .init_select(
+                                db,
+                                options,
+                                default,
+                                route_id,
+                                input.name.as_str().to_string(),
+                            )
+                            .await?
+                    }
+                    InputType::Color(default) => {
+                        self.state_controls
+                            .init_color(db, default, route_id, input.name.as_str().to_string())
+                            .await?
+                    }
                 }
             }
 
@@ -165,6 +542,8 @@ impl Device {
                     db,
                     route.data.name.as_str(),
                     Self::clean_route(route.data.name.as_str()),
+                    description.map(Into::into),
+                    route.data.stateless,
                     route_id,
                 )
                 .await?;
@@ -186,20 +565,660 @@ impl Device {
             .into()
     }
 
-    async fn retrieve(addresses: &mut [DeviceAddress]) -> Option<DeviceData> {
+    // Retrieve device data.
+    //
+    // Addresses which have answered the last time are tried first, so that
+    // a dead address does not delay every retrieval. The address which has
+    // answered is moved to the front of the addresses.
+    //
+    // Invalid data, unexpected content or refused credentials from a
+    // reachable address stop the retrieval, since other addresses lead to
+    // the same device.
+    async fn retrieve(
+        client: &DeviceClient,
+        addresses: &mut [DeviceAddress],
+    ) -> Result<DeviceData, RetrieveError> {
+        // The sort is stable, hence addresses keep their priority order.
+        addresses.sort_by_key(|address| !address.recheable);
+
         // Try each address in order to connect to a device.
-        for address in addresses.iter_mut() {
-            if let Ok(response) = reqwest::get(&address.request).await {
-                // When an error occurs deserializing the device information,
-                // skip it.
-                if let Ok(data) = response.json().await {
-                    return Some(data);
-                } else {
-                    debug!("Deserialize error for address {:?}", address);
+        for index in 0..addresses.len() {
+            let address = &mut addresses[index];
+            address.attempted = true;
+            address.recheable = true;
+            let start = Instant::now();
+            if let Ok(response) = client.request(Method::GET, &address.request).send().await {
+                address.latency = Some(elapsed_millis(start));
+                match Self::decode(response).await {
+                    Ok(data) => {
+                        // Move the address which has answered to the front.
+                        addresses[..=index].rotate_right(1);
+                        return Ok(data);
+                    }
+                    Err(RetrieveError::Unreachable) => {
+                        debug!("Body unreadable for address {:?}", address);
+                    }
+                    Err(e) => {
+                        debug!("{} for address {:?}", e, address);
+                        return Err(e);
+                    }
                 }
             }
             address.recheable = false;
         }
-        None
+        Err(RetrieveError::Unreachable)
+    }
+
+    // Decode device data according to the response content type.
+    //
+    // When no content type is provided, data are assumed to be JSON.
+    async fn decode(response: reqwest::Response) -> Result<DeviceData, RetrieveError> {
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err(RetrieveError::Unauthorized);
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map_or(JSON_CONTENT_TYPE.into(), |value| {
+                value.trim().to_lowercase()
+            });
+
+        if content_type != JSON_CONTENT_TYPE && content_type != CBOR_CONTENT_TYPE {
+            return Err(RetrieveError::UnexpectedContent(content_type));
+        }
+
+        // The body is read first, so that it can be reported when invalid.
+        // A body which cannot be read is a connection failure.
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|_| RetrieveError::Unreachable)?;
+        if content_type == JSON_CONTENT_TYPE {
+            serde_json::from_slice(&bytes).map_err(|e| RetrieveError::decode(e, &bytes))
+        } else {
+            ciborium::from_reader(bytes.as_ref()).map_err(|e| RetrieveError::decode(e, &bytes))
+        }
+    }
+}
+
+// Milliseconds elapsed since an instant, saturating at the maximum.
+#[inline]
+fn elapsed_millis(start: Instant) -> u32 {
+    start.elapsed().as_millis().try_into().unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ascot_library::device::DeviceKind;
+    use ascot_library::hazards::{CategoryData, HazardData, HazardsData};
+    use ascot_library::input::{Input, Inputs, InputsData};
+    use ascot_library::route::{RestKind, RouteConfig, RouteData, Routes};
+    use ascot_library::{LongString, MiniString};
+
+    use rocket_db_pools::Connection;
+
+    use crate::config::HttpConfig;
+    use crate::database::query::{
+        select_device_hazards, select_device_metadata_by_id, select_device_routes_by_id,
+        update_boolean_value, update_rangef64_value, update_rangeu64_value,
+    };
+    use crate::database::{test_connection, Devices, RouteMethod};
+    use crate::request::{serve_body, serve_once};
+
+    // Build a device without routes.
+    fn device(last_retrieved: Option<i64>) -> Device {
+        Device {
+            metadata: Metadata {
+                id: 1,
+                port: 3000,
+                scheme: "http".into(),
+                path: "/".into(),
+                name: None,
+                last_seen: None,
+                next_retry: None,
+                retrieval_error: None,
+                last_retrieved,
+            },
+            addresses: Vec::new(),
+            properties: Vec::new(),
+            data: DeviceData {
+                kind: DeviceKind::Light,
+                main_route: MiniString::new("/light").unwrap(),
+                routes: Routes::init(),
+            },
+            state_controls: StateControls::default(),
+            stale: false,
+            reachable: true,
+            last_seen: None,
+        }
+    }
+
+    #[test]
+    fn address_requests_follow_the_device_scheme() {
+        let mut metadata = device(None).metadata;
+        metadata.scheme = "https".into();
+        let addresses = ["192.168.1.2", "fe80::2"]
+            .into_iter()
+            .map(|address| Address {
+                address: address.into(),
+                reachable: true,
+                latency: None,
+            })
+            .collect();
+
+        let requests = DeviceAddress::addresses(&metadata, addresses)
+            .into_iter()
+            .map(|address| address.request)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            ["https://192.168.1.2:3000/", "https://[fe80::2]:3000/"]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn reachable_addresses_are_tried_first() {
+        let data = serde_json::to_vec(&device(None).data).unwrap();
+        let (port, _) = serve_body("application/json", data).await;
+        let mut metadata = device(None).metadata;
+        metadata.port = port;
+        // Only the first loopback address is listened on.
+        let addresses = [("127.0.0.2", false), ("127.0.0.1", true)]
+            .into_iter()
+            .map(|(address, reachable)| Address {
+                address: address.into(),
+                reachable,
+                latency: None,
+            })
+            .collect();
+        let mut addresses = DeviceAddress::addresses(&metadata, addresses);
+
+        let client = DeviceClient::new(&HttpConfig::default()).unwrap();
+        assert!(Device::retrieve(&client, &mut addresses).await.is_ok());
+
+        assert_eq!(addresses[0].address.to_string(), "127.0.0.1");
+        assert!(addresses[0].latency.is_some());
+        assert!(!addresses[1].attempted);
+        assert_eq!(addresses[1].latency, None);
+    }
+
+    #[rocket::async_test]
+    async fn every_address_is_pinged() {
+        let data = serde_json::to_vec(&device(None).data).unwrap();
+        let (port, _) = serve_body("application/json", data).await;
+        let mut metadata = device(None).metadata;
+        metadata.port = port;
+        let addresses = ["127.0.0.2", "127.0.0.1"]
+            .into_iter()
+            .map(|address| Address {
+                address: address.into(),
+                reachable: true,
+                latency: None,
+            })
+            .collect();
+
+        let client = DeviceClient::new(&HttpConfig::default()).unwrap();
+        let pings = Device::ping(&client, &metadata, addresses).await;
+
+        assert_eq!(pings[0].address.to_string(), "127.0.0.2");
+        assert!(!pings[0].reachable);
+        assert_eq!(pings[0].latency, None);
+        assert_eq!(pings[1].address.to_string(), "127.0.0.1");
+        assert!(pings[1].reachable);
+        assert!(pings[1].latency.is_some());
+    }
+
+    #[rocket::async_test]
+    async fn failed_insertions_leave_no_rows() {
+        let (_client, mut db) = test_connection().await;
+        let mut device = device(None);
+        let addresses = vec![Address {
+            address: "192.168.1.2".into(),
+            reachable: true,
+            latency: None,
+        }];
+        device.addresses = DeviceAddress::addresses(&device.metadata, addresses);
+
+        // Make the insertion fail right after the device row.
+        sqlx::query("DROP TABLE main_routes")
+            .execute(&mut **db)
+            .await
+            .unwrap();
+        assert!(device.insert(&mut db, "light").await.is_err());
+
+        for table in ["devices", "addresses"] {
+            let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+                .fetch_one(&mut **db)
+                .await
+                .unwrap();
+            assert_eq!(count, 0, "{}", table);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn hazard_definitions_are_stored() {
+        let (_client, mut db) = test_connection().await;
+        let mut hazards = HazardsData::init();
+        hazards.add(HazardData {
+            id: 0,
+            name: MiniString::new("Fire Hazard").unwrap(),
+            description: LongString::new("An Hazard fire").unwrap(),
+            category: CategoryData {
+                name: MiniString::new("Safety").unwrap(),
+                description: LongString::new("A safety category").unwrap(),
+            },
+        });
+        let mut device = device(None);
+        device.data.routes.add(RouteConfig {
+            rest_kind: RestKind::Put,
+            hazards,
+            data: RouteData {
+                name: MiniString::new("/on").unwrap(),
+                description: None,
+                stateless: false,
+                inputs: InputsData::init(),
+            },
+        });
+        let id = device.insert(&mut db, "light").await.unwrap();
+
+        let hazards = select_device_hazards(&mut db, id).await.unwrap();
+        assert_eq!(hazards.len(), 1);
+        assert_eq!(hazards[0].hazard_id, 0);
+        assert_eq!(hazards[0].name.as_deref(), Some("Fire Hazard"));
+        assert_eq!(hazards[0].description.as_deref(), Some("An Hazard fire"));
+        assert_eq!(hazards[0].category, "Safety");
+
+        // The rendered hazard is labelled by its name.
+        let rendered = serde_json::to_value(&hazards[0]).unwrap();
+        assert_eq!(rendered["name"], "Fire Hazard");
+    }
+
+    #[rocket::async_test]
+    async fn rest_kinds_are_stored() {
+        let (_client, mut db) = test_connection().await;
+        let mut device = device(None);
+        device.data.routes.add(RouteConfig {
+            rest_kind: RestKind::Put,
+            hazards: HazardsData::init(),
+            data: RouteData {
+                name: MiniString::new("/on").unwrap(),
+                description: None,
+                stateless: false,
+                inputs: InputsData::init(),
+            },
+        });
+        let id = device.insert(&mut db, "light").await.unwrap();
+
+        let routes = select_device_routes_by_id(&mut db, id).await.unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].rest_kind, RouteMethod::Put);
+
+        // The method is stored as its name.
+        let stored: String = sqlx::query_scalar("SELECT rest_kind FROM routes")
+            .fetch_one(&mut **db)
+            .await
+            .unwrap();
+        assert_eq!(stored, "PUT");
+    }
+
+    #[rocket::async_test]
+    async fn route_descriptions_are_stored() {
+        let (_client, mut db) = test_connection().await;
+        let mut device = device(None);
+        for (name, description) in [("/on", Some("Light on")), ("/off", None)] {
+            device.data.routes.add(RouteConfig {
+                rest_kind: RestKind::Put,
+                hazards: HazardsData::init(),
+                data: RouteData {
+                    name: MiniString::new(name).unwrap(),
+                    description: description
+                        .map(|description| LongString::new(description).unwrap()),
+                    stateless: false,
+                    inputs: InputsData::init(),
+                },
+            });
+        }
+        let id = device.insert(&mut db, "light").await.unwrap();
+
+        let routes = select_device_routes_by_id(&mut db, id).await.unwrap();
+        assert_eq!(routes[0].description.as_deref(), Some("Light on"));
+        assert_eq!(routes[1].description, None);
+
+        // Only described routes have a tooltip.
+        let buttons = serde_json::to_value(&device.state_controls).unwrap()["buttons"].clone();
+        assert_eq!(buttons[0]["description"], "Light on");
+        assert!(buttons[1]["description"].is_null());
+    }
+
+    #[rocket::async_test]
+    async fn checkboxes_show_stored_values() {
+        let (_client, mut db) = test_connection().await;
+        let mut device = device(None);
+        let mut inputs = Inputs::init();
+        inputs.add(Input::boolean("state", false));
+        device.data.routes.add(RouteConfig {
+            rest_kind: RestKind::Put,
+            hazards: HazardsData::init(),
+            data: RouteData {
+                name: MiniString::new("/state/<state>").unwrap(),
+                description: None,
+                stateless: false,
+                inputs: InputsData::from_inputs(&inputs).unwrap(),
+            },
+        });
+        let id = device.insert(&mut db, "light").await.unwrap();
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        update_boolean_value(&mut db, route_id, "state", true)
+            .await
+            .unwrap();
+
+        // Controls are rebuilt from the stored route, not inserted again.
+        device.state_controls = StateControls::default();
+        device.insert_routes(&mut db).await.unwrap();
+        assert_eq!(
+            select_device_routes_by_id(&mut db, id).await.unwrap().len(),
+            1
+        );
+
+        let checkboxes =
+            serde_json::to_value(&device.state_controls).unwrap()["checkboxes"].clone();
+        assert_eq!(checkboxes.as_array().unwrap().len(), 1);
+        assert_eq!(checkboxes[0]["value"], true);
+    }
+
+    #[rocket::async_test]
+    async fn sliders_show_stored_values() {
+        let (_client, mut db) = test_connection().await;
+        let mut device = device(None);
+        let mut inputs = Inputs::init();
+        inputs.add(Input::rangeu64("brightness", (0, 20, 1, 5)));
+        inputs.add(Input::rangef64("temperature", (0., 40., 0.5, 20.)));
+        device.data.routes.add(RouteConfig {
+            rest_kind: RestKind::Put,
+            hazards: HazardsData::init(),
+            data: RouteData {
+                name: MiniString::new("/on/<brightness>/<temperature>").unwrap(),
+                description: None,
+                stateless: false,
+                inputs: InputsData::from_inputs(&inputs).unwrap(),
+            },
+        });
+        let id = device.insert(&mut db, "light").await.unwrap();
+
+        // Newly inserted sliders start from their defaults.
+        let controls = serde_json::to_value(&device.state_controls).unwrap();
+        assert_eq!(controls["sliders_u64"][0]["value"], 5);
+        assert_eq!(controls["sliders_f64"][0]["value"], 20.);
+
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        update_rangeu64_value(&mut db, route_id, "brightness", 12)
+            .await
+            .unwrap();
+        update_rangef64_value(&mut db, route_id, "temperature", 22.5)
+            .await
+            .unwrap();
+
+        device.state_controls = StateControls::default();
+        device.insert_routes(&mut db).await.unwrap();
+
+        let controls = serde_json::to_value(&device.state_controls).unwrap();
+        assert_eq!(controls["sliders_u64"][0]["value"], 12);
+        assert_eq!(controls["sliders_f64"][0]["value"], 22.5);
+    }
+
+    #[rocket::async_test]
+    async fn stateful_routes_have_stateful_buttons() {
+        let (_client, mut db) = test_connection().await;
+        let mut device = device(None);
+        for (name, stateless) in [("/off", true), ("/toggle", false)] {
+            device.data.routes.add(RouteConfig {
+                rest_kind: RestKind::Put,
+                hazards: HazardsData::init(),
+                data: RouteData {
+                    name: MiniString::new(name).unwrap(),
+                    description: None,
+                    stateless,
+                    inputs: InputsData::init(),
+                },
+            });
+        }
+        let id = device.insert(&mut db, "light").await.unwrap();
+
+        let buttons = serde_json::to_value(&device.state_controls).unwrap()["buttons"].clone();
+        assert_eq!(buttons[0]["name"], "off");
+        assert_eq!(buttons[0]["with_state"], false);
+        assert_eq!(buttons[1]["name"], "toggle");
+        assert_eq!(buttons[1]["with_state"], true);
+        assert_eq!(buttons[1]["state"], false);
+
+        // Rebuilt buttons show the stored state.
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[1].id;
+        update_boolean_value(&mut db, route_id, "/toggle", true)
+            .await
+            .unwrap();
+        device.state_controls = StateControls::default();
+        device.insert_routes(&mut db).await.unwrap();
+
+        let buttons = serde_json::to_value(&device.state_controls).unwrap()["buttons"].clone();
+        assert_eq!(buttons[0]["with_state"], false);
+        assert_eq!(buttons[1]["state"], true);
+    }
+
+    #[rocket::async_test]
+    async fn only_stateful_routes_store_their_state() {
+        let (_client, mut db) = test_connection().await;
+        let mut device = device(None);
+        for (name, stateless) in [("/off", true), ("/toggle", false)] {
+            device.data.routes.add(RouteConfig {
+                rest_kind: RestKind::Put,
+                hazards: HazardsData::init(),
+                data: RouteData {
+                    name: MiniString::new(name).unwrap(),
+                    description: None,
+                    stateless,
+                    inputs: InputsData::init(),
+                },
+            });
+        }
+        let id = device.insert(&mut db, "light").await.unwrap();
+
+        let routes = select_device_routes_by_id(&mut db, id).await.unwrap();
+        assert!(routes[0].stateless);
+        assert!(!routes[1].stateless);
+
+        let off = select_route_inputs(&mut db, routes[0].id).await.unwrap();
+        assert!(off.values().is_empty());
+        let toggle = select_route_inputs(&mut db, routes[1].id).await.unwrap();
+        assert_eq!(toggle.values()["/toggle"], "false");
+    }
+
+    #[test]
+    fn kinds_are_named_after_their_variant() {
+        let mut device = device(None);
+        device.data.kind = DeviceKind::Fridge;
+
+        assert_eq!(device.kind_name(), "Fridge");
+    }
+
+    #[test]
+    fn never_retrieved_data_are_stale() {
+        let mut device = device(None);
+
+        device.check_staleness(300, 1000);
+        assert!(device.stale);
+    }
+
+    #[test]
+    fn data_are_stale_only_after_the_given_seconds() {
+        let mut device = device(Some(1000));
+
+        device.check_staleness(300, 1300);
+        assert!(!device.stale);
+
+        device.check_staleness(300, 1301);
+        assert!(device.stale);
+    }
+
+    // Decode the answer of a device sending the given body.
+    async fn decode_body(content_type: &str, body: Vec<u8>) -> Result<DeviceData, RetrieveError> {
+        let (port, _) = serve_body(content_type, body).await;
+        let response = reqwest::get(format!("http://127.0.0.1:{port}/"))
+            .await
+            .unwrap();
+        Device::decode(response).await
+    }
+
+    #[rocket::async_test]
+    async fn json_and_cbor_data_are_decoded() {
+        let data = device(None).data;
+        let expected = serde_json::to_value(&data).unwrap();
+
+        let json = serde_json::to_vec(&data).unwrap();
+        let decoded = decode_body("application/json; charset=utf-8", json).await;
+        assert_eq!(serde_json::to_value(decoded.unwrap()).unwrap(), expected);
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&data, &mut cbor).unwrap();
+        let decoded = decode_body("application/cbor", cbor).await;
+        assert_eq!(serde_json::to_value(decoded.unwrap()).unwrap(), expected);
+    }
+
+    #[rocket::async_test]
+    async fn unexpected_content_types_are_reported() {
+        let decoded = decode_body("text/html", b"<html></html>".to_vec()).await;
+
+        assert!(
+            matches!(decoded, Err(RetrieveError::UnexpectedContent(content_type)) if content_type == "text/html")
+        );
+    }
+
+    #[rocket::async_test]
+    async fn undecodable_bodies_are_reported() {
+        let decoded = decode_body("application/json", b"{\"kind\":".to_vec()).await;
+
+        assert!(matches!(decoded, Err(RetrieveError::Decode { body, .. }) if body == "{\"kind\":"));
+    }
+
+    #[rocket::async_test]
+    async fn refused_credentials_are_reported() {
+        let (port, _) = serve_once(401).await;
+        let response = reqwest::get(format!("http://127.0.0.1:{port}/"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            Device::decode(response).await,
+            Err(RetrieveError::Unauthorized)
+        ));
+    }
+
+    // Store a device listening on the given local port, returning its
+    // identifier.
+    async fn stored_device(db: &mut Connection<Devices>, port: u16) -> u16 {
+        let id = insert_device(db, "light", port, "http", "/").await.unwrap();
+        insert_address(db, "127.0.0.1".into(), id).await.unwrap();
+        id
+    }
+
+    // Retrieve every stored device once.
+    async fn retrieve_stored(db: &mut Connection<Devices>, logs: &LogBuffer) -> Vec<Device> {
+        let retry = RetryConfig {
+            max_retries: 1,
+            backoff: 0,
+        };
+        Device::search_for_devices(
+            db,
+            &DeviceClient::new(&HttpConfig::default()).unwrap(),
+            &retry,
+            &Metrics::default(),
+            logs,
+            &DeviceFilter::default(),
+            None,
+            Sort::Id,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn main_routes_follow_the_last_retrieval() {
+        let (_client, mut db) = test_connection().await;
+        let mut data = device(None).data;
+        let (port, _) = serve_body("application/json", serde_json::to_vec(&data).unwrap()).await;
+        let id = stored_device(&mut db, port).await;
+        assert_eq!(
+            retrieve_stored(&mut db, &LogBuffer::default()).await.len(),
+            1
+        );
+
+        data.main_route = MiniString::new("/lamp").unwrap();
+        let (port, _) = serve_body("application/json", serde_json::to_vec(&data).unwrap()).await;
+        sqlx::query("UPDATE devices SET port = $1")
+            .bind(port)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+        assert_eq!(
+            retrieve_stored(&mut db, &LogBuffer::default()).await.len(),
+            1
+        );
+
+        let routes: Vec<String> =
+            sqlx::query_scalar("SELECT route FROM main_routes WHERE device_id = $1")
+                .bind(id)
+                .fetch_all(&mut **db)
+                .await
+                .unwrap();
+        assert_eq!(routes, ["/lamp"]);
+    }
+
+    #[rocket::async_test]
+    async fn devices_sending_unexpected_content_are_kept() {
+        let (_client, mut db) = test_connection().await;
+        let (port, _) = serve_body("text/html", b"<html></html>".to_vec()).await;
+        let id = stored_device(&mut db, port).await;
+        let logs = LogBuffer::default();
+
+        // A single failure would delete an unreachable device.
+        assert!(retrieve_stored(&mut db, &logs).await.is_empty());
+
+        let metadata = select_device_metadata_by_id(&mut db, id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            metadata.retrieval_error.as_deref(),
+            Some("Unexpected content type text/html")
+        );
+        assert!(logs.entries()[0].message.contains("text/html"));
+    }
+
+    #[rocket::async_test]
+    async fn invalid_data_stop_the_retrieval() {
+        let (port, _) = serve_body("application/json", b"{\"kind\":".to_vec()).await;
+        let mut metadata = device(None).metadata;
+        metadata.port = port;
+        // The second address is never contacted.
+        let addresses = ["127.0.0.1", "127.0.0.2"]
+            .into_iter()
+            .map(|address| Address {
+                address: address.into(),
+                reachable: true,
+                latency: None,
+            })
+            .collect();
+        let mut addresses = DeviceAddress::addresses(&metadata, addresses);
+
+        let client = DeviceClient::new(&HttpConfig::default()).unwrap();
+        let retrieved = Device::retrieve(&client, &mut addresses).await;
+
+        assert!(matches!(retrieved, Err(RetrieveError::Decode { .. })));
+        assert!(addresses[0].recheable);
+        assert!(!addresses[1].attempted);
     }
 }
@@ -0,0 +1,377 @@
+use rocket_db_pools::sqlx::{self, Connection as _, SqliteConnection};
+
+use serde::{Deserialize, Serialize};
+
+use crate::inputs::DiscoveryMode;
+
+use super::query::{
+    delete_all_devices, insert_address, insert_boolean_input, insert_color_input,
+    insert_enum_input, insert_hazard, insert_hazard_definition, insert_main_route, insert_property,
+    insert_rangef64_input, insert_rangeu64_input, insert_restored_device, insert_route,
+    insert_text_input, select_device_addresses, select_device_by_fullname, select_device_hazards,
+    select_device_properties, select_device_records, select_device_routes_by_id, select_main_route,
+    select_route_inputs,
+};
+use super::{Property, RouteHazard, RouteInputs, RouteMethod};
+
+// Version of the dump format.
+//
+// It must be increased whenever the format changes, so that older dumps are
+// rejected instead of being partially restored.
+pub(crate) const DUMP_VERSION: u32 = 1;
+
+// Every stored device, exported for a backup.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Dump {
+    // Version of the dump format.
+    pub(crate) version: u32,
+    // Devices.
+    pub(crate) devices: Vec<DeviceDump>,
+}
+
+// Exported device.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DeviceDump {
+    // mDNS full name, missing for manually registered devices.
+    fullname: Option<String>,
+    // Port.
+    port: u16,
+    // Scheme.
+    scheme: String,
+    // Resource path.
+    path: String,
+    // Name given by the user.
+    name: Option<String>,
+    // How the device has been added.
+    source: String,
+    // Addresses, from the preferred one.
+    addresses: Vec<String>,
+    // Properties.
+    properties: Vec<Property>,
+    // Main route.
+    main_route: Option<String>,
+    // Routes.
+    routes: Vec<RouteDump>,
+}
+
+// Exported device route.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RouteDump {
+    // Route.
+    route: String,
+    // HTTP method.
+    rest_kind: RouteMethod,
+    // Description.
+    description: Option<String>,
+    // Whether the route keeps no state.
+    #[serde(default)]
+    stateless: bool,
+    // Hazards.
+    hazards: Vec<HazardDump>,
+    // Inputs, together with their current values.
+    inputs: RouteInputs,
+}
+
+// Exported hazard of a device route.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HazardDump {
+    // Hazard identifier.
+    id: u16,
+    // Category name.
+    category: String,
+    // Hazard name.
+    name: Option<String>,
+    // Hazard description.
+    description: Option<String>,
+    // Category description.
+    category_description: Option<String>,
+}
+
+impl From<RouteHazard> for HazardDump {
+    fn from(hazard: RouteHazard) -> Self {
+        Self {
+            id: hazard.hazard_id,
+            category: hazard.category,
+            name: hazard.name,
+            description: hazard.description,
+            category_description: hazard.category_description,
+        }
+    }
+}
+
+// Export every stored device, together with its routes and the current
+// values of their inputs.
+pub(crate) async fn export(db: &mut SqliteConnection) -> Result<Dump, sqlx::Error> {
+    let mut devices = Vec::new();
+    for record in select_device_records(db).await? {
+        let addresses = select_device_addresses(db, record.id)
+            .await?
+            .into_iter()
+            .map(|address| address.address)
+            .collect();
+        let properties = select_device_properties(db, record.id).await?;
+        let main_route = select_main_route(db, record.id).await?;
+        let mut hazards = select_device_hazards(db, record.id).await?;
+
+        let mut routes = Vec::new();
+        for route in select_device_routes_by_id(db, record.id).await? {
+            let inputs = select_route_inputs(db, route.id).await?;
+            let (route_hazards, others): (Vec<_>, Vec<_>) = hazards
+                .into_iter()
+                .partition(|hazard| hazard.route_id == route.id);
+            hazards = others;
+
+            routes.push(RouteDump {
+                route: route.route,
+                rest_kind: route.rest_kind,
+                description: route.description,
+                stateless: route.stateless,
+                hazards: route_hazards.into_iter().map(HazardDump::from).collect(),
+                inputs,
+            });
+        }
+
+        devices.push(DeviceDump {
+            fullname: record.fullname,
+            port: record.port,
+            scheme: record.scheme,
+            path: record.path,
+            name: record.name,
+            source: record.source,
+            addresses,
+            properties,
+            main_route,
+            routes,
+        });
+    }
+
+    Ok(Dump {
+        version: DUMP_VERSION,
+        devices,
+    })
+}
+
+// Restore the devices of a dump in a single transaction, so that a failure
+// midway leaves the stored devices untouched.
+//
+// In `replace` mode every stored device is removed first, otherwise
+// devices whose full name is already stored are skipped.
+pub(crate) async fn import(
+    db: &mut SqliteConnection,
+    dump: Dump,
+    mode: DiscoveryMode,
+) -> Result<(), sqlx::Error> {
+    // The transaction is rolled back when dropped before being committed.
+    let mut tx = db.begin().await?;
+    restore(&mut tx, dump, mode).await?;
+    tx.commit().await
+}
+
+async fn restore(
+    db: &mut SqliteConnection,
+    dump: Dump,
+    mode: DiscoveryMode,
+) -> Result<(), sqlx::Error> {
+    if mode == DiscoveryMode::Replace {
+        delete_all_devices(db).await?;
+    }
+
+    for device in dump.devices {
+        if let Some(fullname) = device.fullname.as_deref() {
+            if select_device_by_fullname(db, fullname).await?.is_some() {
+                continue;
+            }
+        }
+
+        let device_id = insert_restored_device(
+            db,
+            device.fullname.as_deref(),
+            device.port,
+            &device.scheme,
+            &device.path,
+            device.name.as_deref(),
+            &device.source,
+        )
+        .await?;
+
+        for address in device.addresses {
+            insert_address(db, address, device_id).await?;
+        }
+        for property in device.properties {
+            insert_property(db, &property.key, &property.value, device_id).await?;
+        }
+        if let Some(main_route) = device.main_route {
+            insert_main_route(db, &main_route, device_id).await?;
+        }
+
+        for route in device.routes {
+            let route_id = insert_route(
+                db,
+                &route.route,
+                route.rest_kind,
+                route.description.as_deref(),
+                route.stateless,
+                device_id,
+            )
+            .await?;
+
+            for hazard in route.hazards {
+                insert_hazard(db, hazard.id, &hazard.category, route_id, device_id).await?;
+                if let (Some(name), Some(description), Some(category_description)) =
+                    (hazard.name, hazard.description, hazard.category_description)
+                {
+                    insert_hazard_definition(
+                        db,
+                        hazard.id,
+                        &name,
+                        &description,
+                        &category_description,
+                    )
+                    .await?;
+                }
+            }
+
+            restore_inputs(db, route.inputs, route_id).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn restore_inputs(
+    db: &mut SqliteConnection,
+    inputs: RouteInputs,
+    route_id: u16,
+) -> Result<(), sqlx::Error> {
+    for input in inputs.booleans {
+        insert_boolean_input(db, &input.name, input.default, input.value, route_id).await?;
+    }
+    for range in inputs.rangesu64 {
+        insert_rangeu64_input(db, range, route_id).await?;
+    }
+    for range in inputs.rangesf64 {
+        insert_rangef64_input(db, range, route_id).await?;
+    }
+    for input in inputs.texts {
+        insert_text_input(db, &input.name, &input.default, &input.value, route_id).await?;
+    }
+    for input in inputs.selects {
+        // Options which cannot be decoded allow no value, as when stored.
+        let options = serde_json::from_str::<Vec<String>>(&input.options).unwrap_or_default();
+        insert_enum_input(
+            db,
+            &input.name,
+            &options,
+            &input.default,
+            &input.value,
+            route_id,
+        )
+        .await?;
+    }
+    for input in inputs.colors {
+        insert_color_input(db, &input.name, &input.default, &input.value, route_id).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket_db_pools::Connection;
+
+    use crate::database::query::{clear_database, insert_device, rename_device};
+    use crate::database::{test_connection, Devices};
+
+    // Store a device with every kind of data.
+    async fn stored_device(db: &mut Connection<Devices>) {
+        let device_id = insert_device(db, "light", 3000, "http", "/").await.unwrap();
+        rename_device(db, device_id, "Kitchen").await.unwrap();
+        insert_address(db, "192.168.1.2".into(), device_id)
+            .await
+            .unwrap();
+        insert_property(db, "firmware", "1.0", device_id)
+            .await
+            .unwrap();
+        insert_main_route(db, "/light", device_id).await.unwrap();
+        let route_id = insert_route(
+            db,
+            "/on/<on>",
+            RouteMethod::Post,
+            Some("Light on"),
+            false,
+            device_id,
+        )
+        .await
+        .unwrap();
+        insert_hazard(db, 0, "Safety", route_id, device_id)
+            .await
+            .unwrap();
+        insert_hazard_definition(db, 0, "Fire Hazard", "An Hazard fire", "Safety")
+            .await
+            .unwrap();
+        insert_boolean_input(db, "on", false, true, route_id)
+            .await
+            .unwrap();
+        insert_enum_input(
+            db,
+            "mode",
+            &["eco".into(), "boost".into()],
+            "eco",
+            "boost",
+            route_id,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_exported_with_their_values() {
+        let (_client, mut db) = test_connection().await;
+        stored_device(&mut db).await;
+
+        let dump = serde_json::to_value(export(&mut db).await.unwrap()).unwrap();
+
+        assert_eq!(dump["version"], DUMP_VERSION);
+        let device = &dump["devices"][0];
+        assert_eq!(device["fullname"], "light");
+        assert_eq!(device["name"], "Kitchen");
+        assert_eq!(device["source"], "discovery");
+        assert_eq!(device["addresses"][0], "192.168.1.2");
+        assert_eq!(device["properties"][0]["key"], "firmware");
+        assert_eq!(device["main_route"], "/light");
+        let route = &device["routes"][0];
+        assert_eq!(route["rest_kind"], "POST");
+        assert_eq!(route["description"], "Light on");
+        assert_eq!(route["hazards"][0]["name"], "Fire Hazard");
+        assert_eq!(route["inputs"]["booleans"][0]["value"], true);
+    }
+
+    #[rocket::async_test]
+    async fn exported_devices_are_imported_back() {
+        let (_client, mut db) = test_connection().await;
+        stored_device(&mut db).await;
+        let exported = export(&mut db).await.unwrap();
+        let expected = serde_json::to_value(&exported).unwrap();
+
+        clear_database(&mut db).await.unwrap();
+        import(&mut db, exported, DiscoveryMode::Replace)
+            .await
+            .unwrap();
+
+        let imported = serde_json::to_value(export(&mut db).await.unwrap()).unwrap();
+        assert_eq!(imported, expected);
+    }
+
+    #[rocket::async_test]
+    async fn merged_imports_skip_stored_devices() {
+        let (_client, mut db) = test_connection().await;
+        stored_device(&mut db).await;
+        let exported = export(&mut db).await.unwrap();
+
+        import(&mut db, exported, DiscoveryMode::Merge)
+            .await
+            .unwrap();
+
+        assert_eq!(export(&mut db).await.unwrap().devices.len(), 1);
+    }
+}
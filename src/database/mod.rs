@@ -1,18 +1,99 @@
 pub(crate) mod controls;
 pub(crate) mod device;
+pub(crate) mod dump;
 pub(crate) mod query;
 
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
 use rocket::fairing::{self, AdHoc};
+use rocket::figment::Figment;
 use rocket::{Build, Rocket};
 
-use rocket_db_pools::{sqlx, sqlx::FromRow, Database};
+use rocket_db_pools::sqlx::migrate::Migrator;
+use rocket_db_pools::sqlx::pool::{PoolConnection, PoolOptions};
+use rocket_db_pools::sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqliteConnection, SqlitePool};
+use rocket_db_pools::sqlx::Executor;
+use rocket_db_pools::{sqlx, sqlx::FromRow, Config, Database, Error, Pool};
+
+use ascot_library::route::RestKind;
+
+use reqwest::Method;
 
 use serde::{Deserialize, Serialize};
 
+use crate::inputs::{BatchItem, InputValue};
+
+use query::{select_migration_version, select_tables};
+
 // Create a database for devices.
 #[derive(Database)]
 #[database("devices")]
-pub(crate) struct Devices(sqlx::SqlitePool);
+pub(crate) struct Devices(DevicesPool);
+
+// Pragmas run on each new connection.
+//
+// SQLite enforces foreign keys only when asked to, connection by connection,
+// and the write-ahead log lets reads run concurrently with a write.
+const CONNECTION_PRAGMAS: &str = "PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;";
+
+// Pool of connections to the devices database.
+//
+// It is built as the default SQLx pool, running the connection pragmas
+// after each connection is opened.
+pub(crate) struct DevicesPool(SqlitePool);
+
+impl Deref for DevicesPool {
+    type Target = SqlitePool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl Pool for DevicesPool {
+    type Connection = PoolConnection<Sqlite>;
+
+    type Error = Error<sqlx::Error>;
+
+    async fn init(figment: &Figment) -> Result<Self, Self::Error> {
+        let config = figment.extract::<Config>()?;
+
+        let options = SqliteConnectOptions::from_str(&config.url)
+            .map_err(Error::Init)?
+            .busy_timeout(Duration::from_secs(config.connect_timeout))
+            .create_if_missing(true);
+
+        PoolOptions::new()
+            .max_connections(config.max_connections as u32)
+            .acquire_timeout(Duration::from_secs(config.connect_timeout))
+            .idle_timeout(config.idle_timeout.map(Duration::from_secs))
+            .min_connections(config.min_connections.unwrap_or_default())
+            .after_connect(|connection, _| {
+                Box::pin(async move {
+                    connection.execute(CONNECTION_PRAGMAS).await?;
+                    Ok(())
+                })
+            })
+            .connect_with(options)
+            .await
+            .map(Self)
+            .map_err(Error::Init)
+    }
+
+    async fn get(&self) -> Result<Self::Connection, Self::Error> {
+        self.0.acquire().await.map_err(Error::Get)
+    }
+
+    async fn close(&self) {
+        self.0.close().await;
+    }
+}
 
 // Device metadata.
 #[derive(Debug, FromRow, Serialize, Deserialize)]
@@ -25,13 +106,73 @@ pub(super) struct Metadata {
     pub(crate) scheme: String,
     // Resource path.
     pub(crate) path: String,
+    // Name given by the user.
+    #[sqlx(default)]
+    pub(crate) name: Option<String>,
+    // UTC epoch of the last successful data retrieval.
+    #[sqlx(default)]
+    pub(crate) last_retrieved: Option<i64>,
+    // UTC epoch of the last discovery.
+    #[sqlx(default)]
+    pub(crate) last_seen: Option<i64>,
+    // UTC epoch before which the device is not contacted again.
+    #[serde(skip)]
+    #[sqlx(default)]
+    pub(crate) next_retry: Option<i64>,
+    // Error of the last retrieval, when the device has answered without
+    // data.
+    #[sqlx(default)]
+    pub(crate) retrieval_error: Option<String>,
+}
+
+impl Metadata {
+    // Whether device data have not been retrieved within the given number
+    // of seconds.
+    pub(crate) fn is_stale(&self, stale_after: u64, now: i64) -> bool {
+        self.last_retrieved
+            .is_none_or(|last_retrieved| now.saturating_sub(last_retrieved) > stale_after as i64)
+    }
+}
+
+// Device row, as needed to restore the device.
+#[derive(Debug, FromRow)]
+pub(super) struct DeviceRecord {
+    // Identifier.
+    pub(crate) id: u16,
+    // mDNS full name, missing for manually registered devices.
+    pub(crate) fullname: Option<String>,
+    // Port.
+    pub(crate) port: u16,
+    // Scheme.
+    pub(crate) scheme: String,
+    // Resource path.
+    pub(crate) path: String,
+    // Name given by the user.
+    pub(crate) name: Option<String>,
+    // How the device has been added.
+    pub(crate) source: String,
+}
+
+// Last device response to a control request.
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub(super) struct DeviceResponse {
+    // HTTP status.
+    #[sqlx(rename = "last_response_status")]
+    pub(crate) status: u16,
+    // Body, possibly truncated.
+    #[sqlx(rename = "last_response_body")]
+    pub(crate) body: String,
 }
 
 // Device address.
 #[derive(Debug, FromRow, Serialize, Deserialize)]
 pub(super) struct Address {
     // Device address.
-    address: String,
+    pub(crate) address: String,
+    // Whether the address has answered the last connection attempt.
+    pub(crate) reachable: bool,
+    // Milliseconds the address has taken to answer the last time.
+    pub(crate) latency: Option<u32>,
 }
 
 // Device property.
@@ -43,20 +184,70 @@ pub(super) struct Property {
     value: String,
 }
 
+// HTTP method of a device route, stored as a short string.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "UPPERCASE")]
+#[sqlx(rename_all = "UPPERCASE")]
+pub(crate) enum RouteMethod {
+    Get,
+    Put,
+    Post,
+    Delete,
+}
+
+impl From<RestKind> for RouteMethod {
+    fn from(rest_kind: RestKind) -> Self {
+        match rest_kind {
+            RestKind::Get => Self::Get,
+            RestKind::Put => Self::Put,
+            RestKind::Post => Self::Post,
+            RestKind::Delete => Self::Delete,
+        }
+    }
+}
+
+impl RouteMethod {
+    // HTTP method used to invoke the route.
+    pub(crate) fn method(self) -> Method {
+        match self {
+            Self::Get => Method::GET,
+            Self::Put => Method::PUT,
+            Self::Post => Method::POST,
+            Self::Delete => Method::DELETE,
+        }
+    }
+}
+
 // Device route.
 #[derive(Debug, FromRow, Serialize, Deserialize)]
 pub(super) struct Route {
     // Identifier.
-    id: u16,
+    pub(crate) id: u16,
     // Device route.
-    route: String,
+    pub(crate) route: String,
+    // HTTP method.
+    pub(crate) rest_kind: RouteMethod,
+    // Description, when advertised by the device.
+    pub(crate) description: Option<String>,
+    // Whether the route keeps no state.
+    pub(crate) stateless: bool,
 }
 
-// Device hazard.
+// Hazard of a device route, joined with its definition.
 #[derive(Debug, FromRow, Serialize, Deserialize)]
-pub(super) struct Hazard {
-    // Device route.
-    hazard: String,
+pub(super) struct RouteHazard {
+    // Route identifier.
+    pub(crate) route_id: u16,
+    // Hazard identifier.
+    pub(crate) hazard_id: u16,
+    // Hazard name, if its definition has been stored.
+    pub(crate) name: Option<String>,
+    // Hazard description, if its definition has been stored.
+    pub(crate) description: Option<String>,
+    // Category name.
+    pub(crate) category: String,
+    // Category description, if the hazard definition has been stored.
+    pub(crate) category_description: Option<String>,
 }
 
 // Device boolean input type.
@@ -64,6 +255,9 @@ pub(super) struct Hazard {
 pub(super) struct BooleanInput {
     // Device boolean name.
     name: String,
+    // Device boolean default value.
+    #[sqlx(rename = "default_value")]
+    default: bool,
     // Device boolean value.
     value: bool,
 }
@@ -74,17 +268,30 @@ pub(super) struct RangeInputU64 {
     // Input name.
     name: String,
     // Minimum value.
+    #[sqlx(try_from = "i64")]
     min: u64,
     // Maximum value.
+    #[sqlx(try_from = "i64")]
     max: u64,
     // Step value.
+    #[sqlx(try_from = "i64")]
     step: u64,
     // Default value.
+    #[sqlx(rename = "default_value", try_from = "i64")]
     default: u64,
     // Current value.
+    #[sqlx(try_from = "i64")]
     value: u64,
 }
 
+impl RangeInputU64 {
+    // Checks whether a value lies within the range and on one of its steps.
+    fn contains(&self, value: u64) -> bool {
+        (self.min..=self.max).contains(&value)
+            && (self.step == 0 || (value - self.min).is_multiple_of(self.step))
+    }
+}
+
 // Device range input type for f64.
 #[derive(Debug, FromRow, Serialize, Deserialize)]
 pub(super) struct RangeInputF64 {
@@ -97,32 +304,569 @@ pub(super) struct RangeInputF64 {
     // Step value.
     step: f64,
     // Default value.
+    #[sqlx(rename = "default_value")]
     default: f64,
     // Current value.
     value: f64,
 }
 
+impl RangeInputF64 {
+    // Tolerance used to check whether a value lies on a step.
+    const STEP_TOLERANCE: f64 = 1e-6;
+
+    // Checks whether a value lies within the range and on one of its steps.
+    fn contains(&self, value: f64) -> bool {
+        let steps = (value - self.min) / self.step;
+        (self.min..=self.max).contains(&value)
+            && (self.step <= 0. || (steps - steps.round()).abs() < Self::STEP_TOLERANCE)
+    }
+}
+
+// Device text input type.
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub(super) struct TextInput {
+    // Input name.
+    name: String,
+    // Default value.
+    #[sqlx(rename = "default_value")]
+    default: String,
+    // Current value.
+    value: String,
+}
+
+// Device select input type.
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub(super) struct SelectInput {
+    // Input name.
+    name: String,
+    // Options, encoded as a JSON array.
+    options: String,
+    // Default value.
+    #[sqlx(rename = "default_value")]
+    default: String,
+    // Current value.
+    value: String,
+}
+
+impl SelectInput {
+    // Checks whether a value is one of the options.
+    //
+    // Options which cannot be decoded allow no value.
+    fn contains(&self, value: &str) -> bool {
+        serde_json::from_str::<Vec<String>>(&self.options)
+            .is_ok_and(|options| options.iter().any(|option| option == value))
+    }
+}
+
+// Device color input type.
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub(super) struct ColorInput {
+    // Input name.
+    name: String,
+    // Default value.
+    #[sqlx(rename = "default_value")]
+    default: String,
+    // Current value.
+    value: String,
+}
+
+// Inputs of a device route.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct RouteInputs {
+    // Boolean inputs.
+    booleans: Vec<BooleanInput>,
+    // Range inputs for u64.
+    rangesu64: Vec<RangeInputU64>,
+    // Range inputs for f64.
+    rangesf64: Vec<RangeInputF64>,
+    // Text inputs.
+    texts: Vec<TextInput>,
+    // Select inputs.
+    selects: Vec<SelectInput>,
+    // Color inputs.
+    colors: Vec<ColorInput>,
+}
+
+impl RouteInputs {
+    // Default values of the route inputs, identified by their names.
+    pub(crate) fn defaults(&self) -> HashMap<&str, String> {
+        self.booleans
+            .iter()
+            .map(|input| (input.name.as_str(), input.default.to_string()))
+            .chain(
+                self.rangesu64
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.default.to_string())),
+            )
+            .chain(
+                self.rangesf64
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.default.to_string())),
+            )
+            .chain(
+                self.texts
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.default.clone())),
+            )
+            .chain(
+                self.selects
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.default.clone())),
+            )
+            .chain(
+                self.colors
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.default.clone())),
+            )
+            .collect()
+    }
+
+    // Current values of the route inputs, identified by their names.
+    pub(crate) fn values(&self) -> HashMap<&str, String> {
+        self.booleans
+            .iter()
+            .map(|input| (input.name.as_str(), input.value.to_string()))
+            .chain(
+                self.rangesu64
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.value.to_string())),
+            )
+            .chain(
+                self.rangesf64
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.value.to_string())),
+            )
+            .chain(
+                self.texts
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.value.clone())),
+            )
+            .chain(
+                self.selects
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.value.clone())),
+            )
+            .chain(
+                self.colors
+                    .iter()
+                    .map(|input| (input.name.as_str(), input.value.clone())),
+            )
+            .collect()
+    }
+
+    // Values of the route inputs reported by a device state, an object
+    // whose keys are input names.
+    //
+    // Values of another type, or not allowed by their input, are ignored.
+    pub(crate) fn state_values(
+        &self,
+        state: &serde_json::Map<String, serde_json::Value>,
+    ) -> Vec<(&str, InputValue)> {
+        self.booleans
+            .iter()
+            .filter_map(|input| {
+                let value = state.get(&input.name)?.as_bool()?;
+                Some((input.name.as_str(), InputValue::CheckBox(value)))
+            })
+            .chain(self.rangesu64.iter().filter_map(|input| {
+                let value = state.get(&input.name)?.as_u64()?;
+                Some((input.name.as_str(), InputValue::SliderU64(value)))
+            }))
+            .chain(self.rangesf64.iter().filter_map(|input| {
+                let value = state.get(&input.name)?.as_f64()?;
+                Some((input.name.as_str(), InputValue::SliderF64(value)))
+            }))
+            .chain(self.texts.iter().filter_map(|input| {
+                let value = state.get(&input.name)?.as_str()?;
+                Some((input.name.as_str(), InputValue::Text(value.into())))
+            }))
+            .chain(self.selects.iter().filter_map(|input| {
+                let value = state.get(&input.name)?.as_str()?;
+                Some((input.name.as_str(), InputValue::Select(value.into())))
+            }))
+            .chain(self.colors.iter().filter_map(|input| {
+                let value = state.get(&input.name)?.as_str()?;
+                Some((input.name.as_str(), InputValue::Color(value.into())))
+            }))
+            .filter(|(name, value)| value.is_valid() && self.allows(name, value))
+            .collect()
+    }
+
+    // Values of the route inputs changed by a batch, either an object whose
+    // keys are input names or the value of the only route input.
+    //
+    // Returns nothing when any value is not allowed by its input.
+    pub(crate) fn batch_values(
+        &self,
+        value: Option<&serde_json::Value>,
+    ) -> Option<Vec<(&str, InputValue)>> {
+        let values = match value {
+            None | Some(serde_json::Value::Null) => return Some(Vec::new()),
+            Some(serde_json::Value::Object(values)) => values.clone(),
+            Some(value) => {
+                let names = self.defaults().into_keys().collect::<Vec<_>>();
+                let [name] = names.as_slice() else {
+                    return None;
+                };
+                serde_json::Map::from_iter([(name.to_string(), value.clone())])
+            }
+        };
+
+        let allowed = self.state_values(&values);
+        (allowed.len() == values.len()).then_some(allowed)
+    }
+
+    // Checks whether a value is allowed by the stored input with the same
+    // name and type.
+    //
+    // Slider values must also lie within the stored range and select values
+    // must be one of the stored options. Buttons carry no value, they only
+    // invoke their route, hence they are always allowed.
+    pub(crate) fn allows(&self, name: &str, value: &InputValue) -> bool {
+        match value {
+            InputValue::SliderU64(value) => self
+                .rangesu64
+                .iter()
+                .any(|input| input.name == name && input.contains(*value)),
+            InputValue::SliderF64(value) => self
+                .rangesf64
+                .iter()
+                .any(|input| input.name == name && input.contains(*value)),
+            InputValue::CheckBox(_) => self.booleans.iter().any(|input| input.name == name),
+            InputValue::Text(_) => self.texts.iter().any(|input| input.name == name),
+            InputValue::Select(value) => self
+                .selects
+                .iter()
+                .any(|input| input.name == name && input.contains(value)),
+            InputValue::Color(_) => self.colors.iter().any(|input| input.name == name),
+            InputValue::Button(_) => true,
+        }
+    }
+}
+
+// Named group of control settings.
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+pub(crate) struct Scene {
+    // Scene identifier.
+    pub(crate) id: u16,
+    // Scene name.
+    pub(crate) name: String,
+}
+
+// Control setting of a scene.
+#[derive(Debug, FromRow)]
+pub(crate) struct SceneItem {
+    // Device identifier.
+    device_id: u16,
+    // Device route.
+    route: String,
+    // JSON value sent to the route, if any.
+    value: Option<String>,
+    // Whether the hazards of the route have been confirmed.
+    confirm: bool,
+}
+
+impl SceneItem {
+    // Control change replaying the setting.
+    pub(crate) fn batch_item(self) -> BatchItem {
+        BatchItem {
+            device_id: self.device_id,
+            route: self.route,
+            value: self
+                .value
+                .and_then(|value| serde_json::from_str(&value).ok()),
+            confirm: self.confirm,
+        }
+    }
+}
+
+// Table schema.
+#[derive(Debug, Serialize)]
+pub(crate) struct TableSchema {
+    // Table name.
+    pub(crate) name: String,
+    // Column names.
+    pub(crate) columns: Vec<String>,
+}
+
+// Database schema.
+#[derive(Debug, Serialize)]
+pub(crate) struct Schema {
+    // Latest applied migration version.
+    pub(crate) version: Option<i64>,
+    // Database tables.
+    pub(crate) tables: Vec<TableSchema>,
+}
+
+// Checks that the database schema is not ahead of the migrations known by
+// this gateway, as happens when a database written by a newer gateway is
+// opened after a downgrade.
+async fn check_schema_version(
+    db: &mut SqliteConnection,
+    migrator: &Migrator,
+) -> Result<(), String> {
+    // A new database has no applied migration.
+    let tables = select_tables(db).await.map_err(|e| e.to_string())?;
+    if !tables.iter().any(|table| table == "_sqlx_migrations") {
+        return Ok(());
+    }
+
+    let applied = select_migration_version(db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let known = migrator.iter().map(|migration| migration.version).max();
+    match applied {
+        Some(applied) if Some(applied) > known => Err(format!(
+            "Database schema version {} is newer than the latest one known by this gateway ({}), \
+             the database has been written by a newer gateway",
+            applied,
+            known.unwrap_or_default()
+        )),
+        _ => Ok(()),
+    }
+}
+
 // Runs database migrations scripts.
 //
-// All database tables are created during this phase.
+// All database tables are created during this phase. A database whose
+// schema is ahead of this gateway is refused, rather than being used with
+// an unknown schema.
 async fn run_migrations(rocket: Rocket<Build>) -> fairing::Result {
-    match Devices::fetch(&rocket) {
-        Some(db) => match sqlx::migrate!("db/migrations").run(&**db).await {
-            Ok(_) => Ok(rocket),
-            Err(e) => {
-                error!("Failed to initialize SQLx database: {}", e);
-                Err(rocket)
-            }
-        },
-        None => Err(rocket),
+    let Some(db) = Devices::fetch(&rocket) else {
+        return Err(rocket);
+    };
+    let migrator = sqlx::migrate!("db/migrations");
+
+    let checked = match db.acquire().await {
+        Ok(mut connection) => check_schema_version(&mut connection, &migrator).await,
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = checked {
+        error!("Failed to check SQLx database schema: {}", e);
+        return Err(rocket);
+    }
+
+    match migrator.run(&***db).await {
+        Ok(_) => Ok(rocket),
+        Err(e) => {
+            error!("Failed to initialize SQLx database: {}", e);
+            Err(rocket)
+        }
     }
 }
 
+// Path of the SQLite file of a database URL, if the database is stored in
+// a file.
+fn database_path(url: &str) -> Option<&Path> {
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .unwrap_or(url);
+    let (path, options) = path.split_once('?').unwrap_or((path, ""));
+
+    let in_memory = path.is_empty()
+        || path == ":memory:"
+        || options.split('&').any(|option| option == "mode=memory");
+    (!in_memory).then(|| Path::new(path))
+}
+
+// Checks that the database file can be written, creating its directory when
+// missing, so that a wrong path is reported before the pool is opened.
+async fn prepare_database_path(rocket: Rocket<Build>) -> fairing::Result {
+    let url = match rocket
+        .figment()
+        .extract_inner::<String>("databases.devices.url")
+    {
+        Ok(url) => url,
+        Err(e) => {
+            error!("Missing database URL: {}", e);
+            return Err(rocket);
+        }
+    };
+    let Some(path) = database_path(&url) else {
+        return Ok(rocket);
+    };
+
+    if let Some(directory) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        if let Err(e) = fs::create_dir_all(directory) {
+            error!(
+                "Cannot create the database directory {}: {}",
+                directory.display(),
+                e
+            );
+            return Err(rocket);
+        }
+    }
+
+    // The file is created when missing, an empty file being an empty
+    // database.
+    if let Err(e) = OpenOptions::new().create(true).append(true).open(path) {
+        error!("Cannot write the database {}: {}", path.display(), e);
+        return Err(rocket);
+    }
+
+    Ok(rocket)
+}
+
 // Create a middle layer to define the database during server creation.
 pub(crate) fn stage() -> AdHoc {
     AdHoc::on_ignite("SQLx Stage", |rocket| async {
         rocket
+            .attach(AdHoc::try_on_ignite("SQLite Path", prepare_database_path))
             .attach(Devices::init())
             .attach(AdHoc::try_on_ignite("SQLx Migrations", run_migrations))
     })
 }
+
+// Open a pooled connection to an in-memory database with every migration
+// applied.
+//
+// The returned client owns the pool, hence it must outlive the connection.
+#[cfg(test)]
+pub(crate) async fn test_connection() -> (
+    rocket::local::asynchronous::Client,
+    rocket_db_pools::Connection<Devices>,
+) {
+    use rocket::request::FromRequest;
+
+    let figment = rocket::Config::figment()
+        .merge(("databases.devices.url", "sqlite::memory:"))
+        .merge(("databases.devices.max_connections", 1));
+    let client =
+        rocket::local::asynchronous::Client::untracked(rocket::custom(figment).attach(stage()))
+            .await
+            .unwrap();
+    let request = client.get("/");
+    let db = rocket_db_pools::Connection::<Devices>::from_request(request.inner())
+        .await
+        .succeeded()
+        .unwrap();
+    (client, db)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn database_paths_are_found_in_urls() {
+        for (url, path) in [
+            ("db/devices.sqlite", Some("db/devices.sqlite")),
+            ("sqlite://db/devices.sqlite", Some("db/devices.sqlite")),
+            (
+                "sqlite:/var/lib/ascot/devices.db?mode=rwc",
+                Some("/var/lib/ascot/devices.db"),
+            ),
+            ("sqlite::memory:", None),
+            ("sqlite://devices?mode=memory", None),
+        ] {
+            assert_eq!(database_path(url), path.map(Path::new), "{}", url);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn unwritable_database_paths_are_refused() {
+        let figment = rocket::Config::figment().merge((
+            "databases.devices.url",
+            "/proc/ascot-gateway/devices.sqlite",
+        ));
+        let client =
+            rocket::local::asynchronous::Client::untracked(rocket::custom(figment).attach(stage()))
+                .await;
+
+        assert!(client.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn newer_schemas_are_refused() {
+        let (_client, mut db) = test_connection().await;
+        let migrator = sqlx::migrate!("db/migrations");
+        assert!(check_schema_version(&mut db, &migrator).await.is_ok());
+
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations(version, description, success, checksum, execution_time)
+             VALUES (99991231000000, 'future', 1, x'00', 0)",
+        )
+        .execute(&mut **db)
+        .await
+        .unwrap();
+
+        let error = check_schema_version(&mut db, &migrator).await.unwrap_err();
+        assert!(error.contains("99991231000000"), "{}", error);
+    }
+
+    fn range_u64() -> RangeInputU64 {
+        RangeInputU64 {
+            name: "brightness".into(),
+            min: 2,
+            max: 20,
+            step: 2,
+            default: 2,
+            value: 2,
+        }
+    }
+
+    fn range_f64() -> RangeInputF64 {
+        RangeInputF64 {
+            name: "brightness".into(),
+            min: 0.,
+            max: 2.,
+            step: 0.1,
+            default: 0.,
+            value: 0.,
+        }
+    }
+
+    #[test]
+    fn u64_ranges_contain_only_their_steps() {
+        let range = range_u64();
+
+        assert!(range.contains(2));
+        assert!(range.contains(20));
+        assert!(!range.contains(0));
+        assert!(!range.contains(22));
+        assert!(!range.contains(5));
+    }
+
+    #[test]
+    fn f64_ranges_contain_only_their_steps() {
+        let range = range_f64();
+
+        assert!(range.contains(0.));
+        assert!(range.contains(0.3));
+        assert!(range.contains(2.));
+        assert!(!range.contains(-0.1));
+        assert!(!range.contains(2.1));
+        assert!(!range.contains(0.15));
+    }
+
+    #[test]
+    fn sliders_are_allowed_only_within_their_ranges() {
+        let inputs = RouteInputs {
+            rangesu64: vec![range_u64()],
+            rangesf64: vec![range_f64()],
+            ..RouteInputs::default()
+        };
+
+        assert!(inputs.allows("brightness", &InputValue::SliderU64(4)));
+        assert!(!inputs.allows("brightness", &InputValue::SliderU64(9999)));
+        assert!(inputs.allows("brightness", &InputValue::SliderF64(1.5)));
+        assert!(!inputs.allows("brightness", &InputValue::SliderF64(9999.)));
+        assert!(!inputs.allows("dimmer", &InputValue::SliderU64(4)));
+    }
+
+    #[test]
+    fn rest_kinds_map_to_their_methods() {
+        for (rest_kind, method) in [
+            (RestKind::Get, Method::GET),
+            (RestKind::Put, Method::PUT),
+            (RestKind::Post, Method::POST),
+            (RestKind::Delete, Method::DELETE),
+        ] {
+            assert_eq!(RouteMethod::from(rest_kind).method(), method);
+        }
+    }
+}
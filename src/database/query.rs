@@ -1,43 +1,321 @@
-use rocket_db_pools::{sqlx, sqlx::FromRow, Connection};
+use rocket_db_pools::sqlx::{self, Connection as _, SqliteConnection};
 
-use super::{Address, Devices, Metadata, RangeInputF64, RangeInputU64};
+use crate::inputs::{BatchItem, DeviceFilter, Sort};
+
+use super::{
+    Address, DeviceRecord, DeviceResponse, Metadata, Property, RangeInputF64, RangeInputU64, Route,
+    RouteHazard, RouteInputs, RouteMethod, Scene, SceneItem,
+};
 
 // Checks whether the database is empty.
 #[inline]
-pub(crate) async fn is_db_empty(db: &mut Connection<Devices>) -> Result<bool, sqlx::Error> {
-    sqlx::query_scalar("SELECT COUNT(*) from devices")
-        .fetch_one(&mut ***db)
+pub(crate) async fn is_db_empty(db: &mut SqliteConnection) -> Result<bool, sqlx::Error> {
+    // The scan stops at the first device.
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM devices)")
+        .fetch_one(&mut *db)
+        .await
+        .map(|exists: bool| !exists)
+}
+
+// Return the number of stored devices.
+#[inline]
+pub(crate) async fn count_devices(db: &mut SqliteConnection) -> Result<u32, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM devices")
+        .fetch_one(&mut *db)
         .await
-        .map(|count: u16| count == 0)
 }
 
 // Insert a device in the database returning the associated identifier.
 #[inline]
 pub(crate) async fn insert_device(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
+    fullname: &str,
     port: u16,
     scheme: &str,
     path: &str,
 ) -> Result<u16, sqlx::Error> {
-    sqlx::query_scalar("INSERT INTO devices(port, scheme, path) VALUES ($1, $2, $3) RETURNING id")
-        .bind(port)
+    sqlx::query_scalar(
+        "INSERT INTO devices(fullname, port, scheme, path, last_seen) VALUES ($1, $2, $3, $4, CAST(strftime('%s', 'now') AS INTEGER)) RETURNING id",
+    )
+    .bind(fullname)
+    .bind(port)
+    .bind(scheme)
+    .bind(path)
+    .fetch_one(&mut *db)
+    .await
+}
+
+// Insert a device restored from a backup, returning the associated
+// identifier.
+#[inline]
+pub(crate) async fn insert_restored_device(
+    db: &mut SqliteConnection,
+    fullname: Option<&str>,
+    port: u16,
+    scheme: &str,
+    path: &str,
+    name: Option<&str>,
+    source: &str,
+) -> Result<u16, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO devices(fullname, port, scheme, path, name, source) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+    )
+    .bind(fullname)
+    .bind(port)
+    .bind(scheme)
+    .bind(path)
+    .bind(name)
+    .bind(source)
+    .fetch_one(&mut *db)
+    .await
+}
+
+// Insert a discovered device in the database or refresh the device
+// with the same full name or reachable at the same endpoint, returning the
+// associated identifier.
+//
+// Addresses and properties of a refreshed device are removed, so that they
+// can be replaced with the discovered ones.
+#[inline]
+pub(crate) async fn upsert_device(
+    db: &mut SqliteConnection,
+    fullname: &str,
+    port: u16,
+    scheme: &str,
+    path: &str,
+    addresses: &[String],
+) -> Result<u16, sqlx::Error> {
+    // A device keeping its full name is the same device, even when it has
+    // moved to another endpoint.
+    let mut id = select_device_by_fullname(db, fullname)
+        .await?
+        .map(|metadata| metadata.id);
+
+    if let Some(id) = id {
+        sqlx::query("UPDATE devices SET port = $1, scheme = $2, path = $3 WHERE id = $4")
+            .bind(port)
+            .bind(scheme)
+            .bind(path)
+            .bind(id)
+            .execute(&mut *db)
+            .await?;
+    }
+
+    for address in addresses {
+        if id.is_some() {
+            break;
+        }
+        id = sqlx::query_scalar(
+            "SELECT devices.id FROM devices JOIN addresses ON addresses.device_id = devices.id WHERE scheme = $1 AND path = $2 AND port = $3 AND address = $4",
+        )
         .bind(scheme)
         .bind(path)
-        .fetch_one(&mut ***db)
-        .await
+        .bind(port)
+        .bind(address)
+        .fetch_optional(&mut *db)
+        .await?;
+    }
+
+    let Some(id) = id else {
+        return insert_device(db, fullname, port, scheme, path).await;
+    };
+
+    sqlx::query(
+        "UPDATE devices SET fullname = $1, last_seen = CAST(strftime('%s', 'now') AS INTEGER) WHERE id = $2",
+    )
+    .bind(fullname)
+    .bind(id)
+    .execute(&mut *db)
+    .await?;
+
+    // Forget the addresses which are no longer advertised, keeping the
+    // priority of the other ones.
+    for stored in select_device_addresses(db, id).await? {
+        if !addresses.contains(&stored.address) {
+            sqlx::query("DELETE FROM addresses WHERE address = $1 AND device_id = $2")
+                .bind(stored.address)
+                .bind(id)
+                .execute(&mut *db)
+                .await?;
+        }
+    }
+
+    delete_device_properties(db, id).await?;
+
+    Ok(id)
+}
+
+// Insert a manually registered device in the database returning the
+// associated identifier.
+#[inline]
+pub(crate) async fn insert_manual_device(
+    db: &mut SqliteConnection,
+    port: u16,
+    scheme: &str,
+    path: &str,
+) -> Result<u16, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO devices(port, scheme, path, source) VALUES ($1, $2, $3, 'manual') RETURNING id",
+    )
+    .bind(port)
+    .bind(scheme)
+    .bind(path)
+    .fetch_one(&mut *db)
+    .await
+}
+
+// Update the time of the last successful data retrieval of a device,
+// forgetting its failed retrievals.
+#[inline]
+pub(crate) async fn update_last_retrieved(
+    db: &mut SqliteConnection,
+    id: u16,
+    last_retrieved: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE devices SET last_retrieved = $1, retry_count = 0, next_retry = NULL, retrieval_error = NULL WHERE id = $2",
+    )
+    .bind(last_retrieved)
+    .bind(id)
+    .execute(&mut *db)
+    .await?;
+    Ok(())
+}
+
+// Update the kind of a device, as sent by its last retrieval.
+#[inline]
+pub(crate) async fn update_device_kind(
+    db: &mut SqliteConnection,
+    id: u16,
+    kind: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE devices SET kind = $1 WHERE id = $2")
+        .bind(kind)
+        .bind(id)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the error of the last failed data retrieval of a device.
+#[inline]
+pub(crate) async fn update_retrieval_error(
+    db: &mut SqliteConnection,
+    id: u16,
+    retrieval_error: Option<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE devices SET retrieval_error = $1 WHERE id = $2")
+        .bind(retrieval_error)
+        .bind(id)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the last response of a device to a control request.
+#[inline]
+pub(crate) async fn update_last_response(
+    db: &mut SqliteConnection,
+    id: u16,
+    status: u16,
+    body: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE devices SET last_response_status = $1, last_response_body = $2 WHERE id = $3",
+    )
+    .bind(status)
+    .bind(body)
+    .bind(id)
+    .execute(&mut *db)
+    .await?;
+    Ok(())
+}
+
+// Record a failed data retrieval of a device, returning the number of
+// consecutive failures.
+//
+// The next retry is postponed by a backoff doubling at each failure.
+#[inline]
+pub(crate) async fn record_retrieval_failure(
+    db: &mut SqliteConnection,
+    id: u16,
+    now: i64,
+    backoff: u64,
+) -> Result<u32, sqlx::Error> {
+    sqlx::query_scalar(
+        "UPDATE devices SET retry_count = retry_count + 1, next_retry = $1 + ($2 << min(retry_count, 16)) WHERE id = $3 RETURNING retry_count",
+    )
+    .bind(now)
+    .bind(backoff as i64)
+    .bind(id)
+    .fetch_one(&mut *db)
+    .await
 }
 
-// Insert device address.
+// Insert device address, unless it is already stored.
 #[inline]
 pub(crate) async fn insert_address(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
+    address: String,
+    device_id: u16,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO addresses(address, device_id) VALUES ($1, $2) ON CONFLICT(device_id, address) DO NOTHING",
+    )
+        .bind(address)
+        .bind(device_id)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Promote a device address, so that it is contacted first.
+#[inline]
+pub(crate) async fn promote_address(
+    db: &mut SqliteConnection,
     address: String,
+    priority: i64,
     device_id: u16,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO addresses(address, device_id) VALUES ($1, $2)")
+    sqlx::query("UPDATE addresses SET priority = $1 WHERE address = $2 AND device_id = $3")
+        .bind(priority)
         .bind(address)
         .bind(device_id)
-        .execute(&mut ***db)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Record whether a device address has answered a connection attempt.
+#[inline]
+pub(crate) async fn update_address_reachable(
+    db: &mut SqliteConnection,
+    address: &str,
+    reachable: bool,
+    device_id: u16,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE addresses SET reachable = $1 WHERE address = $2 AND device_id = $3")
+        .bind(reachable)
+        .bind(address)
+        .bind(device_id)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the milliseconds a device address has taken to answer.
+#[inline]
+pub(crate) async fn update_address_latency(
+    db: &mut SqliteConnection,
+    address: &str,
+    latency: u32,
+    device_id: u16,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE addresses SET latency = $1 WHERE address = $2 AND device_id = $3")
+        .bind(latency)
+        .bind(address)
+        .bind(device_id)
+        .execute(&mut *db)
         .await?;
     Ok(())
 }
@@ -45,7 +323,7 @@ pub(crate) async fn insert_address(
 // Insert device properties.
 #[inline]
 pub(crate) async fn insert_property(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
     key: &str,
     value: &str,
     device_id: u16,
@@ -54,7 +332,7 @@ pub(crate) async fn insert_property(
         .bind(key)
         .bind(value)
         .bind(device_id)
-        .execute(&mut ***db)
+        .execute(&mut *db)
         .await?;
     Ok(())
 }
@@ -62,51 +340,91 @@ pub(crate) async fn insert_property(
 // Insert device hazard.
 #[inline]
 pub(crate) async fn insert_hazard(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
     hazard_id: u16,
+    category: &str,
+    route_id: u16,
     device_id: u16,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO hazards(hazard_id, device_id) VALUES ($1, $2)")
-        .bind(hazard_id)
-        .bind(device_id)
-        .execute(&mut ***db)
-        .await?;
+    sqlx::query(
+        "INSERT INTO hazards(hazard_id, category, route_id, device_id) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(hazard_id)
+    .bind(category)
+    .bind(route_id)
+    .bind(device_id)
+    .execute(&mut *db)
+    .await?;
+    Ok(())
+}
+
+// Insert or update a hazard definition.
+#[inline]
+pub(crate) async fn insert_hazard_definition(
+    db: &mut SqliteConnection,
+    hazard_id: u16,
+    name: &str,
+    description: &str,
+    category_description: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO hazard_definitions(id, name, description, category_description) VALUES ($1, $2, $3, $4)
+         ON CONFLICT(id) DO UPDATE SET name = excluded.name, description = excluded.description, category_description = excluded.category_description",
+    )
+    .bind(hazard_id)
+    .bind(name)
+    .bind(description)
+    .bind(category_description)
+    .execute(&mut *db)
+    .await?;
     Ok(())
 }
 
-// Insert device main route.
+// Insert device main route, replacing the stored one.
 #[inline]
 pub(crate) async fn insert_main_route(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
     main_route: &str,
     device_id: u16,
 ) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO main_routes(route, device_id) VALUES ($1, $2)")
-        .bind(main_route)
-        .bind(device_id)
-        .execute(&mut ***db)
-        .await?;
+    sqlx::query(
+        "INSERT INTO main_routes(route, device_id) VALUES ($1, $2)
+         ON CONFLICT(device_id) DO UPDATE SET route = excluded.route",
+    )
+    .bind(main_route)
+    .bind(device_id)
+    .execute(&mut *db)
+    .await?;
     Ok(())
 }
 
 // Insert device route.
 #[inline]
 pub(crate) async fn insert_route(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
     route: &str,
+    rest_kind: RouteMethod,
+    description: Option<&str>,
+    stateless: bool,
     device_id: u16,
 ) -> Result<u16, sqlx::Error> {
-    sqlx::query_scalar("INSERT INTO routes(route, device_id) VALUES ($1, $2) RETURNING id")
-        .bind(route)
-        .bind(device_id)
-        .fetch_one(&mut ***db)
-        .await
+    sqlx::query_scalar(
+        "INSERT INTO routes(route, rest_kind, description, stateless, device_id)
+        VALUES ($1, $2, $3, $4, $5) RETURNING id",
+    )
+    .bind(route)
+    .bind(rest_kind)
+    .bind(description)
+    .bind(stateless)
+    .bind(device_id)
+    .fetch_one(&mut *db)
+    .await
 }
 
 // Insert boolean input for a device.
 #[inline]
 pub(crate) async fn insert_boolean_input(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
     name: &str,
     default: bool,
     value: bool,
@@ -119,7 +437,7 @@ pub(crate) async fn insert_boolean_input(
     .bind(default)
     .bind(value)
     .bind(route_id)
-    .execute(&mut ***db)
+    .execute(&mut *db)
     .await?;
     Ok(())
 }
@@ -127,7 +445,7 @@ pub(crate) async fn insert_boolean_input(
 // Insert range input for u64.
 #[inline]
 pub(crate) async fn insert_rangeu64_input(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
     range: RangeInputU64,
     route_id: u16,
 ) -> Result<(), sqlx::Error> {
@@ -141,7 +459,7 @@ pub(crate) async fn insert_rangeu64_input(
     .bind(range.default as i64)
     .bind(range.value as i64)
     .bind(route_id)
-    .execute(&mut ***db)
+    .execute(&mut *db)
     .await?;
     Ok(())
 }
@@ -149,7 +467,7 @@ pub(crate) async fn insert_rangeu64_input(
 // Insert range input for f64.
 #[inline]
 pub(crate) async fn insert_rangef64_input(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
     range: RangeInputF64,
     route_id: u16,
 ) -> Result<(), sqlx::Error> {
@@ -163,71 +481,1474 @@ pub(crate) async fn insert_rangef64_input(
     .bind(range.default)
     .bind(range.value)
     .bind(route_id)
-    .execute(&mut ***db)
+    .execute(&mut *db)
+    .await?;
+    Ok(())
+}
+
+// Insert text input.
+#[inline]
+pub(crate) async fn insert_text_input(
+    db: &mut SqliteConnection,
+    name: &str,
+    default: &str,
+    value: &str,
+    route_id: u16,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO texts(name, default_value, value, route_id) VALUES ($1, $2, $3, $4)")
+        .bind(name)
+        .bind(default)
+        .bind(value)
+        .bind(route_id)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Insert select input.
+#[inline]
+pub(crate) async fn insert_enum_input(
+    db: &mut SqliteConnection,
+    name: &str,
+    options: &[String],
+    default: &str,
+    value: &str,
+    route_id: u16,
+) -> Result<(), sqlx::Error> {
+    let options = serde_json::json!(options).to_string();
+
+    sqlx::query(
+        "INSERT INTO selects(name, options, default_value, value, route_id) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(name)
+    .bind(options)
+    .bind(default)
+    .bind(value)
+    .bind(route_id)
+    .execute(&mut *db)
     .await?;
     Ok(())
 }
 
-// Delete all data present in a database.
+// Insert color input.
+#[inline]
+pub(crate) async fn insert_color_input(
+    db: &mut SqliteConnection,
+    name: &str,
+    default: &str,
+    value: &str,
+    route_id: u16,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO colors(name, default_value, value, route_id) VALUES ($1, $2, $3, $4)")
+        .bind(name)
+        .bind(default)
+        .bind(value)
+        .bind(route_id)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Delete all data present in a database atomically.
 #[inline]
-pub(crate) async fn clear_database(db: &mut Connection<Devices>) -> Result<(), sqlx::Error> {
+pub(crate) async fn clear_database(db: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    // The transaction is rolled back when dropped before being committed.
+    let mut tx = db.begin().await?;
+    delete_all_devices(&mut tx).await?;
+    tx.commit().await
+}
+
+// Delete all devices together with their data, restarting device
+// identifiers.
+//
+// No transaction is begun, so that deletions can be part of a larger one.
+pub(crate) async fn delete_all_devices(db: &mut SqliteConnection) -> Result<(), sqlx::Error> {
     // Clear the devices table and each of its sub-tables.
-    sqlx::query("DELETE FROM devices")
-        .execute(&mut ***db)
+    //
+    // SQLite does not support `TRUNCATE`, so rows are deleted and removed
+    // on cascade from the other tables.
+    sqlx::query("DELETE FROM devices").execute(&mut *db).await?;
+
+    // Restart device identifiers, if they are generated through an
+    // autoincrement sequence.
+    let has_sequence: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_sequence')",
+    )
+    .fetch_one(&mut *db)
+    .await?;
+    if has_sequence {
+        sqlx::query("DELETE FROM sqlite_sequence WHERE name = 'devices'")
+            .execute(&mut *db)
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Delete all discovered devices, keeping the manually registered ones.
+#[inline]
+pub(crate) async fn clear_discovered_devices(db: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM devices WHERE source = 'discovery'")
+        .execute(&mut *db)
         .await?;
 
     Ok(())
 }
 
-// Delete a device and its data.
+// Delete a device through its mDNS full name.
 #[inline]
-pub(crate) async fn delete_device(
-    db: &mut Connection<Devices>,
-    id: u16,
+pub(crate) async fn delete_device_by_fullname(
+    db: &mut SqliteConnection,
+    fullname: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM devices WHERE fullname = $1")
+        .bind(fullname)
+        .execute(&mut *db)
+        .await?;
+
+    Ok(())
+}
+
+// Delete all properties of a device.
+#[inline]
+pub(crate) async fn delete_device_properties(
+    db: &mut SqliteConnection,
+    device_id: u16,
 ) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM properties WHERE device_id = $1")
+        .bind(device_id)
+        .execute(&mut *db)
+        .await?;
+
+    Ok(())
+}
+
+// Delete a device and its data, returning whether the device existed.
+#[inline]
+pub(crate) async fn delete_device(db: &mut SqliteConnection, id: u16) -> Result<bool, sqlx::Error> {
     // Delete the device identified by the given id.
     //
     // The deleting process is propagated on cascade to all the other tables
     // containing the device id as foreign key.
-    sqlx::query("DELETE FROM devices WHERE id = $1")
+    let result = sqlx::query("DELETE FROM devices WHERE id = $1")
         .bind(id)
-        .execute(&mut ***db)
+        .execute(&mut *db)
         .await?;
 
-    Ok(())
+    Ok(result.rows_affected() > 0)
+}
+
+// Give a name to a device, returning whether the device exists.
+#[inline]
+pub(crate) async fn rename_device(
+    db: &mut SqliteConnection,
+    id: u16,
+    name: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE devices SET name = $1 WHERE id = $2")
+        .bind(name)
+        .bind(id)
+        .execute(&mut *db)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Insert a scene together with its control settings atomically, returning
+// the scene identifier.
+pub(crate) async fn insert_scene(
+    db: &mut SqliteConnection,
+    name: &str,
+    items: &[BatchItem],
+) -> Result<u16, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let id = insert_scene_rows(&mut tx, name, items).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+async fn insert_scene_rows(
+    db: &mut SqliteConnection,
+    name: &str,
+    items: &[BatchItem],
+) -> Result<u16, sqlx::Error> {
+    let id = sqlx::query_scalar("INSERT INTO scenes(name) VALUES ($1) RETURNING id")
+        .bind(name)
+        .fetch_one(&mut *db)
+        .await?;
+
+    for item in items {
+        sqlx::query(
+            "INSERT INTO scene_items(scene_id, device_id, route, value, confirm) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(item.device_id)
+        .bind(&item.route)
+        .bind(item.value.as_ref().map(|value| value.to_string()))
+        .bind(item.confirm)
+        .execute(&mut *db)
+        .await?;
+    }
+
+    Ok(id)
+}
+
+// Return all scenes, sorted by name.
+#[inline]
+pub(crate) async fn select_scenes(db: &mut SqliteConnection) -> Result<Vec<Scene>, sqlx::Error> {
+    sqlx::query_as("SELECT id, name FROM scenes ORDER BY name")
+        .fetch_all(&mut *db)
+        .await
+}
+
+// Return a scene by its name.
+#[inline]
+pub(crate) async fn select_scene_by_name(
+    db: &mut SqliteConnection,
+    name: &str,
+) -> Result<Option<Scene>, sqlx::Error> {
+    sqlx::query_as("SELECT id, name FROM scenes WHERE name = $1")
+        .bind(name)
+        .fetch_optional(&mut *db)
+        .await
+}
+
+// Return a scene by its identifier.
+#[inline]
+pub(crate) async fn select_scene(
+    db: &mut SqliteConnection,
+    id: u16,
+) -> Result<Option<Scene>, sqlx::Error> {
+    sqlx::query_as("SELECT id, name FROM scenes WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&mut *db)
+        .await
 }
 
-// Return device information.
+// Return the control settings of a scene, in their order.
+#[inline]
+pub(crate) async fn select_scene_items(
+    db: &mut SqliteConnection,
+    scene_id: u16,
+) -> Result<Vec<SceneItem>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT device_id, route, value, confirm FROM scene_items WHERE scene_id = $1 ORDER BY id",
+    )
+    .bind(scene_id)
+    .fetch_all(&mut *db)
+    .await
+}
+
+// Return the `ORDER BY` clause of a devices order.
+//
+// Clauses come from a fixed list, so that no user input ends up in a query.
+// Kinds are known once devices are retrieved, hence devices never retrieved
+// come last.
+fn order_by(sort: Sort) -> &'static str {
+    match sort {
+        Sort::Id => "ORDER BY id",
+        Sort::Kind => "ORDER BY kind IS NULL, kind, id",
+        Sort::Name => "ORDER BY name IS NULL, name COLLATE NOCASE, id",
+        Sort::Reachable => {
+            "ORDER BY NOT EXISTS(SELECT 1 FROM addresses WHERE addresses.device_id = devices.id AND addresses.reachable), id"
+        }
+    }
+}
+
+// Condition selecting the devices matching a filter, leaving out those
+// waiting for their next retrieval attempt.
+//
+// The hazard is bound to `$1`, the escaped text to `$2` and the current
+// UTC epoch to `$3`. LIKE wildcards in the text are matched literally.
+const LISTED_DEVICES: &str = "WHERE (next_retry IS NULL OR next_retry <= $3) \
+    AND ($1 IS NULL OR EXISTS(SELECT 1 FROM hazards WHERE hazards.device_id = devices.id AND hazards.hazard_id = $1)) \
+    AND ($2 IS NULL OR path LIKE '%' || $2 || '%' ESCAPE '\\' OR name LIKE '%' || $2 || '%' ESCAPE '\\')";
+
+// Return the information of the devices matching a filter.
 #[inline]
 pub(crate) async fn select_device_metadata(
-    db: &mut Connection<Devices>,
+    db: &mut SqliteConnection,
+    filter: &DeviceFilter,
+    now: i64,
+    sort: Sort,
 ) -> Result<Vec<Metadata>, sqlx::Error> {
-    sqlx::query_as("SELECT id, port, scheme, path FROM devices ORDER BY id")
-        .fetch_all(&mut ***db)
+    let query = format!(
+        "SELECT id, port, scheme, path, name, last_retrieved, last_seen, next_retry FROM devices {} {}",
+        LISTED_DEVICES,
+        order_by(sort)
+    );
+    sqlx::query_as(&query)
+        .bind(filter.hazard)
+        .bind(filter.text.as_deref().map(escape_like))
+        .bind(now)
+        .fetch_all(&mut *db)
         .await
 }
 
-// Return device address information.
+// Return the number of devices matching a filter.
 #[inline]
-pub(crate) async fn select_device_addresses(
-    db: &mut Connection<Devices>,
-    device_id: u16,
-) -> Result<Vec<Address>, sqlx::Error> {
-    sqlx::query_as("SELECT address FROM addresses WHERE device_id = $1")
-        .bind(device_id)
-        .fetch_all(&mut ***db)
+pub(crate) async fn count_listed_devices(
+    db: &mut SqliteConnection,
+    filter: &DeviceFilter,
+    now: i64,
+) -> Result<u32, sqlx::Error> {
+    let query = format!("SELECT COUNT(*) FROM devices {}", LISTED_DEVICES);
+    sqlx::query_scalar(&query)
+        .bind(filter.hazard)
+        .bind(filter.text.as_deref().map(escape_like))
+        .bind(now)
+        .fetch_one(&mut *db)
         .await
 }
 
-// Return all available hazards.
+// Return the rows of all devices.
 #[inline]
-pub(crate) async fn all_hazards(mut db: Connection<Devices>) -> Result<Vec<u16>, sqlx::Error> {
-    #[derive(FromRow)]
-    struct HazardId(u16);
+pub(crate) async fn select_device_records(
+    db: &mut SqliteConnection,
+) -> Result<Vec<DeviceRecord>, sqlx::Error> {
+    sqlx::query_as("SELECT id, fullname, port, scheme, path, name, source FROM devices ORDER BY id")
+        .fetch_all(&mut *db)
+        .await
+}
 
-    let hazards_id: Vec<HazardId> = sqlx::query_as("SELECT DISTINCT hazard_id FROM hazards")
-        .fetch_all(&mut **db)
-        .await?;
+// Return the metadata of a page of the devices matching a filter.
+#[inline]
+pub(crate) async fn select_device_metadata_paginated(
+    db: &mut SqliteConnection,
+    filter: &DeviceFilter,
+    now: i64,
+    limit: u32,
+    offset: u32,
+    sort: Sort,
+) -> Result<Vec<Metadata>, sqlx::Error> {
+    let query = format!(
+        "SELECT id, port, scheme, path, name, last_retrieved, last_seen, next_retry FROM devices {} {} LIMIT $4 OFFSET $5",
+        LISTED_DEVICES,
+        order_by(sort)
+    );
+    sqlx::query_as(&query)
+        .bind(filter.hazard)
+        .bind(filter.text.as_deref().map(escape_like))
+        .bind(now)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&mut *db)
+        .await
+}
+
+// Return the metadata of the discovered devices not seen since a UTC epoch.
+//
+// Manually registered devices are never stale.
+#[inline]
+pub(crate) async fn select_stale_devices(
+    db: &mut SqliteConnection,
+    cutoff: i64,
+) -> Result<Vec<Metadata>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, port, scheme, path, name, last_retrieved, last_seen, next_retry FROM devices WHERE source = 'discovery' AND last_seen < $1 ORDER BY id",
+    )
+    .bind(cutoff)
+    .fetch_all(&mut *db)
+    .await
+}
+
+// Return the hazards presented by the stored devices, together with their
+// names when their definitions have been stored.
+#[inline]
+pub(crate) async fn select_stored_hazards(
+    db: &mut SqliteConnection,
+) -> Result<Vec<(u16, Option<String>)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT DISTINCT hazards.hazard_id, hazard_definitions.name FROM hazards LEFT JOIN hazard_definitions ON hazard_definitions.id = hazards.hazard_id ORDER BY hazards.hazard_id",
+    )
+    .fetch_all(&mut *db)
+    .await
+}
+
+// Escape the LIKE wildcards of a text, together with the escape character.
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Return the properties of a device.
+#[inline]
+pub(crate) async fn select_device_properties(
+    db: &mut SqliteConnection,
+    device_id: u16,
+) -> Result<Vec<Property>, sqlx::Error> {
+    sqlx::query_as("SELECT key, value FROM properties WHERE device_id = $1")
+        .bind(device_id)
+        .fetch_all(&mut *db)
+        .await
+}
+
+// Return device address information.
+#[inline]
+pub(crate) async fn select_device_addresses(
+    db: &mut SqliteConnection,
+    device_id: u16,
+) -> Result<Vec<Address>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT address, reachable, latency FROM addresses WHERE device_id = $1 ORDER BY priority DESC",
+    )
+    .bind(device_id)
+    .fetch_all(&mut *db)
+    .await
+}
+
+// Return the information of a device.
+#[inline]
+pub(crate) async fn select_device_metadata_by_id(
+    db: &mut SqliteConnection,
+    id: u16,
+) -> Result<Option<Metadata>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, port, scheme, path, name, last_retrieved, last_seen, next_retry, retrieval_error FROM devices WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&mut *db)
+    .await
+}
+
+// Return the last response of a device to a control request.
+#[inline]
+pub(crate) async fn select_last_response(
+    db: &mut SqliteConnection,
+    id: u16,
+) -> Result<Option<DeviceResponse>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT last_response_status, last_response_body FROM devices
+         WHERE id = $1 AND last_response_status IS NOT NULL",
+    )
+    .bind(id)
+    .fetch_optional(&mut *db)
+    .await
+}
+
+// Return the information of a device through its mDNS full name.
+#[inline]
+pub(crate) async fn select_device_by_fullname(
+    db: &mut SqliteConnection,
+    fullname: &str,
+) -> Result<Option<Metadata>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, port, scheme, path, name, last_retrieved, last_seen, next_retry FROM devices WHERE fullname = $1",
+    )
+    .bind(fullname)
+    .fetch_optional(&mut *db)
+    .await
+}
+
+// Return the mDNS full name of a device.
+#[inline]
+pub(crate) async fn select_device_fullname(
+    db: &mut SqliteConnection,
+    id: u16,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT fullname FROM devices WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&mut *db)
+        .await
+        .map(Option::flatten)
+}
+
+// Return device main route.
+#[inline]
+pub(crate) async fn select_main_route(
+    db: &mut SqliteConnection,
+    device_id: u16,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT route FROM main_routes WHERE device_id = $1")
+        .bind(device_id)
+        .fetch_optional(&mut *db)
+        .await
+}
+
+// Return a device route.
+#[inline]
+pub(crate) async fn select_route(
+    db: &mut SqliteConnection,
+    route_id: u16,
+    device_id: u16,
+) -> Result<Option<Route>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, route, rest_kind, description, stateless FROM routes WHERE id = $1 AND device_id = $2",
+    )
+    .bind(route_id)
+    .bind(device_id)
+    .fetch_optional(&mut *db)
+    .await
+}
+
+// Return all routes of a device.
+#[inline]
+pub(crate) async fn select_device_routes_by_id(
+    db: &mut SqliteConnection,
+    device_id: u16,
+) -> Result<Vec<Route>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, route, rest_kind, description, stateless FROM routes WHERE device_id = $1 ORDER BY id",
+    )
+    .bind(device_id)
+    .fetch_all(&mut *db)
+    .await
+}
+
+// Return a device route by its name.
+#[inline]
+pub(crate) async fn select_route_by_name(
+    db: &mut SqliteConnection,
+    route: &str,
+    device_id: u16,
+) -> Result<Option<Route>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, route, rest_kind, description, stateless FROM routes WHERE route = $1 AND device_id = $2",
+    )
+    .bind(route)
+    .bind(device_id)
+    .fetch_optional(&mut *db)
+    .await
+}
+
+// Return the inputs of a device route.
+#[inline]
+pub(crate) async fn select_route_inputs(
+    db: &mut SqliteConnection,
+    route_id: u16,
+) -> Result<RouteInputs, sqlx::Error> {
+    let booleans =
+        sqlx::query_as("SELECT name, default_value, value FROM booleans WHERE route_id = $1")
+            .bind(route_id)
+            .fetch_all(&mut *db)
+            .await?;
+
+    let rangesu64 = sqlx::query_as(
+        "SELECT name, min, max, step, default_value, value FROM rangesu64 WHERE route_id = $1",
+    )
+    .bind(route_id)
+    .fetch_all(&mut *db)
+    .await?;
+
+    let rangesf64 = sqlx::query_as(
+        "SELECT name, min, max, step, default_value, value FROM rangesf64 WHERE route_id = $1",
+    )
+    .bind(route_id)
+    .fetch_all(&mut *db)
+    .await?;
+
+    let texts = sqlx::query_as("SELECT name, default_value, value FROM texts WHERE route_id = $1")
+        .bind(route_id)
+        .fetch_all(&mut *db)
+        .await?;
+
+    let selects = sqlx::query_as(
+        "SELECT name, options, default_value, value FROM selects WHERE route_id = $1",
+    )
+    .bind(route_id)
+    .fetch_all(&mut *db)
+    .await?;
+
+    let colors =
+        sqlx::query_as("SELECT name, default_value, value FROM colors WHERE route_id = $1")
+            .bind(route_id)
+            .fetch_all(&mut *db)
+            .await?;
+
+    Ok(RouteInputs {
+        booleans,
+        rangesu64,
+        rangesf64,
+        texts,
+        selects,
+        colors,
+    })
+}
+
+// Update the value of a boolean input.
+#[inline]
+pub(crate) async fn update_boolean_value(
+    db: &mut SqliteConnection,
+    route_id: u16,
+    name: &str,
+    value: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE booleans SET value = $1 WHERE route_id = $2 AND name = $3")
+        .bind(value)
+        .bind(route_id)
+        .bind(name)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the value of a range input for u64.
+#[inline]
+pub(crate) async fn update_rangeu64_value(
+    db: &mut SqliteConnection,
+    route_id: u16,
+    name: &str,
+    value: u64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE rangesu64 SET value = $1 WHERE route_id = $2 AND name = $3")
+        .bind(value as i64)
+        .bind(route_id)
+        .bind(name)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the value of a range input for f64.
+#[inline]
+pub(crate) async fn update_rangef64_value(
+    db: &mut SqliteConnection,
+    route_id: u16,
+    name: &str,
+    value: f64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE rangesf64 SET value = $1 WHERE route_id = $2 AND name = $3")
+        .bind(value)
+        .bind(route_id)
+        .bind(name)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the value of a text input.
+#[inline]
+pub(crate) async fn update_text_value(
+    db: &mut SqliteConnection,
+    route_id: u16,
+    name: &str,
+    value: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE texts SET value = $1 WHERE route_id = $2 AND name = $3")
+        .bind(value)
+        .bind(route_id)
+        .bind(name)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the value of a select input.
+#[inline]
+pub(crate) async fn update_select_value(
+    db: &mut SqliteConnection,
+    route_id: u16,
+    name: &str,
+    value: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE selects SET value = $1 WHERE route_id = $2 AND name = $3")
+        .bind(value)
+        .bind(route_id)
+        .bind(name)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Update the value of a color input.
+#[inline]
+pub(crate) async fn update_color_value(
+    db: &mut SqliteConnection,
+    route_id: u16,
+    name: &str,
+    value: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE colors SET value = $1 WHERE route_id = $2 AND name = $3")
+        .bind(value)
+        .bind(route_id)
+        .bind(name)
+        .execute(&mut *db)
+        .await?;
+    Ok(())
+}
+
+// Reset the inputs of a device route to their default values.
+#[inline]
+pub(crate) async fn reset_route_inputs(
+    db: &mut SqliteConnection,
+    route_id: u16,
+) -> Result<(), sqlx::Error> {
+    for table in [
+        "booleans",
+        "rangesu64",
+        "rangesf64",
+        "texts",
+        "selects",
+        "colors",
+    ] {
+        sqlx::query(&format!(
+            "UPDATE {table} SET value = default_value WHERE route_id = $1"
+        ))
+        .bind(route_id)
+        .execute(&mut *db)
+        .await?;
+    }
+    Ok(())
+}
+
+// Return the hazard categories of a device route.
+#[inline]
+pub(crate) async fn select_route_hazard_categories(
+    db: &mut SqliteConnection,
+    route_id: u16,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT DISTINCT category FROM hazards WHERE route_id = $1")
+        .bind(route_id)
+        .fetch_all(&mut *db)
+        .await
+}
+
+// Return the hazards of each device route, together with their definitions.
+#[inline]
+pub(crate) async fn select_device_hazards(
+    db: &mut SqliteConnection,
+    device_id: u16,
+) -> Result<Vec<RouteHazard>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT hazards.route_id, hazards.hazard_id, hazard_definitions.name, hazard_definitions.description,
+                hazards.category, hazard_definitions.category_description
+         FROM hazards LEFT JOIN hazard_definitions ON hazard_definitions.id = hazards.hazard_id
+         WHERE hazards.device_id = $1
+         ORDER BY hazards.route_id, hazards.hazard_id",
+    )
+    .bind(device_id)
+    .fetch_all(&mut *db)
+    .await
+}
+
+// Return the latest applied migration version.
+#[inline]
+pub(crate) async fn select_migration_version(
+    db: &mut SqliteConnection,
+) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1")
+        .fetch_one(&mut *db)
+        .await
+}
+
+// Return the names of all database tables.
+#[inline]
+pub(crate) async fn select_tables(db: &mut SqliteConnection) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )
+    .fetch_all(&mut *db)
+    .await
+}
+
+// Return the column names of a table.
+#[inline]
+pub(crate) async fn select_table_columns(
+    db: &mut SqliteConnection,
+    table: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT name FROM pragma_table_info($1) ORDER BY cid")
+        .bind(table)
+        .fetch_all(&mut *db)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket_db_pools::Connection;
+
+    use crate::database::{test_connection, Devices};
+
+    // Store a discovered device with a single route, returning their
+    // identifiers.
+    async fn device_with_route(db: &mut Connection<Devices>, fullname: &str) -> (u16, u16) {
+        let device_id = insert_device(db, fullname, 3000, "http", "/")
+            .await
+            .unwrap();
+        let route_id = insert_route(db, "/on", RouteMethod::Put, None, false, device_id)
+            .await
+            .unwrap();
+        (device_id, route_id)
+    }
+
+    #[rocket::async_test]
+    async fn migration_version_is_the_latest_one() {
+        let (_client, mut db) = test_connection().await;
+
+        let latest = sqlx::migrate!("db/migrations")
+            .iter()
+            .map(|migration| migration.version)
+            .max();
+        assert_eq!(select_migration_version(&mut db).await.unwrap(), latest);
+    }
+
+    #[rocket::async_test]
+    async fn tables_exclude_sqlite_internals() {
+        let (_client, mut db) = test_connection().await;
+
+        let tables = select_tables(&mut db).await.unwrap();
+        assert!(tables.iter().any(|table| table == "devices"));
+        assert!(tables.iter().all(|table| !table.starts_with("sqlite_")));
+    }
+
+    #[rocket::async_test]
+    async fn table_columns_follow_their_order() {
+        let (_client, mut db) = test_connection().await;
+
+        let columns = select_table_columns(&mut db, "main_routes").await.unwrap();
+        assert_eq!(columns, ["id", "route", "device_id"]);
+    }
+
+    #[rocket::async_test]
+    async fn boolean_inputs_are_stored() {
+        let (_client, mut db) = test_connection().await;
+        let (_, route_id) = device_with_route(&mut db, "light").await;
+
+        insert_boolean_input(&mut db, "on", true, false, route_id)
+            .await
+            .unwrap();
+
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert_eq!(inputs.defaults()["on"], "true");
+        assert_eq!(inputs.values()["on"], "false");
+    }
+
+    #[rocket::async_test]
+    async fn reset_restores_default_values() {
+        let (_client, mut db) = test_connection().await;
+        let (_, route_id) = device_with_route(&mut db, "light").await;
+
+        insert_boolean_input(&mut db, "on", false, true, route_id)
+            .await
+            .unwrap();
+        let range = RangeInputU64 {
+            name: "brightness".into(),
+            min: 0,
+            max: 10,
+            step: 1,
+            default: 5,
+            value: 8,
+        };
+        insert_rangeu64_input(&mut db, range, route_id)
+            .await
+            .unwrap();
+
+        reset_route_inputs(&mut db, route_id).await.unwrap();
+
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert!(!inputs.booleans[0].value);
+        assert_eq!(inputs.rangesu64[0].value, 5);
+    }
+
+    #[rocket::async_test]
+    async fn reset_leaves_other_routes_alone() {
+        let (_client, mut db) = test_connection().await;
+        let (_, route_id) = device_with_route(&mut db, "light").await;
+        let (_, other_id) = device_with_route(&mut db, "fridge").await;
+
+        insert_boolean_input(&mut db, "on", false, true, route_id)
+            .await
+            .unwrap();
+        insert_boolean_input(&mut db, "on", false, true, other_id)
+            .await
+            .unwrap();
+
+        reset_route_inputs(&mut db, route_id).await.unwrap();
+
+        let inputs = select_route_inputs(&mut db, other_id).await.unwrap();
+        assert!(inputs.booleans[0].value);
+    }
+
+    #[rocket::async_test]
+    async fn discovery_keeps_manual_devices() {
+        let (_client, mut db) = test_connection().await;
+        let discovered_id = insert_device(&mut db, "light", 3000, "http", "/")
+            .await
+            .unwrap();
+        let manual_id = insert_manual_device(&mut db, 3000, "http", "/")
+            .await
+            .unwrap();
+
+        clear_discovered_devices(&mut db).await.unwrap();
+
+        let discovered = select_device_metadata_by_id(&mut db, discovered_id).await;
+        assert!(discovered.unwrap().is_none());
+        let manual = select_device_metadata_by_id(&mut db, manual_id).await;
+        assert!(manual.unwrap().is_some());
+    }
+
+    #[rocket::async_test]
+    async fn promoted_addresses_come_first() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        insert_address(&mut db, "10.0.0.1".into(), device_id)
+            .await
+            .unwrap();
+        insert_address(&mut db, "10.0.0.2".into(), device_id)
+            .await
+            .unwrap();
+
+        promote_address(&mut db, "10.0.0.2".into(), 1, device_id)
+            .await
+            .unwrap();
+
+        let addresses = select_device_addresses(&mut db, device_id).await.unwrap();
+        let addresses = addresses
+            .iter()
+            .map(|address| address.address.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(addresses, ["10.0.0.2", "10.0.0.1"]);
+    }
+
+    #[rocket::async_test]
+    async fn upserted_devices_keep_the_priority_of_their_addresses() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        insert_address(&mut db, "10.0.0.1".into(), device_id)
+            .await
+            .unwrap();
+        insert_address(&mut db, "10.0.0.2".into(), device_id)
+            .await
+            .unwrap();
+        promote_address(&mut db, "10.0.0.2".into(), 1, device_id)
+            .await
+            .unwrap();
+
+        let advertised = ["10.0.0.3".into(), "10.0.0.2".into()];
+        let id = upsert_device(&mut db, "light", 3000, "http", "/", &advertised)
+            .await
+            .unwrap();
+        for address in advertised {
+            insert_address(&mut db, address, id).await.unwrap();
+        }
+
+        assert_eq!(id, device_id);
+        let addresses = select_device_addresses(&mut db, device_id).await.unwrap();
+        let addresses = addresses
+            .iter()
+            .map(|address| address.address.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(addresses, ["10.0.0.2", "10.0.0.3"]);
+    }
+
+    #[rocket::async_test]
+    async fn moved_devices_are_found_by_fullname() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        insert_address(&mut db, "10.0.0.1".into(), device_id)
+            .await
+            .unwrap();
+
+        let advertised = ["10.0.0.2".into()];
+        let id = upsert_device(&mut db, "light", 8080, "https", "/", &advertised)
+            .await
+            .unwrap();
+
+        assert_eq!(id, device_id);
+        let metadata = select_device_by_fullname(&mut db, "light")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.id, device_id);
+        assert_eq!(metadata.port, 8080);
+        assert_eq!(metadata.scheme, "https");
+        assert!(select_device_addresses(&mut db, device_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(select_device_by_fullname(&mut db, "fridge")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[rocket::async_test]
+    async fn discovered_devices_not_seen_are_stale() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        insert_manual_device(&mut db, 3000, "http", "/")
+            .await
+            .unwrap();
+
+        let metadata = select_device_metadata_by_id(&mut db, device_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let last_seen = metadata.last_seen.unwrap();
+
+        assert!(select_stale_devices(&mut db, last_seen)
+            .await
+            .unwrap()
+            .is_empty());
+        let stale = select_stale_devices(&mut db, last_seen + 1).await.unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].id, device_id);
+    }
+
+    #[rocket::async_test]
+    async fn only_discovered_devices_have_a_fullname() {
+        let (_client, mut db) = test_connection().await;
+        let (discovered_id, _) = device_with_route(&mut db, "light").await;
+        let manual_id = insert_manual_device(&mut db, 3000, "http", "/")
+            .await
+            .unwrap();
+
+        let fullname = select_device_fullname(&mut db, discovered_id).await;
+        assert_eq!(fullname.unwrap().as_deref(), Some("light"));
+        assert_eq!(
+            select_device_fullname(&mut db, manual_id).await.unwrap(),
+            None
+        );
+    }
+
+    #[rocket::async_test]
+    async fn databases_are_empty_until_a_device_is_stored() {
+        let (_client, mut db) = test_connection().await;
+        assert!(is_db_empty(&mut db).await.unwrap());
+
+        device_with_route(&mut db, "light").await;
+
+        assert!(!is_db_empty(&mut db).await.unwrap());
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_counted() {
+        let (_client, mut db) = test_connection().await;
+        assert_eq!(count_devices(&mut db).await.unwrap(), 0);
+
+        for name in ["light", "fridge", "oven"] {
+            device_with_route(&mut db, name).await;
+        }
+
+        assert_eq!(count_devices(&mut db).await.unwrap(), 3);
+    }
+
+    #[rocket::async_test]
+    async fn clearing_removes_every_device() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        device_with_route(&mut db, "fridge").await;
+
+        clear_database(&mut db).await.unwrap();
+
+        assert!(is_db_empty(&mut db).await.unwrap());
+        assert!(select_device_routes_by_id(&mut db, device_id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Identifiers restart from the first one.
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        assert_eq!(device_id, 1);
+    }
+
+    #[rocket::async_test]
+    async fn foreign_keys_are_enforced() {
+        let (_client, mut db) = test_connection().await;
+
+        let enabled: bool = sqlx::query_scalar("PRAGMA foreign_keys")
+            .fetch_one(&mut ***db)
+            .await
+            .unwrap();
+        assert!(enabled);
+    }
+
+    #[rocket::async_test]
+    async fn rows_of_unknown_devices_are_rejected() {
+        let (_client, mut db) = test_connection().await;
+
+        assert!(
+            insert_route(&mut db, "/on", RouteMethod::Put, None, false, 9999)
+                .await
+                .is_err()
+        );
+        assert!(insert_address(&mut db, "10.0.0.1".into(), 9999)
+            .await
+            .is_err());
+    }
+
+    #[rocket::async_test]
+    async fn deleting_a_device_removes_its_data() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, route_id) = device_with_route(&mut db, "light").await;
+        insert_address(&mut db, "10.0.0.1".into(), device_id)
+            .await
+            .unwrap();
+        insert_property(&mut db, "scheme", "http", device_id)
+            .await
+            .unwrap();
+        insert_main_route(&mut db, "/light", device_id)
+            .await
+            .unwrap();
+        insert_hazard(&mut db, 0, "Safety", route_id, device_id)
+            .await
+            .unwrap();
+        insert_boolean_input(&mut db, "on", false, false, route_id)
+            .await
+            .unwrap();
+        insert_text_input(&mut db, "label", "", "", route_id)
+            .await
+            .unwrap();
+
+        assert!(delete_device(&mut db, device_id).await.unwrap());
+
+        for table in [
+            "addresses",
+            "properties",
+            "main_routes",
+            "routes",
+            "hazards",
+            "booleans",
+            "texts",
+        ] {
+            let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+                .fetch_one(&mut ***db)
+                .await
+                .unwrap();
+            assert_eq!(count, 0, "{}", table);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn scene_items_follow_their_devices() {
+        let (_client, mut db) = test_connection().await;
+        let (light_id, _) = device_with_route(&mut db, "light").await;
+        let (fan_id, _) = device_with_route(&mut db, "fan").await;
+
+        let item = |device_id| BatchItem {
+            device_id,
+            route: "/on".into(),
+            value: Some(true.into()),
+            confirm: false,
+        };
+        let scene_id = insert_scene(&mut db, "Night", &[item(light_id), item(fan_id)])
+            .await
+            .unwrap();
+
+        let items = select_scene_items(&mut db, scene_id).await.unwrap();
+        let items = items
+            .into_iter()
+            .map(SceneItem::batch_item)
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].device_id, light_id);
+        assert_eq!(items[0].value, Some(true.into()));
+
+        // Deleted devices leave their scenes.
+        delete_device(&mut db, light_id).await.unwrap();
+        let items = select_scene_items(&mut db, scene_id).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert!(select_scene(&mut db, scene_id).await.unwrap().is_some());
+    }
+
+    #[rocket::async_test]
+    async fn scenes_with_unknown_devices_are_not_stored() {
+        let (_client, mut db) = test_connection().await;
+
+        let item = BatchItem {
+            device_id: 9999,
+            route: "/on".into(),
+            value: None,
+            confirm: false,
+        };
+        assert!(insert_scene(&mut db, "Night", &[item]).await.is_err());
+        assert!(select_scenes(&mut db).await.unwrap().is_empty());
+    }
+
+    #[rocket::async_test]
+    async fn unknown_devices_are_not_found() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, route_id) = device_with_route(&mut db, "light").await;
+
+        let metadata = select_device_metadata_by_id(&mut db, device_id).await;
+        assert_eq!(
+            metadata.unwrap().map(|metadata| metadata.id),
+            Some(device_id)
+        );
+        let routes = select_device_routes_by_id(&mut db, device_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            routes.iter().map(|route| route.id).collect::<Vec<_>>(),
+            [route_id]
+        );
+
+        let unknown_id = device_id + 1;
+        assert!(select_device_metadata_by_id(&mut db, unknown_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(select_device_routes_by_id(&mut db, unknown_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[rocket::async_test]
+    async fn failed_retrievals_back_off() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+
+        for (failures, next_retry) in [(1, 1030), (2, 1060), (3, 1120)] {
+            assert_eq!(
+                record_retrieval_failure(&mut db, device_id, 1000, 30)
+                    .await
+                    .unwrap(),
+                failures
+            );
+            let metadata = select_device_metadata_by_id(&mut db, device_id).await;
+            assert_eq!(metadata.unwrap().unwrap().next_retry, Some(next_retry));
+        }
+    }
+
+    #[rocket::async_test]
+    async fn retrievals_forget_failures() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        record_retrieval_failure(&mut db, device_id, 1000, 30)
+            .await
+            .unwrap();
+
+        update_last_retrieved(&mut db, device_id, 1010)
+            .await
+            .unwrap();
+
+        let metadata = select_device_metadata_by_id(&mut db, device_id).await;
+        assert_eq!(metadata.unwrap().unwrap().next_retry, None);
+        assert_eq!(
+            record_retrieval_failure(&mut db, device_id, 1020, 30)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[rocket::async_test]
+    async fn names_survive_a_merge() {
+        let (_client, mut db) = test_connection().await;
+        let (device_id, _) = device_with_route(&mut db, "light").await;
+        insert_address(&mut db, "10.0.0.1".into(), device_id)
+            .await
+            .unwrap();
+
+        assert!(rename_device(&mut db, device_id, "Kitchen").await.unwrap());
+        let id = upsert_device(&mut db, "light", 3000, "http", "/", &["10.0.0.1".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(id, device_id);
+        let metadata = select_device_metadata_by_id(&mut db, device_id).await;
+        assert_eq!(metadata.unwrap().unwrap().name.as_deref(), Some("Kitchen"));
+        assert!(!rename_device(&mut db, device_id + 1, "Kitchen")
+            .await
+            .unwrap());
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_filtered_by_hazard() {
+        let (_client, mut db) = test_connection().await;
+        let (light_id, light_route) = device_with_route(&mut db, "light").await;
+        let (fridge_id, fridge_route) = device_with_route(&mut db, "fridge").await;
+        device_with_route(&mut db, "lamp").await;
+        insert_hazard(&mut db, 0, "Safety", light_route, light_id)
+            .await
+            .unwrap();
+        insert_hazard(&mut db, 1, "Safety", light_route, light_id)
+            .await
+            .unwrap();
+        insert_hazard(&mut db, 0, "Safety", fridge_route, fridge_id)
+            .await
+            .unwrap();
+
+        let filter = |hazard| DeviceFilter {
+            hazard: Some(hazard),
+            text: None,
+        };
+        assert_eq!(
+            filtered_ids(&mut db, &filter(0)).await,
+            [light_id, fridge_id]
+        );
+        assert!(filtered_ids(&mut db, &filter(2)).await.is_empty());
+        assert_eq!(
+            count_listed_devices(&mut db, &filter(0), 0).await.unwrap(),
+            2
+        );
+
+        let stored = select_stored_hazards(&mut db).await.unwrap();
+        let stored = stored.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(stored, [0, 1]);
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_searched_by_name_and_path() {
+        let (_client, mut db) = test_connection().await;
+        let mut ids = Vec::new();
+        for name in ["Kitchen light", "Kitchen_lamp", "Bedroom 100%"] {
+            let id = insert_device(&mut db, name, 3000, "http", "/")
+                .await
+                .unwrap();
+            rename_device(&mut db, id, name).await.unwrap();
+            ids.push(id);
+        }
+        let fan = insert_device(&mut db, "fan", 3000, "http", "/ascot/fan")
+            .await
+            .unwrap();
+
+        async fn search(db: &mut Connection<Devices>, pattern: &str) -> Vec<u16> {
+            let filter = DeviceFilter {
+                hazard: None,
+                text: Some(pattern.into()),
+            };
+            filtered_ids(db, &filter).await
+        }
+        assert_eq!(search(&mut db, "itch").await, ids[..2]);
+        assert_eq!(search(&mut db, "fan").await, [fan]);
+        // Wildcards are matched literally.
+        assert_eq!(search(&mut db, "n_l").await, [ids[1]]);
+        assert_eq!(search(&mut db, "%").await, [ids[2]]);
+    }
+
+    // Return the identifiers of the devices matching a filter.
+    async fn filtered_ids(db: &mut Connection<Devices>, filter: &DeviceFilter) -> Vec<u16> {
+        select_device_metadata(db, filter, 0, Sort::Id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|metadata| metadata.id)
+            .collect()
+    }
+
+    // Return the identifiers of every device, in the given order.
+    async fn sorted_ids(db: &mut Connection<Devices>, sort: Sort) -> Vec<u16> {
+        select_device_metadata(db, &DeviceFilter::default(), 0, sort)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|metadata| metadata.id)
+            .collect()
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_sorted_by_id() {
+        let (_client, mut db) = test_connection().await;
+        let mut ids = Vec::new();
+        for fullname in ["c", "a", "b"] {
+            ids.push(device_with_route(&mut db, fullname).await.0);
+        }
+
+        assert_eq!(sorted_ids(&mut db, Sort::Id).await, ids);
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_sorted_by_kind() {
+        let (_client, mut db) = test_connection().await;
+        let mut ids = Vec::new();
+        for fullname in ["unknown", "light", "fridge", "lamp"] {
+            ids.push(device_with_route(&mut db, fullname).await.0);
+        }
+        for (id, kind) in [(ids[1], "Light"), (ids[2], "Fridge"), (ids[3], "Light")] {
+            update_device_kind(&mut db, id, kind).await.unwrap();
+        }
+
+        assert_eq!(
+            sorted_ids(&mut db, Sort::Kind).await,
+            [ids[2], ids[1], ids[3], ids[0]]
+        );
+        // Kinds are sorted across pages.
+        let page = select_device_metadata_paginated(
+            &mut db,
+            &DeviceFilter::default(),
+            0,
+            1,
+            0,
+            Sort::Kind,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page[0].id, ids[2]);
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_sorted_by_name() {
+        let (_client, mut db) = test_connection().await;
+        let unnamed = device_with_route(&mut db, "unnamed").await.0;
+        let mut named = Vec::new();
+        for name in ["lamp", "Fridge"] {
+            let id = device_with_route(&mut db, name).await.0;
+            rename_device(&mut db, id, name).await.unwrap();
+            named.push(id);
+        }
+
+        assert_eq!(
+            sorted_ids(&mut db, Sort::Name).await,
+            [named[1], named[0], unnamed]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_sorted_by_reachability() {
+        let (_client, mut db) = test_connection().await;
+        let mut ids = Vec::new();
+        for fullname in ["offline", "online"] {
+            let id = device_with_route(&mut db, fullname).await.0;
+            insert_address(&mut db, "192.168.1.2".into(), id)
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        update_address_reachable(&mut db, "192.168.1.2", false, ids[0])
+            .await
+            .unwrap();
+        update_address_reachable(&mut db, "192.168.1.2", true, ids[1])
+            .await
+            .unwrap();
+
+        assert_eq!(sorted_ids(&mut db, Sort::Reachable).await, [ids[1], ids[0]]);
+    }
+
+    #[rocket::async_test]
+    async fn pages_hold_a_slice_of_devices() {
+        let (_client, mut db) = test_connection().await;
+        let mut ids = Vec::new();
+        for fullname in ["a", "b", "c", "d", "e"] {
+            ids.push(device_with_route(&mut db, fullname).await.0);
+        }
+
+        let filter = DeviceFilter::default();
+        let page = select_device_metadata_paginated(&mut db, &filter, 0, 2, 2, Sort::Id)
+            .await
+            .unwrap();
+        let page = page.iter().map(|metadata| metadata.id).collect::<Vec<_>>();
+        assert_eq!(page, ids[2..4]);
+    }
+
+    #[rocket::async_test]
+    async fn devices_waiting_for_a_retry_are_not_listed() {
+        let (_client, mut db) = test_connection().await;
+        let (waiting, _) = device_with_route(&mut db, "waiting").await;
+        let (ready, _) = device_with_route(&mut db, "ready").await;
+        record_retrieval_failure(&mut db, waiting, 1000, 30)
+            .await
+            .unwrap();
 
-    Ok(hazards_id.into_iter().map(|hazard| hazard.0).collect())
+        let filter = DeviceFilter::default();
+        assert_eq!(filtered_ids(&mut db, &filter).await, [ready]);
+        assert_eq!(
+            count_listed_devices(&mut db, &filter, 1000).await.unwrap(),
+            1
+        );
+        // Devices are listed again once their retry is due.
+        assert_eq!(
+            count_listed_devices(&mut db, &filter, 1030).await.unwrap(),
+            2
+        );
+    }
 }
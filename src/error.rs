@@ -4,64 +4,212 @@ use rocket::Request;
 
 use rocket_dyn_templates::{context, Template};
 
+use rocket_db_pools::sqlx;
+
+use crate::request::RequestError;
+
 // Go to devices message.
 const GO_TO_DEVICES_MESSAGE: &str = "Go to devices";
+// Go to login message.
+const GO_TO_LOGIN_MESSAGE: &str = "Log in";
 // Unknown error.
 const UNKNOWN_ERROR_MESSAGE: &str = "Unknown";
 
 struct RenderTemplate;
 
 impl RenderTemplate {
-    fn text(uri: &Origin<'_>, status: u16, error_message: &str) -> Template {
-        Self::render(uri, "/", status, error_message)
+    fn text(uri: &Origin<'_>, status: u16, category: &str, error_message: &str) -> Template {
+        Self::render(
+            uri,
+            "/",
+            status,
+            category,
+            error_message,
+            GO_TO_DEVICES_MESSAGE,
+        )
     }
 
-    fn render(uri: &Origin<'_>, route: &str, status: u16, error_message: &str) -> Template {
+    fn render(
+        uri: &Origin<'_>,
+        route: &str,
+        status: u16,
+        category: &str,
+        error_message: &str,
+        goto_message: &str,
+    ) -> Template {
         Template::render(
             "error",
             context! {
                 route,
                 uri,
                 status,
+                category,
                 error_message,
-                goto_message: GO_TO_DEVICES_MESSAGE,
+                goto_message,
             },
         )
     }
 }
 
 #[derive(Responder)]
-#[response(status = 500, content_type = "html")]
-pub(crate) struct InternalError(Template);
+pub(crate) enum GatewayError {
+    // A database query has failed.
+    #[response(status = 500, content_type = "html")]
+    Database(Template),
+    // Devices cannot be discovered in the network.
+    #[response(status = 500, content_type = "html")]
+    Discovery(Template),
+    // The mDNS daemon cannot be used, not even after being recreated.
+    #[response(status = 503, content_type = "html")]
+    DiscoveryUnavailable(Template),
+    // A device has not answered.
+    #[response(status = 502, content_type = "html")]
+    DeviceUnreachable(Template),
+    // A device has answered with an error status.
+    #[response(status = 502, content_type = "html")]
+    DeviceRejected(Template),
+    // A device has refused the gateway credentials.
+    #[response(status = 502, content_type = "html")]
+    DeviceUnauthorized(Template),
+    // The request input is malformed.
+    #[response(status = 400, content_type = "html")]
+    BadInput(Template),
+    // The request is not allowed.
+    #[response(status = 403, content_type = "html")]
+    Forbidden(Template),
+    // A device has received too many requests.
+    #[response(status = 429, content_type = "html")]
+    TooManyRequests(Template),
+    // The requested resource does not exist.
+    #[response(status = 404, content_type = "html")]
+    NotFound(Template),
+}
 
-impl InternalError {
-    // Render a text containing an internal error
-    pub(crate) fn text(uri: &Origin<'_>, error_message: &str) -> Self {
-        Self(RenderTemplate::text(uri, 500, error_message))
+impl GatewayError {
+    // Render a text reporting a failed database query
+    pub(crate) fn database(uri: &Origin<'_>, error_message: &str) -> Self {
+        Self::Database(RenderTemplate::text(uri, 500, "Database", error_message))
+    }
+
+    // Render a text reporting a failed devices discovery
+    pub(crate) fn discovery(uri: &Origin<'_>, error_message: &str) -> Self {
+        Self::Discovery(RenderTemplate::text(uri, 500, "Discovery", error_message))
+    }
+
+    // Render a text reporting an mDNS daemon which cannot be used
+    pub(crate) fn discovery_unavailable(uri: &Origin<'_>, error_message: &str) -> Self {
+        Self::DiscoveryUnavailable(RenderTemplate::text(
+            uri,
+            503,
+            "Discovery unavailable",
+            error_message,
+        ))
+    }
+
+    // Render a text reporting a device which has not answered
+    pub(crate) fn device_unreachable(uri: &Origin<'_>) -> Self {
+        Self::DeviceUnreachable(RenderTemplate::text(
+            uri,
+            502,
+            "Device unreachable",
+            "The device has not answered",
+        ))
+    }
+
+    // Render a text reporting a device which has refused a request
+    pub(crate) fn device_rejected(uri: &Origin<'_>, status: u16) -> Self {
+        Self::DeviceRejected(RenderTemplate::text(
+            uri,
+            502,
+            "Device error",
+            &format!("The device has answered with status {}", status),
+        ))
+    }
+
+    // Render a text reporting a device which has refused the credentials
+    pub(crate) fn device_unauthorized(uri: &Origin<'_>) -> Self {
+        Self::DeviceUnauthorized(RenderTemplate::text(
+            uri,
+            502,
+            "Device unauthorized",
+            "The device has refused the gateway credentials",
+        ))
+    }
+
+    // Render a text reporting a failed request to a device
+    pub(crate) fn device_request(uri: &Origin<'_>, error: RequestError) -> Self {
+        match error {
+            RequestError::Unreachable => Self::device_unreachable(uri),
+            RequestError::Unauthorized => Self::device_unauthorized(uri),
+            RequestError::Status(status) => Self::device_rejected(uri, status.as_u16()),
+        }
+    }
+
+    // Render a text explaining why a request input is malformed
+    pub(crate) fn bad_input(uri: &Origin<'_>, error_message: &str) -> Self {
+        Self::BadInput(RenderTemplate::text(uri, 400, "Bad input", error_message))
+    }
+
+    // Render a text reporting an input value not accepted by the route
+    pub(crate) fn input_not_allowed(uri: &Origin<'_>, input_name: &str) -> Self {
+        Self::bad_input(
+            uri,
+            &format!("Value of input {} is not allowed", input_name),
+        )
+    }
+
+    // Render a text explaining why a request is not allowed
+    pub(crate) fn forbidden(uri: &Origin<'_>, error_message: &str) -> Self {
+        Self::Forbidden(RenderTemplate::text(uri, 403, "Forbidden", error_message))
+    }
+
+    // Render a text reporting a device which has received too many requests
+    pub(crate) fn too_many_requests(uri: &Origin<'_>) -> Self {
+        Self::TooManyRequests(RenderTemplate::text(
+            uri,
+            429,
+            "Too many requests",
+            "The device has received too many requests, retry later",
+        ))
+    }
+
+    // Render a text reporting a missing resource
+    pub(crate) fn not_found(uri: &Origin<'_>, error_message: &str) -> Self {
+        Self::NotFound(RenderTemplate::text(uri, 404, "Not found", error_message))
     }
 }
 
 #[inline(always)]
-pub(crate) async fn query_error<T, K: ToString>(
-    function: impl std::future::Future<Output = Result<T, K>>,
+pub(crate) async fn query_error<T>(
+    function: impl std::future::Future<Output = Result<T, sqlx::Error>>,
     uri: &Origin<'_>,
-) -> Result<T, InternalError> {
+) -> Result<T, GatewayError> {
     function
         .await
-        .map_err(|e| InternalError::text(uri, &e.to_string()))
+        .map_err(|e| GatewayError::database(uri, &e.to_string()))
+}
+
+// Renders the template for requests which need a logged in user
+#[catch(401)]
+pub(crate) fn unauthorized(req: &Request<'_>) -> Template {
+    RenderTemplate::render(
+        req.uri(),
+        "/login",
+        401,
+        "Unauthorized",
+        "Log in to use the gateway",
+        GO_TO_LOGIN_MESSAGE,
+    )
 }
 
 // Renders the template for any other kind of catchers
 #[catch(default)]
 pub(crate) fn default(status: Status, req: &Request<'_>) -> Template {
-    RenderTemplate::text(
-        req.uri(),
-        status.code,
-        status.reason().unwrap_or(UNKNOWN_ERROR_MESSAGE),
-    )
+    let reason = status.reason().unwrap_or(UNKNOWN_ERROR_MESSAGE);
+    RenderTemplate::text(req.uri(), status.code, reason, reason)
 }
 
 // Returns all defined catchers
 pub(crate) fn catchers() -> Vec<rocket::Catcher> {
-    catchers![default]
+    catchers![unauthorized, default]
 }
@@ -0,0 +1,72 @@
+use rocket::tokio::sync::broadcast::{self, Receiver, Sender};
+
+use serde::Serialize;
+
+// Changes kept for subscribers which are slow to receive them.
+const CAPACITY: usize = 64;
+
+// Change of a stored value of a device route input.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct StateChange {
+    // Device identifier.
+    pub(crate) device_id: u16,
+    // Route identifier.
+    pub(crate) route_id: u16,
+    // Input name, or the route name for the state of a stateful route.
+    pub(crate) name: String,
+    // New value, as stored.
+    pub(crate) value: String,
+}
+
+// Broadcaster of the changes of stored values, so that open pages can be
+// updated without being reloaded.
+//
+// Clones share the same subscribers.
+#[derive(Debug, Clone)]
+pub(crate) struct StateEvents(Sender<StateChange>);
+
+impl Default for StateEvents {
+    fn default() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl StateEvents {
+    // Sends a change to the current subscribers.
+    //
+    // Changes happening while nobody is subscribed are dropped.
+    pub(crate) fn publish(&self, change: StateChange) {
+        let _ = self.0.send(change);
+    }
+
+    // Subscribes to the changes happening from now on.
+    pub(crate) fn subscribe(&self) -> Receiver<StateChange> {
+        self.0.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(value: &str) -> StateChange {
+        StateChange {
+            device_id: 1,
+            route_id: 2,
+            name: "on".into(),
+            value: value.into(),
+        }
+    }
+
+    #[test]
+    fn subscribers_receive_later_changes() {
+        let events = StateEvents::default();
+        events.publish(change("false"));
+
+        let mut receiver = events.clone().subscribe();
+        events.publish(change("true"));
+
+        assert_eq!(receiver.try_recv().unwrap(), change("true"));
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -4,23 +4,42 @@ use serde::Serialize;
 pub(crate) struct Button {
     route_id: u16,
     name: String,
+    // Route description, shown as a tooltip.
+    description: Option<String>,
     with_state: bool,
+    // Current state of the route, when stateful.
+    state: bool,
+    restricted: bool,
+    hazardous: bool,
 }
 
 impl Button {
-    pub(crate) fn init(route_id: u16, name: String) -> Self {
+    pub(crate) fn init(route_id: u16, name: String, description: Option<String>) -> Self {
         Self {
             route_id,
             name,
+            description,
             with_state: false,
+            state: false,
+            restricted: false,
+            hazardous: false,
         }
     }
 
-    pub(crate) fn with_state(route_id: u16, name: String) -> Self {
+    pub(crate) fn with_state(
+        route_id: u16,
+        name: String,
+        description: Option<String>,
+        state: bool,
+    ) -> Self {
         Self {
             route_id,
             name,
+            description,
             with_state: true,
+            state,
+            restricted: false,
+            hazardous: false,
         }
     }
 }
@@ -33,6 +52,8 @@ pub(crate) struct Slider<T> {
     max: T,
     step: T,
     value: T,
+    restricted: bool,
+    hazardous: bool,
 }
 
 impl<T> Slider<T> {
@@ -44,6 +65,8 @@ impl<T> Slider<T> {
             max,
             step,
             value,
+            restricted: false,
+            hazardous: false,
         }
     }
 }
@@ -53,6 +76,8 @@ pub(crate) struct CheckBox {
     route_id: u16,
     name: String,
     value: bool,
+    restricted: bool,
+    hazardous: bool,
 }
 
 impl CheckBox {
@@ -61,6 +86,8 @@ impl CheckBox {
             route_id,
             name,
             value: false,
+            restricted: false,
+            hazardous: false,
         }
     }
 
@@ -69,6 +96,184 @@ impl CheckBox {
             route_id,
             name,
             value: true,
+            restricted: false,
+            hazardous: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TextField {
+    route_id: u16,
+    name: String,
+    value: String,
+    restricted: bool,
+    hazardous: bool,
+}
+
+impl TextField {
+    pub(crate) fn new(route_id: u16, name: String, value: String) -> Self {
+        Self {
+            route_id,
+            name,
+            value,
+            restricted: false,
+            hazardous: false,
         }
     }
 }
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EnumInput {
+    route_id: u16,
+    name: String,
+    options: Vec<String>,
+    value: String,
+    restricted: bool,
+    hazardous: bool,
+}
+
+impl EnumInput {
+    pub(crate) fn new(route_id: u16, name: String, options: Vec<String>, value: String) -> Self {
+        Self {
+            route_id,
+            name,
+            options,
+            value,
+            restricted: false,
+            hazardous: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ColorInput {
+    route_id: u16,
+    name: String,
+    value: String,
+    restricted: bool,
+    hazardous: bool,
+}
+
+impl ColorInput {
+    pub(crate) fn new(route_id: u16, name: String, value: String) -> Self {
+        Self {
+            route_id,
+            name,
+            value,
+            restricted: false,
+            hazardous: false,
+        }
+    }
+}
+
+// A control which can be restricted.
+pub(crate) trait Restrict {
+    // Returns the identifier of the route associated with the control.
+    fn route_id(&self) -> u16;
+
+    // Prevents the control from being used.
+    fn restrict(&mut self);
+}
+
+impl Restrict for Button {
+    fn route_id(&self) -> u16 {
+        self.route_id
+    }
+
+    fn restrict(&mut self) {
+        self.restricted = true;
+    }
+}
+
+impl<T> Restrict for Slider<T> {
+    fn route_id(&self) -> u16 {
+        self.route_id
+    }
+
+    fn restrict(&mut self) {
+        self.restricted = true;
+    }
+}
+
+impl Restrict for CheckBox {
+    fn route_id(&self) -> u16 {
+        self.route_id
+    }
+
+    fn restrict(&mut self) {
+        self.restricted = true;
+    }
+}
+
+impl Restrict for TextField {
+    fn route_id(&self) -> u16 {
+        self.route_id
+    }
+
+    fn restrict(&mut self) {
+        self.restricted = true;
+    }
+}
+
+impl Restrict for EnumInput {
+    fn route_id(&self) -> u16 {
+        self.route_id
+    }
+
+    fn restrict(&mut self) {
+        self.restricted = true;
+    }
+}
+
+impl Restrict for ColorInput {
+    fn route_id(&self) -> u16 {
+        self.route_id
+    }
+
+    fn restrict(&mut self) {
+        self.restricted = true;
+    }
+}
+
+// A control whose route presents hazards.
+pub(crate) trait MarkHazardous: Restrict {
+    // Asks for a confirmation before the control is used.
+    fn mark_hazardous(&mut self);
+}
+
+impl MarkHazardous for Button {
+    fn mark_hazardous(&mut self) {
+        self.hazardous = true;
+    }
+}
+
+impl<T> MarkHazardous for Slider<T> {
+    fn mark_hazardous(&mut self) {
+        self.hazardous = true;
+    }
+}
+
+impl MarkHazardous for CheckBox {
+    fn mark_hazardous(&mut self) {
+        self.hazardous = true;
+    }
+}
+
+impl MarkHazardous for TextField {
+    fn mark_hazardous(&mut self) {
+        self.hazardous = true;
+    }
+}
+
+impl MarkHazardous for EnumInput {
+    fn mark_hazardous(&mut self) {
+        self.hazardous = true;
+    }
+}
+
+impl MarkHazardous for ColorInput {
+    fn mark_hazardous(&mut self) {
+        self.hazardous = true;
+    }
+}
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
+use std::net::IpAddr;
 
-use rocket::form::FromForm;
+use rocket::form::{FromForm, FromFormField};
+use rocket::serde::json::Value;
+
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, FromForm)]
 struct Data<T> {
@@ -17,6 +21,309 @@ pub(crate) struct DeviceData<'r> {
     pub(crate) sliders_f64: HashMap<&'r str, Data<f64>>,
     #[field(name = "checkboxes")]
     pub(crate) checkboxes: HashMap<&'r str, Data<bool>>,
+    #[field(name = "texts")]
+    pub(crate) texts: HashMap<&'r str, Data<String>>,
+    #[field(name = "selects")]
+    pub(crate) selects: HashMap<&'r str, Data<String>>,
+    #[field(name = "colors")]
+    pub(crate) colors: HashMap<&'r str, Data<String>>,
     #[field(name = "buttons")]
     pub(crate) buttons: HashMap<&'r str, Data<bool>>,
+    // Whether the hazards of the invoked routes have been confirmed.
+    pub(crate) confirm: bool,
+}
+
+impl<'r> DeviceData<'r> {
+    // Returns the form inputs sorted by route identifier and name.
+    //
+    // Form inputs are stored into maps, hence their iteration order is not
+    // deterministic.
+    pub(crate) fn sorted_inputs(&self) -> Vec<FormInput<'r>> {
+        let mut inputs = Vec::new();
+        inputs.extend(
+            self.sliders_u64
+                .iter()
+                .map(|(&name, data)| FormInput::new(name, data, InputValue::SliderU64)),
+        );
+        inputs.extend(
+            self.sliders_f64
+                .iter()
+                .map(|(&name, data)| FormInput::new(name, data, InputValue::SliderF64)),
+        );
+        inputs.extend(
+            self.checkboxes
+                .iter()
+                .map(|(&name, data)| FormInput::new(name, data, InputValue::CheckBox)),
+        );
+        inputs.extend(
+            self.texts
+                .iter()
+                .map(|(&name, data)| FormInput::new(name, data, InputValue::Text)),
+        );
+        inputs.extend(
+            self.selects
+                .iter()
+                .map(|(&name, data)| FormInput::new(name, data, InputValue::Select)),
+        );
+        inputs.extend(
+            self.colors
+                .iter()
+                .map(|(&name, data)| FormInput::new(name, data, InputValue::Color)),
+        );
+        inputs.extend(
+            self.buttons
+                .iter()
+                .map(|(&name, data)| FormInput::new(name, data, InputValue::Button)),
+        );
+
+        inputs.sort_by(|a, b| (a.route_id, a.name).cmp(&(b.route_id, b.name)));
+        inputs
+    }
+}
+
+// Value of a form input.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum InputValue {
+    SliderU64(u64),
+    SliderF64(f64),
+    CheckBox(bool),
+    Text(String),
+    Select(String),
+    Color(String),
+    Button(bool),
+}
+
+impl InputValue {
+    // Returns the textual representation of the value sent to a device.
+    //
+    // Buttons do not send any value.
+    pub(crate) fn text(&self) -> Option<String> {
+        match self {
+            Self::SliderU64(value) => Some(value.to_string()),
+            Self::SliderF64(value) => Some(value.to_string()),
+            Self::CheckBox(value) => Some(value.to_string()),
+            Self::Text(value) | Self::Select(value) | Self::Color(value) => Some(value.clone()),
+            Self::Button(_) => None,
+        }
+    }
+
+    // Checks whether the value is well-formed.
+    //
+    // Colors must be expressed as `#RRGGBB`. Texts cannot be `.` or `..`,
+    // since URLs resolve those path segments even when percent-encoded.
+    pub(crate) fn is_valid(&self) -> bool {
+        match self {
+            Self::Color(value) => {
+                value.len() == 7
+                    && value.starts_with('#')
+                    && value[1..].chars().all(|c| c.is_ascii_hexdigit())
+            }
+            Self::Text(value) => value != "." && value != "..",
+            _ => true,
+        }
+    }
+}
+
+// A form input.
+#[derive(Debug)]
+pub(crate) struct FormInput<'r> {
+    pub(crate) route_id: u16,
+    pub(crate) name: &'r str,
+    pub(crate) value: InputValue,
+}
+
+impl<'r> FormInput<'r> {
+    fn new<T: Clone>(name: &'r str, data: &Data<T>, value: impl Fn(T) -> InputValue) -> Self {
+        Self {
+            route_id: data.route_id,
+            name,
+            value: value(data.val.clone()),
+        }
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub(crate) struct ManualDevice<'r> {
+    pub(crate) address: IpAddr,
+    pub(crate) port: u16,
+    pub(crate) scheme: Option<&'r str>,
+    pub(crate) path: Option<&'r str>,
+}
+
+#[derive(Debug, FromForm)]
+pub(crate) struct DeviceName<'r> {
+    #[field(validate = len(1..))]
+    pub(crate) name: &'r str,
+}
+
+// Control change of a batch of device requests.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BatchItem {
+    // Device identifier.
+    pub(crate) device_id: u16,
+    // Device route, such as `/on`.
+    pub(crate) route: String,
+    // New value of the only route input, or an object with the new values
+    // of the route inputs. Without a value, the route is invoked as its
+    // button is pressed.
+    #[serde(default)]
+    pub(crate) value: Option<Value>,
+    // Whether the hazards of the route have been confirmed.
+    #[serde(default)]
+    pub(crate) confirm: bool,
+}
+
+// Scene to create, replaying its control changes when applied.
+#[derive(Debug, Deserialize)]
+pub(crate) struct NewScene {
+    // Scene name.
+    pub(crate) name: String,
+    // Control changes of the scene.
+    pub(crate) items: Vec<BatchItem>,
+}
+
+#[derive(FromForm)]
+pub(crate) struct Login<'r> {
+    pub(crate) username: &'r str,
+    pub(crate) password: &'r str,
+}
+
+#[derive(Debug, FromForm)]
+pub(crate) struct Confirmation {
+    pub(crate) confirm: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FromFormField, UriDisplayQuery)]
+pub(crate) enum DiscoveryMode {
+    // Replace all discovered devices.
+    #[field(value = "replace")]
+    Replace,
+    // Merge discovered devices with the stored ones.
+    #[field(value = "merge")]
+    Merge,
+}
+
+// Order of the devices list.
+#[derive(Debug, Default, Clone, Copy, PartialEq, FromFormField, UriDisplayQuery)]
+pub(crate) enum Sort {
+    // Insertion order.
+    #[default]
+    #[field(value = "id")]
+    Id,
+    // Name given by the user, unnamed devices last.
+    #[field(value = "name")]
+    Name,
+    // Device kind.
+    #[field(value = "kind")]
+    Kind,
+    // Reachable devices first.
+    #[field(value = "reachable")]
+    Reachable,
+}
+
+// Filters of the devices list.
+#[derive(Debug, Default)]
+pub(crate) struct DeviceFilter {
+    // Hazard presented by the devices.
+    pub(crate) hazard: Option<u16>,
+    // Text contained in the device names or paths.
+    pub(crate) text: Option<String>,
+}
+
+// A page of devices.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Page {
+    // Page number, starting from 1.
+    pub(crate) number: u32,
+    // Number of devices in a page.
+    pub(crate) size: u32,
+}
+
+impl Page {
+    // Default number of devices in a page.
+    const DEFAULT_SIZE: u32 = 20;
+    // Maximum number of devices in a page.
+    const MAX_SIZE: u32 = 100;
+
+    pub(crate) fn new(number: Option<u32>, size: Option<u32>) -> Self {
+        Self {
+            number: number.unwrap_or(1).max(1),
+            size: size.unwrap_or(Self::DEFAULT_SIZE).clamp(1, Self::MAX_SIZE),
+        }
+    }
+
+    // Number of devices preceding the page.
+    #[inline]
+    pub(crate) fn offset(&self) -> u32 {
+        (self.number - 1).saturating_mul(self.size)
+    }
+
+    // Number of pages needed to show the given number of devices.
+    #[inline]
+    pub(crate) fn count(&self, devices: u32) -> u32 {
+        devices.div_ceil(self.size).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::form::Form;
+
+    #[test]
+    fn inputs_are_sorted_by_route_and_name() {
+        let form = Form::<DeviceData<'_>>::parse(
+            "buttons[toggle].route=1&buttons[toggle].val=true\
+             &checkboxes[save].route=2&checkboxes[save].val=true\
+             &slidersu64[dimmer].route=2&slidersu64[dimmer].val=4\
+             &slidersf64[brightness].route=2&slidersf64[brightness].val=0.5",
+        )
+        .unwrap();
+
+        let inputs = form
+            .sorted_inputs()
+            .into_iter()
+            .map(|input| (input.route_id, input.name, input.value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            inputs,
+            [
+                (1, "toggle", InputValue::Button(true)),
+                (2, "brightness", InputValue::SliderF64(0.5)),
+                (2, "dimmer", InputValue::SliderU64(4)),
+                (2, "save", InputValue::CheckBox(true)),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_well_formed_values_are_valid() {
+        assert!(InputValue::Color("#00ff7F".into()).is_valid());
+        assert!(!InputValue::Color("00ff7f".into()).is_valid());
+        assert!(!InputValue::Color("#00ff7g".into()).is_valid());
+
+        assert!(InputValue::Text("...".into()).is_valid());
+        assert!(!InputValue::Text("..".into()).is_valid());
+    }
+
+    #[test]
+    fn pages_are_bounded() {
+        let page = Page::new(None, None);
+        assert_eq!((page.number, page.size), (1, Page::DEFAULT_SIZE));
+
+        let page = Page::new(Some(0), Some(1000));
+        assert_eq!((page.number, page.size), (1, Page::MAX_SIZE));
+    }
+
+    #[test]
+    fn pages_cover_every_device() {
+        let page = Page::new(Some(3), Some(10));
+
+        assert_eq!(page.offset(), 20);
+        assert_eq!(page.count(0), 1);
+        assert_eq!(page.count(20), 2);
+        assert_eq!(page.count(21), 3);
+    }
 }
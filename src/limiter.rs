@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::config::RateLimitConfig;
+
+// Requests left to a device.
+#[derive(Debug)]
+struct Bucket {
+    // Requests the device can still receive.
+    tokens: f64,
+    // Last time the requests left have been computed.
+    updated: Instant,
+}
+
+// Limiter of the requests sent to each device, so that low-powered devices
+// are not overwhelmed.
+//
+// Each device has a token bucket, holding up to a burst of requests and
+// refilled at a constant rate. Clones share the same buckets.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<u16, Bucket>>>,
+    burst: f64,
+    per_second: f64,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            burst: config.burst.into(),
+            per_second: config.per_second,
+        }
+    }
+
+    // Checks whether a device can receive a request, taking it from the
+    // device bucket.
+    pub(crate) fn allow(&self, id: u16) -> bool {
+        self.allow_at(id, Instant::now())
+    }
+
+    fn allow_at(&self, id: u16, now: Instant) -> bool {
+        let mut buckets = self.lock();
+        let bucket = buckets.entry(id).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.per_second).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // A panic while holding the lock cannot leave the buckets inconsistent,
+    // hence a poisoned lock is still used.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u16, Bucket>> {
+        self.buckets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    fn limiter(burst: u32, per_second: f64) -> RateLimiter {
+        RateLimiter::new(&RateLimitConfig { burst, per_second })
+    }
+
+    #[test]
+    fn requests_beyond_the_burst_are_refused() {
+        let limiter = limiter(2, 1.0);
+        let now = Instant::now();
+
+        assert!(limiter.allow_at(1, now));
+        assert!(limiter.allow_at(1, now));
+        assert!(!limiter.allow_at(1, now));
+        // Devices have their own bucket.
+        assert!(limiter.clone().allow_at(2, now));
+    }
+
+    #[test]
+    fn buckets_are_refilled_over_time() {
+        let limiter = limiter(2, 2.0);
+        let now = Instant::now();
+
+        assert!(limiter.allow_at(1, now));
+        assert!(limiter.allow_at(1, now));
+        assert!(!limiter.allow_at(1, now));
+
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.allow_at(1, later));
+        assert!(!limiter.allow_at(1, later));
+
+        // Buckets never hold more than the burst.
+        let much_later = later + Duration::from_secs(60);
+        assert!(limiter.allow_at(1, much_later));
+        assert!(limiter.allow_at(1, much_later));
+        assert!(!limiter.allow_at(1, much_later));
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use tracing::{error, warn};
+
+use crate::time::{self, Timezone};
+
+// Maximum number of entries kept in the buffer.
+const CAPACITY: usize = 200;
+
+// Severity of a log entry.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub(crate) enum Level {
+    Warning,
+    Error,
+}
+
+// Log entry.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Entry {
+    // UTC epoch, in seconds, of the entry.
+    pub(crate) time: i64,
+    // Severity.
+    pub(crate) level: Level,
+    // Message.
+    pub(crate) message: String,
+}
+
+// Log entry ready to be rendered.
+#[derive(Debug, Serialize)]
+pub(crate) struct FormattedEntry {
+    // Time of the entry, in the configured timezone.
+    time: Option<String>,
+    // Severity.
+    level: Level,
+    // Message.
+    message: String,
+}
+
+// Buffer of the most recent log entries, shown in the gateway to users
+// without terminal access.
+//
+// When full, the oldest entry is dropped for each new one. Clones share the
+// same entries.
+#[derive(Debug, Clone)]
+pub(crate) struct LogBuffer {
+    entries: Arc<Mutex<VecDeque<Entry>>>,
+    capacity: usize,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::with_capacity(CAPACITY)
+    }
+}
+
+impl LogBuffer {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    // Log a warning, both to the buffer and to `tracing`.
+    pub(crate) fn warn(&self, message: String) {
+        warn!("{}", message);
+        self.push(Level::Warning, message);
+    }
+
+    // Log an error, both to the buffer and to `tracing`.
+    pub(crate) fn error(&self, message: String) {
+        error!("{}", message);
+        self.push(Level::Error, message);
+    }
+
+    // Return the buffered entries, from the most recent one.
+    pub(crate) fn entries(&self) -> Vec<Entry> {
+        self.lock().iter().rev().cloned().collect()
+    }
+
+    // Return the buffered entries, from the most recent one, with their
+    // times formatted in a timezone.
+    pub(crate) fn formatted_entries(&self, timezone: &Timezone) -> Vec<FormattedEntry> {
+        self.entries()
+            .into_iter()
+            .map(|entry| FormattedEntry {
+                time: timezone.format(entry.time),
+                level: entry.level,
+                message: entry.message,
+            })
+            .collect()
+    }
+
+    fn push(&self, level: Level, message: String) {
+        let mut entries = self.lock();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(Entry {
+            time: time::now(),
+            level,
+            message,
+        });
+    }
+
+    // A panic while holding the lock cannot leave the entries inconsistent,
+    // hence a poisoned lock is still used.
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Entry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_entries_come_first() {
+        let logs = LogBuffer::default();
+        logs.warn("first".into());
+        logs.error("second".into());
+
+        let entries = logs.entries();
+        assert_eq!(entries[0].message, "second");
+        assert_eq!(entries[0].level, Level::Error);
+        assert_eq!(entries[1].message, "first");
+        assert_eq!(entries[1].level, Level::Warning);
+    }
+
+    #[test]
+    fn clones_share_their_entries() {
+        let logs = LogBuffer::default();
+        logs.clone().warn("background".into());
+
+        assert_eq!(logs.entries()[0].message, "background");
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let logs = LogBuffer::with_capacity(2);
+        for message in ["a", "b", "c"] {
+            logs.warn(message.into());
+        }
+
+        let messages = logs
+            .entries()
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["c", "b"]);
+    }
+}
@@ -1,48 +1,121 @@
 #[macro_use]
 extern crate rocket;
 
+mod actuation;
+mod auth;
+mod config;
+mod csrf;
 mod database;
 mod error;
+mod events;
 mod form;
 mod inputs;
+mod limiter;
+mod logs;
+mod metrics;
+mod request;
+mod service;
+#[cfg(feature = "demo")]
 mod test;
+#[cfg(test)]
+mod testing;
+mod text;
+mod time;
 
-use std::time::Duration;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Instant;
 
 // Ascot library
 use ascot_library::hazards::HazardsData;
 
 // Service protocol: mDNS-SD
-use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
+use mdns_sd::{Receiver, ServiceEvent, ServiceInfo, TxtProperties};
 
 // Web app
+use rocket::fairing::AdHoc;
 use rocket::form::Form;
+use rocket::futures::future::join_all;
+use rocket::futures::{SinkExt, StreamExt};
 use rocket::http::uri::Origin;
+use rocket::http::{ContentType, CookieJar, Status};
+use rocket::response::stream::{Event, EventStream};
 use rocket::response::Redirect;
-use rocket::State;
+use rocket::serde::json::{self, json, Json, Value};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::tokio::sync::Mutex;
+use rocket::{Build, Rocket, Shutdown, State};
 
 // Templates engine
 use rocket_dyn_templates::{context, Template};
 
+// Live updates of the stored values
+use rocket_ws::{Channel, Message, WebSocket};
+
 // Database
-use rocket_db_pools::Connection;
+use rocket_db_pools::sqlx::{self, Connection as _, SqliteConnection};
+use rocket_db_pools::{Connection, Database};
+
+// Device requests
+use reqwest::Response;
+
+// Serialization
+use serde::Serialize;
 
 // Tracing
 use tracing::warn;
 
+use crate::actuation::check_route;
+use crate::auth::{Authenticated, SESSION_COOKIE};
+use crate::config::{AddressFilter, DiscoveryConfig, GatewayConfig};
+use crate::csrf::CsrfToken;
 use crate::database::{
-    query::{clear_database, insert_address, insert_device, insert_property, is_db_empty},
-    Devices,
+    device::{Device, Ping},
+    dump::{export, import, Dump, DUMP_VERSION},
+    query::{
+        clear_database, clear_discovered_devices, count_devices, count_listed_devices,
+        delete_device, delete_device_by_fullname, delete_device_properties, insert_address,
+        insert_device, insert_manual_device, insert_property, insert_scene, rename_device,
+        reset_route_inputs, select_device_addresses, select_device_by_fullname,
+        select_device_fullname, select_device_hazards, select_device_metadata_by_id,
+        select_device_properties, select_device_routes_by_id, select_last_response,
+        select_main_route, select_migration_version, select_route, select_route_by_name,
+        select_route_inputs, select_scene, select_scene_by_name, select_scene_items, select_scenes,
+        select_stale_devices, select_stored_hazards, select_table_columns, select_tables,
+        update_address_latency, update_address_reachable, update_boolean_value, update_color_value,
+        update_last_response, update_rangef64_value, update_rangeu64_value, update_select_value,
+        update_text_value, upsert_device,
+    },
+    Devices, Route, SceneItem, Schema, TableSchema,
+};
+use crate::error::{query_error, GatewayError};
+use crate::events::{StateChange, StateEvents};
+use crate::inputs::{
+    BatchItem, Confirmation, DeviceData, DeviceFilter, DeviceName, DiscoveryMode, FormInput,
+    InputValue, Login, ManualDevice, NewScene, Page, Sort,
 };
-use crate::error::{query_error, InternalError};
-use crate::inputs::DeviceData;
+use crate::limiter::RateLimiter;
+use crate::logs::LogBuffer;
+use crate::metrics::Metrics;
+use crate::request::{DeviceClient, DeviceEndpoint, DeviceRequest, RequestError};
+use crate::service::ServiceState;
+use crate::text::TextLimits;
 
-// Ascot service type.
-const SERVICE_TYPE: &str = "_ascot._tcp.local.";
+// Default ascot service type.
+pub(crate) const SERVICE_TYPE: &str = "_ascot._tcp.local.";
 
 // Default scheme is `http`.
 const DEFAULT_SCHEME: &str = "http";
 
+// Scheme of devices advertising themselves as secure.
+const SECURE_SCHEME: &str = "https";
+
+// Schemes devices can be contacted through.
+const ALLOWED_SCHEMES: [&str; 2] = [DEFAULT_SCHEME, SECURE_SCHEME];
+
 // Well-known URI.
 // https://en.wikipedia.org/wiki/Well-known_URI
 //
@@ -50,125 +123,704 @@ const DEFAULT_SCHEME: &str = "http";
 // at URLs consistent well-known locations across servers.
 const WELL_KNOWN_URI: &str = "/.well-known/ascot";
 
-// Search ascot devices.
-async fn search_devices(receiver: Receiver<ServiceEvent>) -> Vec<ServiceInfo> {
-    let mut devices_info = Vec::new();
-    // Run for 1 second in search of devices and returns their information.
-    while let Ok(event) = receiver.recv_timeout(Duration::from_secs(1)) {
-        if let ServiceEvent::ServiceResolved(info) = event {
-            // Check whether there are device addresses.
-            //
-            // If no address has been found, prints a warning and continue the
-            // loop.
-            if info.get_addresses().is_empty() {
-                // TODO: We should implement a logger to show this messages
-                // directly in the gateway.
-                warn!("No device address available for {:?}", info);
-                continue;
+// Route advertised by devices streaming their logs.
+const LOGS_ROUTE: &str = "/logs";
+
+// Route advertised by devices able to identify themselves, e.g. blinking.
+const IDENTIFY_ROUTE: &str = "/identify";
+
+// Route advertised by devices able to reboot.
+const REBOOT_ROUTE: &str = "/reboot";
+
+// Device resolved during a discovery.
+struct ResolvedDevice {
+    // Device information.
+    info: ServiceInfo,
+    // Addresses collected from every record sharing the device full name.
+    addresses: Vec<IpAddr>,
+}
+
+// Devices discovery outcome.
+#[derive(Default)]
+struct Discovery {
+    // Resolved devices, one for each full name.
+    resolved: Vec<ResolvedDevice>,
+    // Full names of the devices removed from the network.
+    removed: Vec<String>,
+}
+
+impl Discovery {
+    // Add a resolved device, returning whether it is a new one.
+    //
+    // A device advertising itself on several interfaces is resolved more
+    // than once, so collapse its addresses under the same full name.
+    fn resolve(&mut self, info: ServiceInfo) -> bool {
+        match self
+            .resolved
+            .iter_mut()
+            .find(|device| device.info.get_fullname() == info.get_fullname())
+        {
+            Some(device) => {
+                for address in info.get_addresses() {
+                    if !device.addresses.contains(address) {
+                        device.addresses.push(*address);
+                    }
+                }
+                false
+            }
+            None => {
+                self.resolved.push(ResolvedDevice {
+                    addresses: info.get_addresses().iter().copied().collect(),
+                    info,
+                });
+                true
+            }
+        }
+    }
+
+    // Add an mDNS event, returning the device it has newly resolved.
+    fn record(&mut self, event: ServiceEvent, logs: &LogBuffer) -> Option<&ResolvedDevice> {
+        match event {
+            ServiceEvent::ServiceResolved(info) => {
+                // Check whether there are device addresses.
+                //
+                // If no address has been found, logs a warning and skip the
+                // device.
+                if info.get_addresses().is_empty() {
+                    logs.warn(format!(
+                        "No device address available for {}",
+                        info.get_fullname()
+                    ));
+                    return None;
+                }
+
+                if self.resolve(info) {
+                    return self.resolved.last();
+                }
             }
+            // A device has left the network.
+            ServiceEvent::ServiceRemoved(_, fullname) => self.removed.push(fullname),
+            _ => {}
+        }
+        None
+    }
+}
+
+// Receives the next mDNS event, waiting at most for the discovery timeout
+// and never beyond the discovery deadline.
+//
+// Events are awaited, so that no runtime worker is blocked while waiting.
+async fn next_event(
+    receiver: &Receiver<ServiceEvent>,
+    config: &DiscoveryConfig,
+    start: Instant,
+) -> Option<ServiceEvent> {
+    let timeout = match config.deadline() {
+        Some(deadline) => config.timeout().min(deadline.checked_sub(start.elapsed())?),
+        None => config.timeout(),
+    };
+    rocket::tokio::time::timeout(timeout, receiver.recv_async())
+        .await
+        .ok()?
+        .ok()
+}
 
-            // Save discovered devices information.
-            devices_info.push(info);
+// Search ascot devices among the events of each browsed service type.
+//
+// Full names end with their service type, hence devices of different
+// service types never share them.
+async fn search_devices(
+    receivers: Vec<Receiver<ServiceEvent>>,
+    config: &DiscoveryConfig,
+    logs: &LogBuffer,
+) -> Discovery {
+    let mut discovery = Discovery::default();
+    // Run until no device answers within the timeout or the deadline has
+    // elapsed, and return devices information.
+    //
+    // The deadline covers the whole discovery, not each service type.
+    let start = Instant::now();
+    for receiver in receivers {
+        while let Some(event) = next_event(&receiver, config, start).await {
+            discovery.record(event, logs);
         }
     }
-    devices_info
+    discovery
+}
+
+// Normalizes a path into a local absolute path.
+//
+// A missing leading slash is added. A path containing an authority
+// (`//host/x`), a scheme or a parent directory (`..`) could point the
+// gateway to a different host or resource, hence it is rejected.
+fn local_path(path: &str) -> Option<Cow<'_, str>> {
+    let is_local = !path.starts_with("//")
+        && !path.contains('\\')
+        && !path
+            .split('/')
+            .next()
+            .is_some_and(|segment| segment.contains(':'))
+        && !path.contains("://")
+        && !path.split('/').any(|segment| segment == "..");
+
+    if !is_local {
+        warn!("Invalid path {:?}, using the default one", path);
+        return None;
+    }
+
+    Some(if path.starts_with('/') {
+        Cow::Borrowed(path)
+    } else {
+        Cow::Owned(format!("/{path}"))
+    })
+}
+
+// Checks whether a scheme can be used to contact a device.
+//
+// Any other scheme, such as `file` or `javascript`, is replaced with the
+// default one.
+fn allowed_scheme(scheme: &str) -> &'static str {
+    ALLOWED_SCHEMES
+        .into_iter()
+        .find(|allowed| allowed.eq_ignore_ascii_case(scheme))
+        .unwrap_or_else(|| {
+            warn!("Invalid scheme {:?}, using the default one", scheme);
+            DEFAULT_SCHEME
+        })
+}
+
+// Internet scheme of a discovered device.
+//
+// A truthy `secure` property forces `https`, whatever the `scheme` property
+// says. If no allowed scheme has been found, use `http` as default scheme.
+fn device_scheme(properties: &TxtProperties) -> &str {
+    // A boolean attribute without a value is true.
+    let secure = properties
+        .get_property_val_str("secure")
+        .is_some_and(|secure| {
+            secure.is_empty()
+                || ["true", "1", "yes"]
+                    .iter()
+                    .any(|truthy| secure.eq_ignore_ascii_case(truthy))
+        });
+
+    if secure {
+        SECURE_SCHEME
+    } else {
+        properties
+            .get_property_val_str("scheme")
+            .map_or(DEFAULT_SCHEME, allowed_scheme)
+    }
 }
 
 // Save discovered devices into the database.
 async fn save_devices(
-    mut db: Connection<Devices>,
-    devices_info: Vec<ServiceInfo>,
-    uri: &Origin<'_>,
-) -> Result<(), InternalError> {
-    for info in devices_info {
+    db: &mut SqliteConnection,
+    devices: Vec<ResolvedDevice>,
+    mode: DiscoveryMode,
+    address_filter: AddressFilter,
+    text_limits: &TextLimits,
+    logs: &LogBuffer,
+) -> Result<(), sqlx::Error> {
+    for ResolvedDevice { info, addresses } in devices {
+        // Keep only the addresses of the allowed IP family.
+        //
+        // If no address is left, logs a warning and skip the device.
+        let addresses = addresses
+            .iter()
+            .filter(|address| address_filter.allows(address))
+            .map(|address| address.to_string())
+            .collect::<Vec<_>>();
+
+        if addresses.is_empty() {
+            logs.warn(format!(
+                "No allowed address available for {}",
+                info.get_fullname()
+            ));
+            continue;
+        }
+
         // Device properties.
         let properties = info.get_properties();
 
         // Internet scheme.
-        //
-        // If no scheme has been found, use `http` as default scheme.
-        let scheme = properties
-            .get_property_val_str("scheme")
-            .unwrap_or(DEFAULT_SCHEME);
+        let scheme = device_scheme(properties);
 
         // Resource path.
         //
-        // If no path has been found, use the well-known URI as default
+        // If no valid path has been found, use the well-known URI as default
         // path.
         let path = properties
             .get_property_val_str("path")
-            .unwrap_or(WELL_KNOWN_URI);
+            .and_then(local_path)
+            .unwrap_or(Cow::Borrowed(WELL_KNOWN_URI));
 
-        // Insert device into the database and get back its identifier
-        let id = query_error(insert_device(&mut db, info.get_port(), scheme, path), uri).await?;
+        // Insert device into the database and get back its identifier.
+        //
+        // When merging, an already stored device is refreshed instead.
+        let id = match mode {
+            DiscoveryMode::Replace => {
+                insert_device(db, info.get_fullname(), info.get_port(), scheme, &path).await?
+            }
+            DiscoveryMode::Merge => {
+                upsert_device(
+                    db,
+                    info.get_fullname(),
+                    info.get_port(),
+                    scheme,
+                    &path,
+                    &addresses,
+                )
+                .await?
+            }
+        };
 
         // Save addresses
-        for address in info.get_addresses() {
-            query_error(insert_address(&mut db, address.to_string(), id), uri).await?;
+        for address in addresses {
+            insert_address(db, address, id).await?;
         }
 
         // Save properties
-        for property in properties.iter() {
-            query_error(
-                insert_property(&mut db, property.key(), property.val_str(), id),
-                uri,
-            )
-            .await?;
-        }
+        save_properties(db, properties, id, text_limits).await?;
+    }
+    Ok(())
+}
+
+// Replace the stored properties of a device with the advertised ones.
+//
+// Properties are replaced in a single transaction, so that a failure midway
+// leaves the old properties.
+async fn replace_properties(
+    db: &mut SqliteConnection,
+    properties: &TxtProperties,
+    id: u16,
+    text_limits: &TextLimits,
+) -> Result<(), sqlx::Error> {
+    // The transaction is rolled back when dropped before being committed.
+    let mut tx = db.begin().await?;
+    delete_device_properties(&mut tx, id).await?;
+    save_properties(&mut tx, properties, id, text_limits).await?;
+    tx.commit().await
+}
+
+// Save device properties into the database, truncating overlong values.
+//
+// Keys are kept whole, since properties are looked up by them.
+async fn save_properties(
+    db: &mut SqliteConnection,
+    properties: &TxtProperties,
+    id: u16,
+    text_limits: &TextLimits,
+) -> Result<(), sqlx::Error> {
+    for property in properties.iter() {
+        insert_property(
+            db,
+            property.key(),
+            &text_limits.description(property.val_str()),
+            id,
+        )
+        .await?;
     }
     Ok(())
 }
 
+// Discover devices in the network.
+//
+// Every service type is browsed at once, so that the answers to each of
+// them are collected together.
+async fn discover(
+    state: &ServiceState,
+    config: &DiscoveryConfig,
+    logs: &LogBuffer,
+    metrics: &Metrics,
+) -> Result<Discovery, mdns_sd::Error> {
+    let mut receivers = Vec::new();
+    for service_type in config.service_types.iter() {
+        receivers.push(state.browse(service_type, logs)?);
+    }
+
+    // If a service type has been found, search devices and their metadata.
+    let discovery = search_devices(receivers, config, logs).await;
+    metrics.discovery(discovery.resolved.len());
+    Ok(discovery)
+}
+
+// Save the outcome of a discovery into the database.
+//
+// Everything is saved in a single transaction, so that a failure midway
+// leaves the stored devices untouched.
+async fn store_discovery(
+    db: &mut SqliteConnection,
+    discovery: Discovery,
+    mode: DiscoveryMode,
+    config: &GatewayConfig,
+    logs: &LogBuffer,
+) -> Result<(), sqlx::Error> {
+    // The transaction is rolled back when dropped before being committed.
+    let mut tx = db.begin().await?;
+
+    // Delete devices which have left the network.
+    for fullname in discovery.removed.iter() {
+        delete_device_by_fullname(&mut tx, fullname).await?;
+    }
+
+    // If some devices have been found, delete every old discovered device
+    // from the database and insert every discovered devices.
+    //
+    // Manually registered devices are kept. When merging, devices not seen
+    // are kept too.
+    if !discovery.resolved.is_empty() {
+        // Clear discovered devices
+        if mode == DiscoveryMode::Replace {
+            clear_discovered_devices(&mut tx).await?;
+        }
+
+        // Save devices into the database.
+        save_devices(
+            &mut tx,
+            discovery.resolved,
+            mode,
+            config.discovery.address_filter,
+            &config.text_limits,
+            logs,
+        )
+        .await?;
+    }
+
+    // Delete discovered devices which have not been seen for too long.
+    if let Some(ttl) = config.discovery.device_ttl {
+        let cutoff = time::now().saturating_sub(ttl as i64);
+        for metadata in select_stale_devices(&mut tx, cutoff).await? {
+            logs.warn(format!(
+                "Device {} not seen for {} seconds, deleting it",
+                metadata.id, ttl
+            ));
+            delete_device(&mut tx, metadata.id).await?;
+        }
+    }
+    tx.commit().await
+}
+
+// Resolve a device through its mDNS full name.
+async fn resolve_device(
+    receiver: Receiver<ServiceEvent>,
+    fullname: &str,
+    config: &DiscoveryConfig,
+) -> Option<ServiceInfo> {
+    let start = Instant::now();
+    while let Some(event) = next_event(&receiver, config, start).await {
+        if let ServiceEvent::ServiceResolved(info) = event {
+            if info.get_fullname() == fullname {
+                return Some(info);
+            }
+        }
+    }
+    None
+}
+
 // Find devices in the network and
 // save their metadata into the database.
-#[put("/")]
+//
+// In `merge` mode, discovered devices are merged with the stored ones,
+// otherwise they replace every old discovered device.
+#[put("/?<mode>")]
 async fn devices_discovery(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    mode: Option<DiscoveryMode>,
     state: &State<ServiceState>,
+    config: &State<GatewayConfig>,
+    lock: &State<DiscoveryLock>,
+    logs: &State<LogBuffer>,
+    metrics: &State<Metrics>,
     mut db: Connection<Devices>,
     uri: &Origin<'_>,
-) -> Result<Redirect, InternalError> {
-    // Browse the network in search of the input service type.
-    let receiver = state
-        .0
-        .browse(SERVICE_TYPE)
-        .map_err(|e| InternalError::text(uri, &e.to_string()))?;
+) -> Result<Redirect, GatewayError> {
+    // Prevent manual registrations from running during a discovery.
+    let _guard = lock.0.lock().await;
 
-    // If a service type has been found, search devices and their metadata.
-    let devices_info = search_devices(receiver).await;
+    let discovery = discover(state, &config.discovery, logs, metrics)
+        .await
+        .map_err(|e| GatewayError::discovery_unavailable(uri, &e.to_string()))?;
 
-    // If some devices have been found, delete every old device from the
-    // database and insert every discovered devices.
-    if !devices_info.is_empty() {
-        // Clear the database
-        query_error(clear_database(&mut db), uri).await?;
+    query_error(
+        store_discovery(
+            &mut db,
+            discovery,
+            mode.unwrap_or(DiscoveryMode::Replace),
+            config,
+            logs,
+        ),
+        uri,
+    )
+    .await?;
 
-        // Save devices into the database.
-        save_devices(db, devices_info, uri).await?;
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Streams the devices found in the network as soon as they are resolved,
+// so that they can be shown while the discovery is running.
+//
+// The stream ends when the discovery window elapses. Devices are not saved.
+//
+// Browsing a service type again stops its running browse, hence the
+// stream holds the discovery lock until it ends, as the other discoveries do.
+#[get("/discover/stream")]
+async fn discovery_stream<'a>(
+    _auth: Authenticated,
+    state: &'a State<ServiceState>,
+    config: &'a State<GatewayConfig>,
+    lock: &'a State<DiscoveryLock>,
+    logs: &'a State<LogBuffer>,
+    uri: &Origin<'_>,
+) -> Result<EventStream![Event + 'a], GatewayError> {
+    let guard = lock.0.clone().lock_owned().await;
+
+    let mut receivers = Vec::new();
+    for service_type in config.discovery.service_types.iter() {
+        let receiver = state
+            .browse(service_type, logs)
+            .map_err(|e| GatewayError::discovery_unavailable(uri, &e.to_string()))?;
+        receivers.push(receiver);
+    }
+
+    Ok(EventStream! {
+        let mut discovery = Discovery::default();
+        // The deadline covers the whole discovery, not each service type.
+        let start = Instant::now();
+        for receiver in receivers {
+            while let Some(event) = next_event(&receiver, &config.discovery, start).await {
+                if let Some(device) = discovery.record(event, logs) {
+                    yield Event::json(&context! {
+                        fullname: device.info.get_fullname(),
+                        hostname: device.info.get_hostname(),
+                        port: device.info.get_port(),
+                        addresses: &device.addresses,
+                    })
+                    .event("device");
+                }
+            }
+        }
+        drop(guard);
+        yield Event::data("Discovery completed").event("end");
+    })
+}
+
+// Mark each device found by a discovery as already stored or new.
+async fn mark_stored<'a>(
+    db: &mut SqliteConnection,
+    discovery: &'a Discovery,
+) -> Result<Vec<(&'a ResolvedDevice, bool)>, sqlx::Error> {
+    let mut devices = Vec::new();
+    for device in discovery.resolved.iter() {
+        let stored = select_device_by_fullname(db, device.info.get_fullname())
+            .await?
+            .is_some();
+        devices.push((device, stored));
     }
+    Ok(devices)
+}
+
+// Previews the devices a discovery would find, without saving them.
+//
+// Found devices are marked as new or already stored, so that users can
+// check what a discovery would change before running it.
+#[get("/discover/preview")]
+async fn discovery_preview(
+    _auth: Authenticated,
+    csrf: CsrfToken,
+    state: &State<ServiceState>,
+    config: &State<GatewayConfig>,
+    lock: &State<DiscoveryLock>,
+    logs: &State<LogBuffer>,
+    metrics: &State<Metrics>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Template, GatewayError> {
+    // Browsing would stop a running discovery, hence wait for it.
+    let guard = lock.0.lock().await;
+    let discovery = discover(state, &config.discovery, logs, metrics)
+        .await
+        .map_err(|e| GatewayError::discovery_unavailable(uri, &e.to_string()))?;
+    drop(guard);
+
+    let devices = query_error(mark_stored(&mut db, &discovery), uri)
+        .await?
+        .into_iter()
+        .map(|(device, stored)| {
+            context! {
+                fullname: device.info.get_fullname(),
+                hostname: device.info.get_hostname(),
+                port: device.info.get_port(),
+                addresses: &device.addresses,
+                stored,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Template::render(
+        "discovery-preview",
+        context! {
+            csrf_token: csrf.value(),
+            no_devices_message: devices.is_empty().then_some("No devices found"),
+            devices,
+            removed: &discovery.removed,
+            discover_route: uri!(devices_discovery(Some(DiscoveryMode::Replace))),
+            discover_message: "Discover devices",
+            merge_route: uri!(devices_discovery(Some(DiscoveryMode::Merge))),
+            merge_message: "Update devices",
+        },
+    ))
+}
+
+// Refresh the properties of a device, re-resolving its mDNS record.
+//
+// Device routes and controls are left untouched.
+#[put("/device/<id>/properties/refresh")]
+async fn refresh_properties(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    state: &State<ServiceState>,
+    config: &State<GatewayConfig>,
+    lock: &State<DiscoveryLock>,
+    logs: &State<LogBuffer>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    // Browsing would stop a running discovery, hence wait for it.
+    let _guard = lock.0.lock().await;
+
+    let fullname = query_error(select_device_fullname(&mut db, id), uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Device not discovered through mDNS"))?;
+
+    let service_type = config
+        .discovery
+        .service_type(&fullname)
+        .ok_or_else(|| GatewayError::discovery(uri, "Device service type not browsed"))?;
+
+    let receiver = state
+        .browse(service_type, logs)
+        .map_err(|e| GatewayError::discovery_unavailable(uri, &e.to_string()))?;
+
+    let info = resolve_device(receiver, &fullname, &config.discovery)
+        .await
+        .ok_or_else(|| GatewayError::discovery(uri, "Device not found in the network"))?;
+
+    query_error(
+        replace_properties(&mut db, info.get_properties(), id, &config.text_limits),
+        uri,
+    )
+    .await?;
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Register a device manually, without discovering it.
+#[post("/devices", data = "<device>")]
+async fn register_device<'r>(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    device: Form<ManualDevice<'r>>,
+    lock: &State<DiscoveryLock>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    let device = device.into_inner();
+
+    // Wait for a running discovery to complete.
+    let _guard = lock.0.lock().await;
+
+    let id = query_error(
+        insert_manual_device(
+            &mut db,
+            device.port,
+            device.scheme.map_or(DEFAULT_SCHEME, allowed_scheme),
+            &device
+                .path
+                .and_then(local_path)
+                .unwrap_or(Cow::Borrowed(WELL_KNOWN_URI)),
+        ),
+        uri,
+    )
+    .await?;
+
+    query_error(insert_address(&mut db, device.address.to_string(), id), uri).await?;
 
     // Redirect to index
-    Ok(Redirect::to(uri!(index)))
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
 }
 
-#[get("/")]
+#[get("/?<page>&<per_page>&<hazard>&<q>&<sort>")]
 async fn index<'a>(
+    _auth: Authenticated,
+    csrf: CsrfToken,
+    page: Option<u32>,
+    per_page: Option<u32>,
+    hazard: Option<u16>,
+    q: Option<String>,
+    sort: Option<Sort>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    logs: &State<LogBuffer>,
     mut db: Connection<Devices>,
     uri: &Origin<'_>,
-) -> Result<Template, InternalError> {
-    // Check whether the database is empty.
-    let is_db_empty = query_error(is_db_empty(&mut db), uri).await?;
+) -> Result<Template, GatewayError> {
+    let page = Page::new(page, per_page);
 
-    // Contact discovered devices with the goal of retrieving their data and
-    // building their controls.
-    let devices = if is_db_empty {
-        //query_error(Device::search_for_devices(&mut db), uri).await?
-        crate::test::generate_devices_and_init_db(db, uri).await?
+    // Show fake devices in place of an empty database, only in demo builds.
+    #[cfg(feature = "demo")]
+    let fake_devices = if query_error(database::query::is_db_empty(&mut db), uri).await? {
+        Some(crate::test::generate_devices_and_init_db(&mut db, uri).await?)
     } else {
-        //query_error(Device::read_from_database(db), uri).await?
-        crate::test::generate_devices_and_init_db(db, uri).await?
+        None
+    };
+    #[cfg(not(feature = "demo"))]
+    let fake_devices = None;
+
+    // Keep only the devices presenting the requested hazard and matching
+    // the searched text.
+    let q = q.filter(|q| !q.trim().is_empty());
+    let filter = DeviceFilter {
+        hazard,
+        text: q.as_deref().map(|q| q.trim().to_owned()),
+    };
+
+    // Contact the devices of the requested page with the goal of retrieving
+    // their data and building their controls.
+    let (mut devices, total) = match fake_devices {
+        Some(devices) => {
+            let total = devices.len() as u32;
+            (devices, total)
+        }
+        None => {
+            let devices = query_error(
+                Device::search_for_devices(
+                    &mut db,
+                    client,
+                    &config.retry,
+                    metrics,
+                    logs,
+                    &filter,
+                    Some(page),
+                    sort.unwrap_or_default(),
+                ),
+                uri,
+            )
+            .await?;
+            // Counted once devices are retrieved, so that devices deleted
+            // or put on hold by the retrieval are left out.
+            let total =
+                query_error(count_listed_devices(&mut db, &filter, time::now()), uri).await?;
+            (devices, total)
+        }
     };
 
-    // Avoid having duplicated hazards.
+    // Avoid having duplicated hazards among the shown devices.
     let hazards = devices
         .iter()
         .fold(HazardsData::init(), |mut hazards, device| {
@@ -180,15 +832,247 @@ async fn index<'a>(
             hazards
         });
 
+    let count = query_error(count_devices(&mut db), uri).await?;
+
+    // Arguments of `uri!` are bound to the names of the route parameters,
+    // hence `page` cannot be read while building the links.
+    let previous_page = page.number.saturating_sub(1);
+    let next_page = page.number + 1;
+    let page_size = page.size;
+
+    // Hazards filters, one for each hazard presented by a stored device.
+    let hazard_filters = query_error(select_stored_hazards(&mut db), uri)
+        .await?
+        .into_iter()
+        .map(|(id, name)| {
+            context! {
+                name: name.unwrap_or_else(|| format!("Hazard {id}")),
+                route: uri!(index(_, Some(page_size), Some(id), q.as_deref(), sort)),
+                active: hazard == Some(id),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let pages = page.count(total);
+
+    // Mark devices not retrieved recently.
+    let now = time::now();
+    devices
+        .iter_mut()
+        .for_each(|device| device.check_staleness(config.stale_after, now));
+
+    // Show when devices have last been discovered.
+    devices.iter_mut().for_each(|device| {
+        device.last_seen = device
+            .metadata
+            .last_seen
+            .and_then(|time| config.timezone.format(time));
+    });
+
+    // Restrict controls of routes presenting a denied hazard category, and
+    // ask for a confirmation before using the controls of the other routes
+    // presenting hazards.
+    for device in devices.iter_mut() {
+        let route_hazards =
+            query_error(select_device_hazards(&mut db, device.metadata.id), uri).await?;
+        let route_ids = route_hazards
+            .iter()
+            .filter(|hazard| config.is_denied(std::iter::once(hazard.category.as_str())))
+            .map(|hazard| hazard.route_id)
+            .collect::<Vec<_>>();
+        device.state_controls.restrict(&route_ids);
+
+        let route_ids = route_hazards
+            .iter()
+            .map(|hazard| hazard.route_id)
+            .collect::<Vec<_>>();
+        device.state_controls.mark_hazardous(&route_ids);
+    }
+
+    // Orders of the devices list.
+    let sorts = [
+        (Sort::Id, "id", "Added"),
+        (Sort::Name, "name", "Name"),
+        (Sort::Kind, "kind", "Kind"),
+        (Sort::Reachable, "reachable", "Reachable"),
+    ]
+    .map(|(value, field, name)| {
+        context! {
+            field,
+            name,
+            selected: sort.unwrap_or_default() == value,
+        }
+    });
+
     Ok(Template::render(
         "index",
         context! {
           no_devices_message: devices.is_empty().then_some("No devices available!"),
+          count_message: match count {
+              1 => "1 device".into(),
+              count => format!("{count} devices"),
+          },
           devices,
           hazards,
-          discover_route: uri!(devices_discovery),
+          hazard_filters,
+          all_devices_route: hazard.map(|_| uri!(index(_, Some(page_size), _, q.as_deref(), sort))),
+          // Forms sent through `GET` replace the query, hence the current
+          // filters are sent as hidden fields.
+          search: context! {
+              route: uri!(index(_, _, _, _, _)),
+              text: q.as_deref(),
+              hazard,
+              per_page: page_size,
+              sorts,
+          },
+          // Forms send the CSRF token back as a hidden field.
+          csrf_token: csrf.value(),
+          discover_route: uri!(devices_discovery(Some(DiscoveryMode::Replace))),
+          merge_route: uri!(devices_discovery(Some(DiscoveryMode::Merge))),
+          merge_message: "Update devices",
           discover_message: "Discover devices",
+          preview_route: uri!(discovery_preview),
+          preview_message: "Preview discovery",
+          register_route: uri!(register_device),
+          register_message: "Add device",
+          clear_route: uri!(clear_devices),
+          clear_message: "Delete all devices",
+          logs_route: uri!(gateway_logs),
+          logs_message: "Logs",
+          logout_route: config.auth.is_some().then(|| uri!(logout)),
+          logout_message: "Log out",
+          pagination: context! {
+              page: page.number,
+              pages,
+              previous_route: (page.number > 1)
+                  .then(|| uri!(index(Some(previous_page), Some(page_size), hazard, q.as_deref(), sort))),
+              next_route: (page.number < pages)
+                  .then(|| uri!(index(Some(next_page), Some(page_size), hazard, q.as_deref(), sort))),
+          },
+
+        },
+    ))
+}
+
+// Shows the form to log in the gateway.
+#[get("/login")]
+fn login_page(csrf: CsrfToken) -> Template {
+    Template::render(
+        "login",
+        context! {
+            csrf_token: csrf.value(),
+            login_route: uri!(login),
+            index_route: uri!(index(_, _, _, _, _)),
+        },
+    )
+}
+
+// Logs in the gateway, storing the user into a private session cookie.
+//
+// Logins need the CSRF token too, so that other sites cannot log browsers
+// in with their own credentials.
+#[post("/login", data = "<login>")]
+fn login(
+    csrf: CsrfToken,
+    login: Form<Login<'_>>,
+    config: &State<GatewayConfig>,
+    cookies: &CookieJar<'_>,
+) -> Result<Redirect, (Status, Template)> {
+    // Without authentication, there is nothing to log in.
+    let Some(auth) = config.auth.as_ref() else {
+        return Ok(Redirect::to(uri!(index(_, _, _, _, _))));
+    };
+
+    if !auth.verify(login.username, login.password) {
+        return Err((
+            Status::Unauthorized,
+            Template::render(
+                "login",
+                context! {
+                    csrf_token: csrf.value(),
+                    login_route: uri!(login),
+                    index_route: uri!(index(_, _, _, _, _)),
+                    error_message: "Wrong username or password",
+                },
+            ),
+        ));
+    }
+
+    cookies.add_private((SESSION_COOKIE, auth.username.clone()));
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Logs out of the gateway, removing the session cookie.
+#[post("/logout")]
+fn logout(_csrf: CsrfToken, cookies: &CookieJar<'_>) -> Redirect {
+    cookies.remove_private(SESSION_COOKIE);
+    Redirect::to(uri!(login_page))
+}
+
+// Shows the details of a single device, read from the database: its
+// metadata, addresses, properties and routes, together with their hazards
+// and the current values of their inputs.
+#[get("/device/<id>")]
+async fn device_details(
+    _auth: Authenticated,
+    id: u16,
+    config: &State<GatewayConfig>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Template, GatewayError> {
+    let metadata = query_error(select_device_metadata_by_id(&mut db, id), uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Device not found"))?;
+    let addresses = query_error(select_device_addresses(&mut db, id), uri).await?;
+    let properties = query_error(select_device_properties(&mut db, id), uri).await?;
+    let main_route = query_error(select_main_route(&mut db, id), uri).await?;
+    let hazards = query_error(select_device_hazards(&mut db, id), uri).await?;
 
+    // Show JSON responses indented.
+    let last_response = query_error(select_last_response(&mut db, id), uri)
+        .await?
+        .map(|response| {
+            let body = serde_json::from_str::<Value>(&response.body)
+                .ok()
+                .and_then(|body| serde_json::to_string_pretty(&body).ok())
+                .unwrap_or(response.body);
+            context! { status: response.status, body }
+        });
+
+    let mut routes = Vec::new();
+    for route in query_error(select_device_routes_by_id(&mut db, id), uri).await? {
+        let inputs = query_error(select_route_inputs(&mut db, route.id), uri).await?;
+        routes.push(context! {
+            hazards: hazards
+                .iter()
+                .filter(|hazard| hazard.route_id == route.id)
+                .collect::<Vec<_>>(),
+            // Sorted by input name.
+            values: inputs.values().into_iter().collect::<BTreeMap<_, _>>(),
+            id: route.id,
+            route: route.route,
+            description: route.description,
+        });
+    }
+
+    Ok(Template::render(
+        "device-details",
+        context! {
+          // Data are shown as stored, hence they may be old.
+          stale: metadata.is_stale(config.stale_after, time::now()),
+          last_retrieved: metadata
+              .last_retrieved
+              .and_then(|time| config.timezone.format(time)),
+          last_seen: metadata
+              .last_seen
+              .and_then(|time| config.timezone.format(time)),
+          metadata,
+          main_route,
+          addresses,
+          properties,
+          routes,
+          last_response,
+          index_route: uri!(index(_, _, _, _, _)),
         },
     ))
 }
@@ -201,42 +1085,2700 @@ async fn index<'a>(
 // 4. Go to the index
 #[put("/device/<id>", data = "<inputs>")]
 async fn device_request<'r>(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
     id: u16,
     inputs: Form<DeviceData<'r>>,
-    db: Connection<Devices>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    limiter: &State<RateLimiter>,
+    events: &State<StateEvents>,
+    mut db: Connection<Devices>,
     uri: &Origin<'_>,
-) -> Result<Redirect, InternalError> {
-    // Retrieve form controls values.
+) -> Result<Redirect, GatewayError> {
+    // Look up the device first, so that requests to an unknown device are
+    // answered with a not found error.
+    let endpoint = device_endpoint(&mut db, client, id, uri).await?;
+
+    // Protect devices from being flooded with requests.
+    if !limiter.allow(id) {
+        return Err(GatewayError::too_many_requests(uri));
+    }
+
     let inputs = inputs.into_inner();
+    let hazards_confirmed = inputs.confirm;
+
+    // Retrieve form controls values, always processing them in the same
+    // order.
+    let inputs = inputs.sorted_inputs();
+
+    // Reject malformed values before contacting the device.
+    if let Some(input) = inputs.iter().find(|input| !input.value.is_valid()) {
+        return Err(GatewayError::bad_input(
+            uri,
+            &format!("Invalid value for input {}", input.name),
+        ));
+    }
+
+    let routes = query_error(select_device_routes_by_id(&mut db, id), uri).await?;
 
-    // Save changed form controls into database.
-    // TODO: Move downside after the change in the route happened
+    // Form inputs are grouped by route.
+    let mut requests = Vec::new();
+    for route_inputs in inputs.chunk_by(|a, b| a.route_id == b.route_id) {
+        let route_id = route_inputs[0].route_id;
 
-    // Build a REST request from data passed as input.
+        let route = routes
+            .iter()
+            .find(|route| route.id == route_id)
+            .ok_or_else(|| GatewayError::not_found(uri, "Route not found"))?;
 
-    // Send the request
+        let stored_inputs = query_error(select_route_inputs(&mut db, route.id), uri).await?;
 
-    // Save into the database the new data
+        // Reject inputs the route does not have and values outside of the
+        // stored ranges before contacting the device, so that no route is
+        // invoked with a crafted form.
+        if let Some(input) = route_inputs
+            .iter()
+            .find(|input| !stored_inputs.allows(input.name, &input.value))
+        {
+            return Err(GatewayError::input_not_allowed(uri, input.name));
+        }
+
+        requests.push((route, route_inputs, stored_inputs));
+    }
+
+    // Check every route to invoke before sending any request, so that a
+    // refused route cannot leave the device partly changed.
+    let mut invocations = Vec::new();
+    for (route, route_inputs, stored_inputs) in &requests {
+        // Start from the stored values, so that inputs missing from the
+        // form keep their values.
+        let mut values = stored_inputs.values();
+
+        // A route is invoked when its button has been pressed or when any of
+        // its inputs has changed.
+        let mut invoke = false;
+        for input in route_inputs.iter() {
+            match input.value.text() {
+                Some(value) => {
+                    invoke |= values.get(input.name) != Some(&value);
+                    values.insert(input.name, value);
+                }
+                None => invoke |= input.value == InputValue::Button(true),
+            }
+        }
+
+        if !invoke {
+            continue;
+        }
+
+        check_route(&mut db, config, route.id, hazards_confirmed, uri)
+            .await?
+            .map_err(|refusal| refusal.into_gateway_error(uri))?;
+
+        invocations.push((route, route_inputs, values));
+    }
+
+    for (route, route_inputs, values) in invocations {
+        // Build a REST request from data passed as input and send it,
+        // trying each device address in turn.
+        let mut request = endpoint.request(&route.route, &values);
+        let response = request.send(route.rest_kind.method()).await;
+        let response = finish_request(&mut db, metrics, &request, response, id, uri).await?;
+
+        let state = values.get(route.route.as_str()).map(String::as_str) == Some("true");
+        save_answer(
+            &mut db,
+            config,
+            events,
+            route,
+            route_inputs,
+            state,
+            response,
+            id,
+            uri,
+        )
+        .await?;
+    }
 
     // Redirect to index
-    Ok(Redirect::to(uri!(index)))
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
 }
 
-// Service state.
-struct ServiceState(ServiceDaemon);
+// Saves what a device has accepted: the new values of the route inputs,
+// together with the device answer.
+//
+// The state of a stateful route is flipped when its button is pressed.
+async fn save_answer(
+    db: &mut Connection<Devices>,
+    config: &GatewayConfig,
+    events: &StateEvents,
+    route: &Route,
+    route_inputs: &[FormInput<'_>],
+    state: bool,
+    response: Response,
+    id: u16,
+    uri: &Origin<'_>,
+) -> Result<(), GatewayError> {
+    // Stateless routes are fire-and-forget, so they keep no value.
+    if !route.stateless {
+        // Save into the database the new data
+        for input in route_inputs.iter() {
+            save_input(db, events, id, route.id, input, uri).await?;
+        }
+
+        // Pressing the button of a stateful route flips its state.
+        if route_inputs
+            .iter()
+            .any(|input| input.value == InputValue::Button(true))
+        {
+            query_error(
+                update_boolean_value(db, route.id, &route.route, !state),
+                uri,
+            )
+            .await?;
+            events.publish(StateChange {
+                device_id: id,
+                route_id: route.id,
+                name: route.route.clone(),
+                value: (!state).to_string(),
+            });
+        }
+    }
 
-#[launch]
-fn rocket() -> _ {
-    // Enable tracing subscriber
-    tracing_subscriber::fmt().init();
+    // Keep the device answer, so that users can check whether the device
+    // has accepted the request.
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+    query_error(
+        update_last_response(db, id, status, &config.text_limits.response(&body)),
+        uri,
+    )
+    .await?;
 
-    // Create a daemon
-    let mdns = ServiceDaemon::new().expect("Failed to create mdns daemon");
+    // Stateless routes keep no value, even when the device answers with its
+    // state.
+    if route.stateless {
+        return Ok(());
+    }
 
-    rocket::build()
-        .mount("/", routes![index, devices_discovery, device_request])
-        .manage(ServiceState(mdns))
-        .attach(database::stage())
-        .attach(Template::fairing())
-        .register("/", error::catchers())
+    // A device answering with its state is the authority on the values of
+    // the route inputs.
+    if let Ok(state) = serde_json::from_str::<serde_json::Map<_, _>>(&body) {
+        let stored_inputs = query_error(select_route_inputs(db, route.id), uri).await?;
+        for (name, value) in stored_inputs.state_values(&state) {
+            let input = FormInput {
+                route_id: route.id,
+                name,
+                value,
+            };
+            save_input(db, events, id, route.id, &input, uri).await?;
+        }
+    }
+    Ok(())
+}
+
+// Control change of a batch, ready to be sent.
+struct BatchInvocation {
+    // Device identifier.
+    id: u16,
+    // Invoked route.
+    route: Route,
+    // New values of the route inputs, or a pressed button.
+    inputs: Vec<(String, InputValue)>,
+    // Whether the route state is on, before the change.
+    state: bool,
+    // Request to the device.
+    request: DeviceRequest,
+}
+
+// Outcome of a control change of a batch.
+#[derive(Debug, Serialize)]
+struct BatchOutcome {
+    // Device identifier.
+    device_id: u16,
+    // Device route.
+    route: String,
+    // Status answered by the device, when it has accepted the change.
+    status: Option<u16>,
+    // Why the change has failed, if it has.
+    error: Option<String>,
+}
+
+// Sends many control changes at once, such as a scene turning every light
+// off.
+//
+// Each change is reported, in the same order.
+#[put("/devices/batch", data = "<batch>")]
+async fn devices_batch(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    batch: Json<Vec<BatchItem>>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    limiter: &State<RateLimiter>,
+    events: &State<StateEvents>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Json<Vec<BatchOutcome>>, GatewayError> {
+    run_batch(
+        &mut db, config, client, metrics, limiter, events, &batch, uri,
+    )
+    .await
+    .map(Json)
+}
+
+// Runs a batch of control changes, returning the outcome of each one.
+//
+// Changes are checked one at a time, then sent to their devices
+// concurrently. Only the values of the changes accepted by their devices are
+// saved.
+async fn run_batch(
+    db: &mut Connection<Devices>,
+    config: &GatewayConfig,
+    client: &DeviceClient,
+    metrics: &Metrics,
+    limiter: &RateLimiter,
+    events: &StateEvents,
+    batch: &[BatchItem],
+    uri: &Origin<'_>,
+) -> Result<Vec<BatchOutcome>, GatewayError> {
+    let mut outcomes = batch
+        .iter()
+        .map(|item| BatchOutcome {
+            device_id: item.device_id,
+            route: item.route.clone(),
+            status: None,
+            error: None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut invocations = Vec::new();
+    for (index, item) in batch.iter().enumerate() {
+        match prepare_batch_item(db, config, client, limiter, item, uri).await? {
+            Ok(invocation) => invocations.push((index, invocation)),
+            Err(error) => outcomes[index].error = Some(error),
+        }
+    }
+
+    // Contact devices concurrently, so that slow devices do not add up their
+    // timeouts.
+    let responses = join_all(
+        invocations
+            .iter_mut()
+            .map(|(_, invocation)| invocation.request.send(invocation.route.rest_kind.method())),
+    )
+    .await;
+
+    for ((index, invocation), response) in invocations.iter().zip(responses) {
+        let id = invocation.id;
+        record_request(db, metrics, &invocation.request, response.is_err(), id, uri).await?;
+        match response {
+            Ok(response) => {
+                outcomes[*index].status = Some(response.status().as_u16());
+                let inputs = invocation
+                    .inputs
+                    .iter()
+                    .map(|(name, value)| FormInput {
+                        route_id: invocation.route.id,
+                        name: name.as_str(),
+                        value: value.clone(),
+                    })
+                    .collect::<Vec<_>>();
+                save_answer(
+                    db,
+                    config,
+                    events,
+                    &invocation.route,
+                    &inputs,
+                    invocation.state,
+                    response,
+                    id,
+                    uri,
+                )
+                .await?;
+            }
+            Err(e) => outcomes[*index].error = Some(e.to_string()),
+        }
+    }
+
+    Ok(outcomes)
+}
+
+// Checks a control change of a batch, building its request.
+//
+// Database failures are returned as errors, while refused changes are
+// returned as the reason why they cannot be sent.
+async fn prepare_batch_item(
+    db: &mut Connection<Devices>,
+    config: &GatewayConfig,
+    client: &DeviceClient,
+    limiter: &RateLimiter,
+    item: &BatchItem,
+    uri: &Origin<'_>,
+) -> Result<Result<BatchInvocation, String>, GatewayError> {
+    let id = item.device_id;
+    let Some(endpoint) = load_endpoint(db, client, id, uri).await? else {
+        return Ok(Err("Device not found".into()));
+    };
+
+    let Some(route) = query_error(select_route_by_name(db, &item.route, id), uri).await? else {
+        return Ok(Err("Route not advertised by the device".into()));
+    };
+
+    let stored_inputs = query_error(select_route_inputs(db, route.id), uri).await?;
+    let Some(new_values) = stored_inputs.batch_values(item.value.as_ref()) else {
+        return Ok(Err("Invalid value for the route inputs".into()));
+    };
+
+    if let Err(refusal) = check_route(db, config, route.id, item.confirm, uri).await? {
+        return Ok(Err(refusal.message().into()));
+    }
+
+    // Protect devices from being flooded with requests.
+    if !limiter.allow(id) {
+        return Ok(Err("The device has received too many requests".into()));
+    }
+
+    // Start from the stored values, so that missing inputs keep their
+    // values.
+    let mut values = stored_inputs.values();
+    let state = values.get(route.route.as_str()).map(String::as_str) == Some("true");
+    for (name, value) in &new_values {
+        if let Some(text) = value.text() {
+            values.insert(*name, text);
+        }
+    }
+    let request = endpoint.request(&route.route, &values);
+
+    // Without values, the route is invoked as its button is pressed.
+    let inputs = if new_values.is_empty() {
+        vec![(route.route.clone(), InputValue::Button(true))]
+    } else {
+        new_values
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect()
+    };
+
+    Ok(Ok(BatchInvocation {
+        id,
+        route,
+        inputs,
+        state,
+        request,
+    }))
+}
+
+// Scene together with its control changes.
+#[derive(Debug, Serialize)]
+struct SceneDetails {
+    // Scene identifier.
+    id: u16,
+    // Scene name.
+    name: String,
+    // Control changes sent when the scene is applied.
+    items: Vec<BatchItem>,
+}
+
+// Saves a named group of control changes, to be applied later.
+//
+// Each change must target a route advertised by its device, while values
+// are checked only when the scene is applied, since inputs can change in
+// the meantime.
+#[post("/scenes", data = "<scene>")]
+async fn create_scene(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    scene: Json<NewScene>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<(Status, Json<SceneDetails>), GatewayError> {
+    let NewScene { name, items } = scene.into_inner();
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(GatewayError::bad_input(uri, "Scene name cannot be empty"));
+    }
+    if items.is_empty() {
+        return Err(GatewayError::bad_input(
+            uri,
+            "A scene needs at least one control change",
+        ));
+    }
+    if query_error(select_scene_by_name(&mut db, name), uri)
+        .await?
+        .is_some()
+    {
+        return Err(GatewayError::bad_input(
+            uri,
+            &format!("Scene {name} already exists"),
+        ));
+    }
+
+    for item in &items {
+        if query_error(
+            select_route_by_name(&mut db, &item.route, item.device_id),
+            uri,
+        )
+        .await?
+        .is_none()
+        {
+            return Err(GatewayError::bad_input(
+                uri,
+                &format!(
+                    "Device {} does not advertise route {}",
+                    item.device_id, item.route
+                ),
+            ));
+        }
+    }
+
+    let id = query_error(insert_scene(&mut db, name, &items), uri).await?;
+    Ok((
+        Status::Created,
+        Json(SceneDetails {
+            id,
+            name: name.into(),
+            items,
+        }),
+    ))
+}
+
+// Lists the scenes together with their control changes.
+#[get("/scenes")]
+async fn scenes(
+    _auth: Authenticated,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Json<Vec<SceneDetails>>, GatewayError> {
+    let mut scenes = Vec::new();
+    for scene in query_error(select_scenes(&mut db), uri).await? {
+        let items = query_error(select_scene_items(&mut db, scene.id), uri).await?;
+        scenes.push(SceneDetails {
+            id: scene.id,
+            name: scene.name,
+            items: items.into_iter().map(SceneItem::batch_item).collect(),
+        });
+    }
+    Ok(Json(scenes))
+}
+
+// Applies a scene, sending its control changes as a batch.
+//
+// Each change is reported, in the scene order.
+#[put("/scenes/<id>/apply")]
+async fn apply_scene(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    limiter: &State<RateLimiter>,
+    events: &State<StateEvents>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Json<Vec<BatchOutcome>>, GatewayError> {
+    if query_error(select_scene(&mut db, id), uri).await?.is_none() {
+        return Err(GatewayError::not_found(uri, "Scene not found"));
+    }
+
+    let batch = query_error(select_scene_items(&mut db, id), uri)
+        .await?
+        .into_iter()
+        .map(SceneItem::batch_item)
+        .collect::<Vec<_>>();
+    run_batch(
+        &mut db, config, client, metrics, limiter, events, &batch, uri,
+    )
+    .await
+    .map(Json)
+}
+
+// Save the value of a form input into the database, notifying the open
+// pages of the change.
+async fn save_input(
+    db: &mut Connection<Devices>,
+    events: &StateEvents,
+    device_id: u16,
+    route_id: u16,
+    input: &FormInput<'_>,
+    uri: &Origin<'_>,
+) -> Result<(), GatewayError> {
+    store_input(db, route_id, input, uri).await?;
+    if let Some(value) = input.value.text() {
+        events.publish(StateChange {
+            device_id,
+            route_id,
+            name: input.name.into(),
+            value,
+        });
+    }
+    Ok(())
+}
+
+async fn store_input(
+    db: &mut Connection<Devices>,
+    route_id: u16,
+    input: &FormInput<'_>,
+    uri: &Origin<'_>,
+) -> Result<(), GatewayError> {
+    match &input.value {
+        InputValue::SliderU64(value) => {
+            query_error(update_rangeu64_value(db, route_id, input.name, *value), uri).await
+        }
+        InputValue::SliderF64(value) => {
+            query_error(update_rangef64_value(db, route_id, input.name, *value), uri).await
+        }
+        InputValue::CheckBox(value) => {
+            query_error(update_boolean_value(db, route_id, input.name, *value), uri).await
+        }
+        InputValue::Text(value) => {
+            query_error(update_text_value(db, route_id, input.name, value), uri).await
+        }
+        InputValue::Select(value) => {
+            query_error(update_select_value(db, route_id, input.name, value), uri).await
+        }
+        InputValue::Color(value) => {
+            query_error(update_color_value(db, route_id, input.name, value), uri).await
+        }
+        // Buttons do not have a value.
+        InputValue::Button(_) => Ok(()),
+    }
+}
+
+// Loads the information needed to contact a device.
+async fn device_endpoint(
+    db: &mut Connection<Devices>,
+    client: &DeviceClient,
+    id: u16,
+    uri: &Origin<'_>,
+) -> Result<DeviceEndpoint, GatewayError> {
+    load_endpoint(db, client, id, uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Device not found"))
+}
+
+// Loads the information needed to contact a device, if stored.
+async fn load_endpoint(
+    db: &mut Connection<Devices>,
+    client: &DeviceClient,
+    id: u16,
+    uri: &Origin<'_>,
+) -> Result<Option<DeviceEndpoint>, GatewayError> {
+    let Some(metadata) = query_error(select_device_metadata_by_id(db, id), uri).await? else {
+        return Ok(None);
+    };
+
+    let addresses = query_error(select_device_addresses(db, id), uri).await?;
+
+    let main_route = query_error(select_main_route(db, id), uri)
+        .await?
+        .unwrap_or_default();
+
+    Ok(Some(DeviceEndpoint {
+        client: client.clone(),
+        metadata,
+        addresses,
+        main_route,
+    }))
+}
+
+// Saves whether the addresses contacted by a request have answered and
+// counts the request, returning its response.
+async fn finish_request(
+    db: &mut Connection<Devices>,
+    metrics: &Metrics,
+    request: &DeviceRequest,
+    response: Result<Response, RequestError>,
+    id: u16,
+    uri: &Origin<'_>,
+) -> Result<Response, GatewayError> {
+    record_request(db, metrics, request, response.is_err(), id, uri).await?;
+    response.map_err(|e| GatewayError::device_request(uri, e))
+}
+
+// Saves whether the addresses contacted by a request have answered and
+// counts the request.
+async fn record_request(
+    db: &mut Connection<Devices>,
+    metrics: &Metrics,
+    request: &DeviceRequest,
+    failed: bool,
+    id: u16,
+    uri: &Origin<'_>,
+) -> Result<(), GatewayError> {
+    for (address, reachable) in request.attempts() {
+        if !reachable {
+            warn!("Device {} is unreachable at {}", id, address);
+        }
+        query_error(update_address_reachable(db, address, reachable, id), uri).await?;
+    }
+
+    metrics.device_request(failed);
+    Ok(())
+}
+
+// Invokes a device route without inputs.
+async fn invoke_device_route(
+    db: &mut Connection<Devices>,
+    config: &GatewayConfig,
+    client: &DeviceClient,
+    metrics: &Metrics,
+    id: u16,
+    route: &str,
+    hazards_confirmed: bool,
+    uri: &Origin<'_>,
+) -> Result<(), GatewayError> {
+    let endpoint = device_endpoint(db, client, id, uri).await?;
+
+    let route = query_error(select_route_by_name(db, route, id), uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Route not advertised by the device"))?;
+
+    check_route(db, config, route.id, hazards_confirmed, uri)
+        .await?
+        .map_err(|refusal| refusal.into_gateway_error(uri))?;
+
+    let mut request = endpoint.request(&route.route, &HashMap::new());
+    let response = request.send(route.rest_kind.method()).await;
+    finish_request(db, metrics, &request, response, id, uri).await?;
+
+    Ok(())
+}
+
+// Checks whether a device is online, contacting each of its addresses
+// without changing the device state.
+//
+// Whether each address has answered is saved.
+#[get("/device/<id>/ping")]
+async fn ping_device(
+    _auth: Authenticated,
+    id: u16,
+    client: &State<DeviceClient>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Json<Vec<Ping>>, GatewayError> {
+    let metadata = query_error(select_device_metadata_by_id(&mut db, id), uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Device not found"))?;
+    let addresses = query_error(select_device_addresses(&mut db, id), uri).await?;
+
+    let pings = Device::ping(client, &metadata, addresses).await;
+    for ping in pings.iter() {
+        let address = ping.address.to_string();
+        query_error(
+            update_address_reachable(&mut db, &address, ping.reachable, id),
+            uri,
+        )
+        .await?;
+        if let Some(latency) = ping.latency {
+            query_error(update_address_latency(&mut db, &address, latency, id), uri).await?;
+        }
+    }
+
+    Ok(Json(pings))
+}
+
+// Asks a device to identify itself.
+#[post("/device/<id>/identify", data = "<confirmation>")]
+async fn identify_device(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    confirmation: Form<Confirmation>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    invoke_device_route(
+        &mut db,
+        config,
+        client,
+        metrics,
+        id,
+        IDENTIFY_ROUTE,
+        confirmation.confirm,
+        uri,
+    )
+    .await?;
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Reboots a device.
+//
+// The reboot must be explicitly confirmed.
+#[post("/device/<id>/reboot", data = "<confirmation>")]
+async fn reboot_device(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    confirmation: Form<Confirmation>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    if !confirmation.confirm {
+        return Err(GatewayError::bad_input(
+            uri,
+            "Device reboot must be confirmed",
+        ));
+    }
+
+    // A confirmed reboot confirms the route hazards too.
+    invoke_device_route(
+        &mut db,
+        config,
+        client,
+        metrics,
+        id,
+        REBOOT_ROUTE,
+        true,
+        uri,
+    )
+    .await?;
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Re-sends the stored values of the inputs of a device route.
+//
+// Useful to restore the intended device state after a device reboot.
+#[post("/device/<id>/route/<route_id>/resync", data = "<confirmation>")]
+async fn resync_route(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    route_id: u16,
+    confirmation: Form<Confirmation>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    let endpoint = device_endpoint(&mut db, client, id, uri).await?;
+
+    let route = query_error(select_route(&mut db, route_id, id), uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Route not found"))?;
+
+    check_route(&mut db, config, route.id, confirmation.confirm, uri)
+        .await?
+        .map_err(|refusal| refusal.into_gateway_error(uri))?;
+
+    let inputs = query_error(select_route_inputs(&mut db, route.id), uri).await?;
+
+    // Send stored values to the device.
+    let mut request = endpoint.request(&route.route, &inputs.values());
+    let response = request.send(route.rest_kind.method()).await;
+    finish_request(&mut db, metrics, &request, response, id, uri).await?;
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Resets the inputs of a device route to their default values.
+//
+// 1. Send the default values to the device.
+// 2. Save default values into the database.
+// 3. Go to the index
+#[post("/device/<id>/route/<route_id>/reset", data = "<confirmation>")]
+async fn reset_route(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    route_id: u16,
+    confirmation: Form<Confirmation>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    events: &State<StateEvents>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    let endpoint = device_endpoint(&mut db, client, id, uri).await?;
+
+    let route = query_error(select_route(&mut db, route_id, id), uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Route not found"))?;
+
+    check_route(&mut db, config, route.id, confirmation.confirm, uri)
+        .await?
+        .map_err(|refusal| refusal.into_gateway_error(uri))?;
+
+    let inputs = query_error(select_route_inputs(&mut db, route.id), uri).await?;
+
+    // Send default values to the device.
+    let mut request = endpoint.request(&route.route, &inputs.defaults());
+    let response = request.send(route.rest_kind.method()).await;
+    finish_request(&mut db, metrics, &request, response, id, uri).await?;
+
+    // Save default values into the database.
+    query_error(reset_route_inputs(&mut db, route.id), uri).await?;
+    for (name, value) in inputs.defaults() {
+        events.publish(StateChange {
+            device_id: id,
+            route_id: route.id,
+            name: name.into(),
+            value,
+        });
+    }
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Deletes a device and all its data.
+#[delete("/device/<id>")]
+async fn remove_device(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    if !query_error(delete_device(&mut db, id), uri).await? {
+        return Err(GatewayError::not_found(uri, "Device not found"));
+    }
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Gives a name to a device, shown in place of its path.
+//
+// Names are kept when devices are merged with the discovered ones.
+#[put("/device/<id>/name", data = "<device>")]
+async fn name_device<'r>(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    id: u16,
+    device: Form<DeviceName<'r>>,
+    config: &State<GatewayConfig>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    let name = config.text_limits.name(device.name.trim());
+
+    if !query_error(rename_device(&mut db, id, &name), uri).await? {
+        return Err(GatewayError::not_found(uri, "Device not found"));
+    }
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Deletes every device, both discovered and manually registered.
+//
+// The deletion must be explicitly confirmed.
+#[delete("/devices", data = "<confirmation>")]
+async fn clear_devices(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    confirmation: Form<Confirmation>,
+    lock: &State<DiscoveryLock>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    if !confirmation.confirm {
+        return Err(GatewayError::bad_input(
+            uri,
+            "Devices deletion must be confirmed",
+        ));
+    }
+
+    // Prevent devices from being added while clearing the database.
+    let _guard = lock.0.lock().await;
+
+    query_error(clear_database(&mut db), uri).await?;
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Streams device logs as Server-Sent Events.
+//
+// Only available for devices advertising a logs route.
+#[get("/device/<id>/logs")]
+async fn device_logs(
+    _auth: Authenticated,
+    id: u16,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], GatewayError> {
+    let endpoint = device_endpoint(&mut db, client, id, uri).await?;
+
+    let route = query_error(select_route_by_name(&mut db, LOGS_ROUTE, id), uri)
+        .await?
+        .ok_or_else(|| GatewayError::not_found(uri, "Device does not stream logs"))?;
+
+    let mut request = endpoint.request(&route.route, &HashMap::new());
+    let response = request.open().await;
+    let mut response = finish_request(&mut db, metrics, &request, response, id, uri).await?;
+
+    // When a client disconnects, the stream is dropped together with the
+    // device connection.
+    Ok(EventStream! {
+        // Incomplete line received from the device.
+        let mut buffer = String::new();
+        loop {
+            let chunk = select! {
+                chunk = response.chunk() => chunk,
+                _ = &mut shutdown => break,
+            };
+
+            match chunk {
+                Ok(Some(bytes)) => {
+                    buffer.push_str(&String::from_utf8_lossy(&bytes));
+                    while let Some((line, rest)) = buffer.split_once('\n') {
+                        let event = Event::data(line.trim_end_matches('\r').to_owned());
+                        buffer = rest.to_owned();
+                        yield event;
+                    }
+                }
+                // The device has closed the stream.
+                Ok(None) => {
+                    yield Event::data("Device disconnected").event("end");
+                    break;
+                }
+                Err(e) => {
+                    yield Event::data(e.to_string()).event("error");
+                    break;
+                }
+            }
+        }
+    })
+}
+
+// Shows the most recent gateway log entries, such as the reasons why a
+// discovered device has been skipped.
+#[get("/logs")]
+fn gateway_logs(
+    _auth: Authenticated,
+    config: &State<GatewayConfig>,
+    logs: &State<LogBuffer>,
+) -> Template {
+    Template::render(
+        "logs",
+        context! {
+            entries: logs.formatted_entries(&config.timezone),
+            no_entries_message: "No log entries",
+        },
+    )
+}
+
+// Returns the database schema: the latest applied migration version and
+// the columns of each table.
+//
+// Only available when the `debug` option is enabled.
+#[get("/api/debug/schema")]
+async fn debug_schema(
+    _auth: Authenticated,
+    config: &State<GatewayConfig>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Option<Json<Schema>>, GatewayError> {
+    if !config.debug {
+        return Ok(None);
+    }
+
+    let version = query_error(select_migration_version(&mut db), uri).await?;
+
+    let mut tables = Vec::new();
+    for name in query_error(select_tables(&mut db), uri).await? {
+        let columns = query_error(select_table_columns(&mut db, &name), uri).await?;
+        tables.push(TableSchema { name, columns });
+    }
+
+    Ok(Some(Json(Schema { version, tables })))
+}
+
+// List devices as JSON, retrieving their data.
+#[get("/api/devices?<page>&<per_page>")]
+async fn api_devices(
+    _auth: Authenticated,
+    page: Option<u32>,
+    per_page: Option<u32>,
+    config: &State<GatewayConfig>,
+    client: &State<DeviceClient>,
+    metrics: &State<Metrics>,
+    logs: &State<LogBuffer>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Json<Vec<Device>>, GatewayError> {
+    // Without pagination parameters, every device is listed.
+    let page = (page.is_some() || per_page.is_some()).then(|| Page::new(page, per_page));
+
+    let devices = query_error(
+        Device::search_for_devices(
+            &mut db,
+            client,
+            &config.retry,
+            metrics,
+            logs,
+            &DeviceFilter::default(),
+            page,
+            Sort::Id,
+        ),
+        uri,
+    )
+    .await?;
+    Ok(Json(devices))
+}
+
+// Export every stored device as JSON, so that devices can be restored
+// after the database has been wiped.
+#[get("/api/export")]
+async fn export_devices(
+    _auth: Authenticated,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Json<Dump>, GatewayError> {
+    query_error(export(&mut db), uri).await.map(Json)
+}
+
+// Restore devices from a JSON dump produced by the export.
+//
+// In `merge` mode the dumped devices are added to the stored ones,
+// otherwise they replace every stored device.
+#[post("/api/import?<mode>", data = "<dump>")]
+async fn import_devices(
+    _auth: Authenticated,
+    _csrf: CsrfToken,
+    mode: Option<DiscoveryMode>,
+    dump: Result<Json<Dump>, json::Error<'_>>,
+    mut db: Connection<Devices>,
+    uri: &Origin<'_>,
+) -> Result<Redirect, GatewayError> {
+    let dump = dump
+        .map_err(|e| GatewayError::bad_input(uri, &format!("Malformed dump: {e}")))?
+        .into_inner();
+    if dump.version != DUMP_VERSION {
+        return Err(GatewayError::bad_input(
+            uri,
+            &format!("Unsupported dump version {}", dump.version),
+        ));
+    }
+
+    query_error(
+        import(&mut db, dump, mode.unwrap_or(DiscoveryMode::Replace)),
+        uri,
+    )
+    .await?;
+
+    // Redirect to index
+    Ok(Redirect::to(uri!(index(_, _, _, _, _))))
+}
+
+// Pushes the changes of the stored values of device routes, so that open
+// pages are updated without being reloaded.
+//
+// Each change is sent as a JSON text message, until the client closes the
+// connection or the gateway shuts down.
+#[get("/events")]
+fn state_events(
+    _auth: Authenticated,
+    ws: WebSocket,
+    events: &State<StateEvents>,
+    mut shutdown: Shutdown,
+) -> Channel<'static> {
+    let mut changes = events.subscribe();
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            loop {
+                select! {
+                    change = changes.recv() => match change {
+                        Ok(change) => {
+                            let Ok(text) = json::to_string(&change) else {
+                                continue;
+                            };
+                            stream.send(Message::Text(text)).await?;
+                        }
+                        // Changes missed by a slow client are skipped.
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                    message = stream.next() => match message {
+                        // Clients have nothing to send, besides closing the
+                        // connection.
+                        Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                        Some(Ok(_)) => continue,
+                    },
+                    _ = &mut shutdown => break,
+                }
+            }
+            Ok(())
+        })
+    })
+}
+
+// Report the gateway activity counters in the Prometheus text format.
+#[get("/metrics")]
+fn gateway_metrics(_auth: Authenticated, metrics: &State<Metrics>) -> (ContentType, String) {
+    (
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        metrics.render(),
+    )
+}
+
+// Report the gateway and database status.
+//
+// This route is meant for liveness probes, hence its answers are always
+// JSON and never go through the error templates.
+#[get("/health")]
+async fn health(db: Option<Connection<Devices>>) -> (Status, Value) {
+    let devices = match db {
+        Some(mut db) => count_devices(&mut db).await.ok(),
+        None => None,
+    };
+
+    match devices {
+        Some(devices) => (Status::Ok, json!({ "status": "ok", "devices": devices })),
+        None => (
+            Status::ServiceUnavailable,
+            json!({ "status": "database unreachable" }),
+        ),
+    }
+}
+
+// Lock shared among the routes adding devices to the database.
+#[derive(Clone)]
+struct DiscoveryLock(Arc<Mutex<()>>);
+
+// Discover devices in the background at the configured interval.
+//
+// Discovered devices are merged with the stored ones, so that the names
+// given by users are kept.
+fn periodic_discovery() -> AdHoc {
+    AdHoc::on_liftoff("Periodic Discovery", |rocket| {
+        Box::pin(async move {
+            // The configuration has already been validated at ignition.
+            let Ok(config) = rocket.figment().focus("gateway").extract::<GatewayConfig>() else {
+                return;
+            };
+            let Some(interval) = config.discovery.interval() else {
+                return;
+            };
+            let (Some(state), Some(lock), Some(logs), Some(metrics), Some(db)) = (
+                rocket.state::<ServiceState>(),
+                rocket.state::<DiscoveryLock>(),
+                rocket.state::<LogBuffer>(),
+                rocket.state::<Metrics>(),
+                Devices::fetch(rocket),
+            ) else {
+                return;
+            };
+
+            let state = state.clone();
+            let lock = lock.clone();
+            let logs = logs.clone();
+            let metrics = metrics.clone();
+            let pool = (***db).clone();
+            let mut shutdown = rocket.shutdown();
+
+            rocket::tokio::spawn(async move {
+                let mut ticks = rocket::tokio::time::interval(interval);
+                loop {
+                    select! {
+                        _ = ticks.tick() => {},
+                        _ = &mut shutdown => break,
+                    }
+
+                    // Prevent manual registrations from running during a
+                    // discovery.
+                    let _guard = lock.0.lock().await;
+
+                    let Ok(discovery) = discover(&state, &config.discovery, &logs, &metrics).await
+                    else {
+                        continue;
+                    };
+
+                    let result = match pool.acquire().await {
+                        Ok(mut db) => {
+                            store_discovery(
+                                &mut db,
+                                discovery,
+                                DiscoveryMode::Merge,
+                                &config,
+                                &logs,
+                            )
+                            .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        logs.error(format!("Failed to save discovered devices: {}", e));
+                    }
+                }
+            });
+        })
+    })
+}
+
+#[launch]
+fn rocket() -> _ {
+    // Enable tracing subscriber
+    tracing_subscriber::fmt().init();
+
+    gateway()
+}
+
+// Build the gateway server.
+fn gateway() -> Rocket<Build> {
+    rocket::build()
+        .mount(
+            "/",
+            routes![
+                index,
+                device_details,
+                devices_discovery,
+                discovery_stream,
+                discovery_preview,
+                register_device,
+                refresh_properties,
+                remove_device,
+                name_device,
+                clear_devices,
+                device_request,
+                devices_batch,
+                create_scene,
+                scenes,
+                apply_scene,
+                state_events,
+                reset_route,
+                resync_route,
+                ping_device,
+                identify_device,
+                reboot_device,
+                device_logs,
+                gateway_logs,
+                api_devices,
+                export_devices,
+                import_devices,
+                health,
+                login_page,
+                login,
+                logout,
+                gateway_metrics,
+                debug_schema
+            ],
+        )
+        .manage(DiscoveryLock(Arc::new(Mutex::new(()))))
+        .manage(LogBuffer::default())
+        .manage(Metrics::default())
+        .manage(StateEvents::default())
+        .attach(config::stage())
+        .attach(csrf::stage())
+        .attach(database::stage())
+        .attach(Template::fairing())
+        .attach(periodic_discovery())
+        .register("/", error::catchers())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use ascot_library::device::{DeviceData, DeviceKind};
+    use ascot_library::input::{Input, Inputs, InputsData};
+    use ascot_library::route::{RestKind, RouteConfig, RouteData, Routes};
+    use ascot_library::MiniString;
+
+    use rocket::http::{ContentType, Header, Status};
+
+    use crate::config::{HttpConfig, RetryConfig};
+    use crate::csrf::CSRF_COOKIE;
+    use crate::database::controls::StateControls;
+    use crate::database::device::Device;
+    use crate::database::query::{
+        insert_boolean_input, insert_enum_input, insert_hazard, insert_hazard_definition,
+        is_db_empty, promote_address, update_last_retrieved,
+    };
+    use crate::database::test_connection;
+    use crate::request::{serve_body, serve_once};
+    use crate::testing::{
+        auth_client, client, configured_client, connection, local_device, post_form, put_form,
+        submit_form, with_csrf,
+    };
+
+    #[test]
+    fn paths_escaping_the_device_are_rejected() {
+        for path in [
+            "//evil.example/x",
+            "http://evil.example/x",
+            "/a/../../b",
+            "..",
+            "\\\\evil.example\\x",
+            "evil.example:80/x",
+            "javascript:alert(1)",
+        ] {
+            assert!(local_path(path).is_none(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn local_paths_are_kept() {
+        assert_eq!(local_path("/light").as_deref(), Some("/light"));
+        assert_eq!(local_path("/a/b..c/").as_deref(), Some("/a/b..c/"));
+    }
+
+    #[test]
+    fn paths_start_with_a_single_slash() {
+        assert_eq!(local_path("light").as_deref(), Some("/light"));
+        assert_eq!(local_path("").as_deref(), Some("/"));
+        assert!(local_path("//light").is_none());
+    }
+
+    #[rocket::async_test]
+    async fn reboots_must_be_confirmed() {
+        let client = client().await;
+        let (port, _) = serve_once(200).await;
+        let id = local_device(&client, port, REBOOT_ROUTE).await;
+
+        let response = post_form(&client, &format!("/device/{id}/reboot"), "confirm=false").await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn identify_invokes_the_advertised_route() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, IDENTIFY_ROUTE).await;
+
+        let response = post_form(&client, &format!("/device/{id}/identify"), "").await;
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(received.await.unwrap(), "PUT /light/identify HTTP/1.1");
+    }
+
+    #[rocket::async_test]
+    async fn device_requests_are_counted() {
+        let client = client().await;
+        let (port, received) = serve_once(500).await;
+        let id = local_device(&client, port, IDENTIFY_ROUTE).await;
+
+        post_form(&client, &format!("/device/{id}/identify"), "").await;
+        received.await.unwrap();
+
+        let response = client.get("/metrics").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let text = response.into_string().await.unwrap();
+        for line in [
+            "ascot_device_requests_total 1",
+            "ascot_device_request_failures_total 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{}", line);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn address_reachability_is_saved() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, IDENTIFY_ROUTE).await;
+
+        // Only the first loopback address is listened on, and the second one
+        // is contacted first.
+        let mut db = connection(&client).await;
+        insert_address(&mut db, "127.0.0.2".into(), id)
+            .await
+            .unwrap();
+        promote_address(&mut db, "127.0.0.2".into(), 1, id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let response = post_form(&client, &format!("/device/{id}/identify"), "").await;
+        assert_eq!(response.status(), Status::SeeOther);
+        received.await.unwrap();
+
+        let mut db = connection(&client).await;
+        let addresses = select_device_addresses(&mut db, id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|address| (address.address, address.reachable))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addresses,
+            [
+                ("127.0.0.2".to_string(), false),
+                ("127.0.0.1".to_string(), true)
+            ]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn actions_not_advertised_are_not_found() {
+        let client = client().await;
+        let (port, _) = serve_once(200).await;
+        let id = local_device(&client, port, "/on").await;
+
+        let response = post_form(&client, &format!("/device/{id}/identify"), "").await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    fn scheme_of(properties: &[(&str, &str)]) -> String {
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "light",
+            "light.local.",
+            "127.0.0.1",
+            3000,
+            properties,
+        )
+        .unwrap();
+        device_scheme(info.get_properties()).to_owned()
+    }
+
+    #[test]
+    fn secure_devices_use_https() {
+        assert_eq!(scheme_of(&[("secure", "true")]), "https");
+        assert_eq!(scheme_of(&[("secure", "1"), ("scheme", "http")]), "https");
+        assert_eq!(scheme_of(&[("secure", "")]), "https");
+        assert_eq!(scheme_of(&[("scheme", "https")]), "https");
+    }
+
+    #[test]
+    fn bogus_schemes_fall_back_to_http() {
+        assert_eq!(scheme_of(&[("scheme", "javascript")]), "http");
+        assert_eq!(scheme_of(&[("scheme", "file")]), "http");
+        assert_eq!(scheme_of(&[("scheme", "")]), "http");
+        assert_eq!(scheme_of(&[("scheme", "HTTPS")]), "https");
+    }
+
+    #[test]
+    fn devices_use_http_by_default() {
+        assert_eq!(scheme_of(&[]), "http");
+        assert_eq!(scheme_of(&[("secure", "false")]), "http");
+        assert_eq!(
+            scheme_of(&[("secure", "false"), ("scheme", "https")]),
+            "https"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn property_keys_are_kept_whole() {
+        let (_client, mut db) = test_connection().await;
+        let id = insert_device(&mut db, "light", 3000, "http", "/")
+            .await
+            .unwrap();
+
+        let key = "k".repeat(20);
+        let value = "v".repeat(20);
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "light",
+            "light.local.",
+            "127.0.0.1",
+            3000,
+            &[(key.as_str(), value.as_str())][..],
+        )
+        .unwrap();
+        let text_limits = TextLimits {
+            name: 10,
+            description: 10,
+            ..TextLimits::default()
+        };
+        assert!(
+            save_properties(&mut db, info.get_properties(), id, &text_limits)
+                .await
+                .is_ok()
+        );
+
+        let property: (String, String) = sqlx::query_as("SELECT key, value FROM properties")
+            .fetch_one(&mut **db)
+            .await
+            .unwrap();
+        assert_eq!(property, (key, format!("{}…", "v".repeat(9))));
+    }
+
+    #[rocket::async_test]
+    async fn failed_refreshes_keep_the_old_properties() {
+        let (_client, mut db) = test_connection().await;
+        let id = insert_device(&mut db, "light", 3000, "http", "/")
+            .await
+            .unwrap();
+        insert_property(&mut db, "version", "1", id).await.unwrap();
+
+        // Make every new property fail to be saved.
+        sqlx::query(
+            "CREATE TRIGGER no_properties BEFORE INSERT ON properties BEGIN SELECT RAISE(ABORT, 'full'); END",
+        )
+        .execute(&mut **db)
+        .await
+        .unwrap();
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "light",
+            "light.local.",
+            "127.0.0.1",
+            3000,
+            &[("version", "2")][..],
+        )
+        .unwrap();
+        assert!(
+            replace_properties(&mut db, info.get_properties(), id, &TextLimits::default())
+                .await
+                .is_err()
+        );
+
+        let values: Vec<String> = sqlx::query_scalar("SELECT value FROM properties")
+            .fetch_all(&mut **db)
+            .await
+            .unwrap();
+        assert_eq!(values, ["1"]);
+    }
+
+    #[rocket::async_test]
+    async fn only_discovered_devices_are_refreshed() {
+        let client = client().await;
+        let mut db = connection(&client).await;
+        let id = insert_manual_device(&mut db, 3000, "http", "/")
+            .await
+            .unwrap();
+        drop(db);
+
+        let response = put_form(&client, &format!("/device/{id}/properties/refresh"), "").await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn sent_values_are_stored() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, "/on/<state>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        insert_boolean_input(&mut db, "state", false, false, route_id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let response = put_form(
+            &client,
+            &format!("/device/{id}"),
+            &format!("checkboxes[state].route={route_id}&checkboxes[state].val=true"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(received.await.unwrap(), "PUT /light/on/true HTTP/1.1");
+
+        let mut db = connection(&client).await;
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert_eq!(inputs.values()["state"], "true");
+    }
+
+    #[rocket::async_test]
+    async fn reset_sends_and_stores_default_values() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, "/level/<brightness>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        sqlx::query(
+            "INSERT INTO rangesu64(name, min, max, step, default_value, value, route_id) VALUES ('brightness', 0, 10, 1, 5, 5, $1)",
+        )
+        .bind(route_id)
+        .execute(&mut **db)
+        .await
+        .unwrap();
+        drop(db);
+
+        // Move the slider away from its default value.
+        let response = put_form(
+            &client,
+            &format!("/device/{id}"),
+            &format!("slidersu64[brightness].route={route_id}&slidersu64[brightness].val=8"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(received.await.unwrap(), "PUT /light/level/8 HTTP/1.1");
+
+        // The device now listens on another port.
+        let (port, received) = serve_once(200).await;
+        let mut db = connection(&client).await;
+        sqlx::query("UPDATE devices SET port = $1 WHERE id = $2")
+            .bind(port)
+            .bind(id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+        drop(db);
+
+        let response = post_form(
+            &client,
+            &format!("/device/{id}/route/{route_id}/reset"),
+            "confirm=false",
+        )
+        .await;
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(received.await.unwrap(), "PUT /light/level/5 HTTP/1.1");
+
+        let mut db = connection(&client).await;
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert_eq!(inputs.values()["brightness"], "5");
+    }
+
+    #[rocket::async_test]
+    async fn stored_values_are_pushed() {
+        let client = client().await;
+        let (port, _) = serve_once(200).await;
+        let id = local_device(&client, port, "/on/<state>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        insert_boolean_input(&mut db, "state", false, false, route_id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let mut changes = client.rocket().state::<StateEvents>().unwrap().subscribe();
+        let response = put_form(
+            &client,
+            &format!("/device/{id}"),
+            &format!("checkboxes[state].route={route_id}&checkboxes[state].val=true"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::SeeOther);
+
+        assert_eq!(
+            changes.try_recv().unwrap(),
+            StateChange {
+                device_id: id,
+                route_id,
+                name: "state".into(),
+                value: "true".into(),
+            }
+        );
+    }
+
+    #[rocket::async_test]
+    async fn stateless_routes_keep_no_value() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, "/on/<state>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        sqlx::query("UPDATE routes SET stateless = TRUE WHERE id = $1")
+            .bind(route_id)
+            .execute(&mut **db)
+            .await
+            .unwrap();
+        insert_boolean_input(&mut db, "state", false, false, route_id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let form = format!("checkboxes[state].route={route_id}&checkboxes[state].val=true");
+        let response = put_form(&client, &format!("/device/{id}"), &form).await;
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(received.await.unwrap(), "PUT /light/on/true HTTP/1.1");
+
+        let mut db = connection(&client).await;
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert_eq!(inputs.values()["state"], "false");
+    }
+
+    #[rocket::async_test]
+    async fn stateful_buttons_flip_their_state() {
+        let client = client().await;
+        let id = local_device(&client, 1, "/toggle").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        insert_boolean_input(&mut db, "/toggle", false, false, route_id)
+            .await
+            .unwrap();
+        drop(db);
+
+        for expected in ["true", "false"] {
+            // Each device answers a single request.
+            let (port, received) = serve_once(200).await;
+            let mut db = connection(&client).await;
+            sqlx::query("UPDATE devices SET port = $1 WHERE id = $2")
+                .bind(port)
+                .bind(id)
+                .execute(&mut **db)
+                .await
+                .unwrap();
+            drop(db);
+
+            let form = format!("buttons[toggle].route={route_id}&buttons[toggle].val=true");
+            let response = put_form(&client, &format!("/device/{id}"), &form).await;
+            assert_eq!(response.status(), Status::SeeOther);
+            assert_eq!(received.await.unwrap(), "PUT /light/toggle HTTP/1.1");
+
+            let mut db = connection(&client).await;
+            let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+            assert_eq!(inputs.values()["/toggle"], expected);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn device_responses_are_kept() {
+        let client = client().await;
+        let (port, _) = serve_body("application/json", br#"{"state": false}"#.to_vec()).await;
+        let id = local_device(&client, port, "/on/<state>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        insert_boolean_input(&mut db, "state", false, false, route_id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let form = format!("checkboxes[state].route={route_id}&checkboxes[state].val=true");
+        let response = put_form(&client, &format!("/device/{id}"), &form).await;
+        assert_eq!(response.status(), Status::SeeOther);
+
+        // The device has answered with its state, which is kept.
+        let mut db = connection(&client).await;
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert_eq!(inputs.values()["state"], "false");
+        let last_response = select_last_response(&mut db, id).await.unwrap().unwrap();
+        assert_eq!(last_response.status, 200);
+        drop(db);
+
+        let response = client.get(format!("/device/{id}")).dispatch().await;
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains("Last response"));
+    }
+
+    #[rocket::async_test]
+    async fn device_errors_are_bad_gateways() {
+        let client = client().await;
+        let (port, _) = serve_once(500).await;
+        let id = local_device(&client, port, "/toggle").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        drop(db);
+
+        let form = format!("buttons[toggle].route={route_id}&buttons[toggle].val=true");
+        let response = put_form(&client, &format!("/device/{id}"), &form).await;
+        assert_eq!(response.status(), Status::BadGateway);
+        let body = response.into_string().await.unwrap();
+        assert!(body.contains("status 500"));
+    }
+
+    #[rocket::async_test]
+    async fn pings_save_reachability() {
+        let client = client().await;
+        let data = DeviceData {
+            kind: DeviceKind::Light,
+            main_route: MiniString::new("/light").unwrap(),
+            routes: Routes::init(),
+        };
+        let (port, _) = serve_body("application/json", serde_json::to_vec(&data).unwrap()).await;
+        let id = local_device(&client, port, "/on").await;
+
+        let mut db = connection(&client).await;
+        update_address_reachable(&mut db, "127.0.0.1", false, id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let response = client.get(format!("/device/{id}/ping")).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let pings: Value = response.into_json().await.unwrap();
+        assert_eq!(pings[0]["address"], "127.0.0.1");
+        assert_eq!(pings[0]["reachable"], true);
+        assert!(pings[0]["latency"].is_u64());
+
+        let mut db = connection(&client).await;
+        let address = &select_device_addresses(&mut db, id).await.unwrap()[0];
+        assert!(address.reachable);
+        assert!(address.latency.is_some());
+        drop(db);
+
+        let response = client.get("/device/999/ping").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn hazards_must_be_confirmed() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, "/on/<state>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        insert_boolean_input(&mut db, "state", false, false, route_id)
+            .await
+            .unwrap();
+        insert_hazard(&mut db, 0, "Safety", route_id, id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let form = format!("checkboxes[state].route={route_id}&checkboxes[state].val=true");
+        let response = put_form(&client, &format!("/device/{id}"), &form).await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = put_form(
+            &client,
+            &format!("/device/{id}"),
+            &format!("{form}&confirm=true"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(received.await.unwrap(), "PUT /light/on/true HTTP/1.1");
+    }
+
+    #[rocket::async_test]
+    async fn stale_device_details_are_marked() {
+        let client = client().await;
+        let id = local_device(&client, 3000, "/on").await;
+
+        // Never retrieved data are stale.
+        let body = client
+            .get(format!("/device/{id}"))
+            .dispatch()
+            .await
+            .into_string()
+            .await
+            .unwrap();
+        assert!(body.contains("Stale"));
+
+        let mut db = connection(&client).await;
+        update_last_retrieved(&mut db, id, time::now())
+            .await
+            .unwrap();
+        drop(db);
+
+        let body = client
+            .get(format!("/device/{id}"))
+            .dispatch()
+            .await
+            .into_string()
+            .await
+            .unwrap();
+        assert!(!body.contains("Stale"));
+    }
+
+    #[rocket::async_test]
+    async fn device_details_are_shown() {
+        let client = client().await;
+        let id = local_device(&client, 3000, "/on/<state>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        insert_boolean_input(&mut db, "state", false, true, route_id)
+            .await
+            .unwrap();
+        insert_hazard(&mut db, 0, "Safety", route_id, id)
+            .await
+            .unwrap();
+        insert_hazard_definition(&mut db, 0, "Fire Hazard", "An Hazard fire", "Safety")
+            .await
+            .unwrap();
+        drop(db);
+
+        let response = client.get(format!("/device/{id}")).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().await.unwrap();
+        for text in ["127.0.0.1", "Fire Hazard", "state", "true"] {
+            assert!(body.contains(text), "{}", text);
+        }
+
+        // Each route is shown in its own box, with its name escaped.
+        let route = body
+            .split(&format!("data-route-id=\"{route_id}\""))
+            .nth(1)
+            .expect("route box");
+        let name = format!(
+            "<p class=\"has-text-weight-semibold\">{}</p>",
+            rocket_dyn_templates::handlebars::html_escape("/on/<state>")
+        );
+        assert!(route.contains(&name), "{}", name);
+
+        let response = client.get("/device/999").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn f64_sliders_are_stored() {
+        let (_client, mut db) = test_connection().await;
+        let id = insert_device(&mut db, "light", 3000, "http", "/")
+            .await
+            .unwrap();
+
+        let mut inputs = Inputs::init();
+        inputs.add(Input::rangef64("brightness", (0., 20., 0.1, 5.)));
+        let mut routes = Routes::init();
+        routes.add(RouteConfig {
+            rest_kind: RestKind::Put,
+            hazards: HazardsData::init(),
+            data: RouteData {
+                name: MiniString::new("/on/<brightness>").unwrap(),
+                description: None,
+                stateless: false,
+                inputs: InputsData::from_inputs(&inputs).unwrap(),
+            },
+        });
+        let mut device = Device {
+            metadata: select_device_metadata_by_id(&mut db, id)
+                .await
+                .unwrap()
+                .unwrap(),
+            addresses: Vec::new(),
+            properties: Vec::new(),
+            data: DeviceData {
+                kind: DeviceKind::Light,
+                main_route: MiniString::new("/light").unwrap(),
+                routes,
+            },
+            state_controls: StateControls::default(),
+            stale: false,
+            reachable: true,
+            last_seen: None,
+        };
+        device.insert_routes(&mut db).await.unwrap();
+
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert_eq!(inputs.values()["brightness"], "5");
+    }
+
+    #[rocket::async_test]
+    async fn devices_are_saved_once_per_fullname() {
+        let (_client, mut db) = test_connection().await;
+        let mut discovery = Discovery::default();
+        for address in ["192.168.1.2", "fe80::2"] {
+            let info = ServiceInfo::new(SERVICE_TYPE, "light", "light.local.", address, 3000, None)
+                .unwrap();
+            discovery.resolve(info);
+        }
+
+        assert!(save_devices(
+            &mut db,
+            discovery.resolved,
+            DiscoveryMode::Replace,
+            AddressFilter::Both,
+            &TextLimits::default(),
+            &LogBuffer::default(),
+        )
+        .await
+        .is_ok());
+
+        let ids: Vec<u16> = sqlx::query_scalar("SELECT id FROM devices")
+            .fetch_all(&mut **db)
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 1);
+        let addresses = select_device_addresses(&mut db, ids[0]).await.unwrap();
+        assert_eq!(addresses.len(), 2);
+    }
+
+    #[rocket::async_test]
+    async fn failed_discoveries_keep_the_stored_devices() {
+        let (_client, mut db) = test_connection().await;
+        let fullname = format!("light.{SERVICE_TYPE}");
+        insert_device(&mut db, &fullname, 3000, DEFAULT_SCHEME, WELL_KNOWN_URI)
+            .await
+            .unwrap();
+
+        // Make every new property fail to be saved, after the stored
+        // devices have been cleared.
+        sqlx::query(
+            "CREATE TRIGGER no_properties BEFORE INSERT ON properties BEGIN SELECT RAISE(ABORT, 'full'); END",
+        )
+        .execute(&mut **db)
+        .await
+        .unwrap();
+        let mut discovery = Discovery::default();
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "fan",
+            "fan.local.",
+            "192.168.1.3",
+            3000,
+            &[("version", "1")][..],
+        )
+        .unwrap();
+        discovery.resolve(info);
+
+        assert!(store_discovery(
+            &mut db,
+            discovery,
+            DiscoveryMode::Replace,
+            &GatewayConfig::default(),
+            &LogBuffer::default(),
+        )
+        .await
+        .is_err());
+
+        let fullnames: Vec<String> = sqlx::query_scalar("SELECT fullname FROM devices")
+            .fetch_all(&mut **db)
+            .await
+            .unwrap();
+        assert_eq!(fullnames, [fullname]);
+    }
+
+    #[rocket::async_test]
+    async fn previews_mark_stored_devices() {
+        let (_client, mut db) = test_connection().await;
+        insert_device(
+            &mut db,
+            &format!("light.{SERVICE_TYPE}"),
+            3000,
+            DEFAULT_SCHEME,
+            WELL_KNOWN_URI,
+        )
+        .await
+        .unwrap();
+
+        let mut discovery = Discovery::default();
+        for name in ["light", "fan"] {
+            let info =
+                ServiceInfo::new(SERVICE_TYPE, name, "host.local.", "192.168.1.2", 3000, None)
+                    .unwrap();
+            discovery.resolve(info);
+        }
+
+        let devices = mark_stored(&mut db, &discovery).await.unwrap();
+        let stored = devices
+            .iter()
+            .map(|(device, stored)| (device.info.get_fullname(), *stored))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stored,
+            [
+                (format!("light.{SERVICE_TYPE}").as_str(), true),
+                (format!("fan.{SERVICE_TYPE}").as_str(), false)
+            ]
+        );
+        assert_eq!(count_devices(&mut db).await.unwrap(), 1);
+    }
+
+    #[test]
+    fn only_new_devices_are_recorded() {
+        let logs = LogBuffer::default();
+        let mut discovery = Discovery::default();
+        let event = |address| {
+            let info = ServiceInfo::new(SERVICE_TYPE, "light", "light.local.", address, 3000, None)
+                .unwrap();
+            ServiceEvent::ServiceResolved(info)
+        };
+
+        let device = discovery.record(event("192.168.1.2"), &logs).unwrap();
+        assert_eq!(device.addresses.len(), 1);
+        assert!(discovery.record(event("fe80::2"), &logs).is_none());
+        assert_eq!(discovery.resolved[0].addresses.len(), 2);
+    }
+
+    #[rocket::async_test]
+    async fn skipped_devices_are_logged() {
+        let (_client, mut db) = test_connection().await;
+        let mut discovery = Discovery::default();
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            "light",
+            "light.local.",
+            "192.168.1.2",
+            3000,
+            None,
+        )
+        .unwrap();
+        discovery.resolve(info);
+
+        let logs = LogBuffer::default();
+        assert!(save_devices(
+            &mut db,
+            discovery.resolved,
+            DiscoveryMode::Replace,
+            AddressFilter::Ipv6Only,
+            &TextLimits::default(),
+            &logs,
+        )
+        .await
+        .is_ok());
+
+        assert!(is_db_empty(&mut db).await.unwrap());
+        let entries = logs.entries();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].message.contains("light"));
+    }
+
+    #[rocket::async_test]
+    async fn select_values_must_be_among_the_options() {
+        let client = client().await;
+        let id = local_device(&client, 3000, "/mode/<mode>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        let options = ["eco".to_string(), "normal".to_string()];
+        insert_enum_input(&mut db, "mode", &options, "eco", "eco", route_id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let response = put_form(
+            &client,
+            &format!("/device/{id}"),
+            &format!("selects[mode].route={route_id}&selects[mode].val=turbo"),
+        )
+        .await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn malformed_dumps_are_rejected() {
+        let client = client().await;
+
+        for body in [
+            "{\"version\": 1".to_string(),
+            json!({ "version": DUMP_VERSION + 1, "devices": [] }).to_string(),
+        ] {
+            let response = with_csrf(client.post("/api/import"))
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::BadRequest);
+        }
+
+        let response = with_csrf(client.post("/api/import?mode=merge"))
+            .header(ContentType::JSON)
+            .body(json!({ "version": DUMP_VERSION, "devices": [] }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::SeeOther);
+    }
+
+    #[rocket::async_test]
+    async fn unknown_devices_are_not_found() {
+        let client = client().await;
+
+        let response = put_form(&client, "/device/9999", "").await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn flooded_devices_refuse_requests() {
+        let client = configured_client(|figment| {
+            figment
+                .merge(("gateway.rate_limit.burst", 1))
+                .merge(("gateway.rate_limit.per_second", 0.001))
+        })
+        .await;
+        let id = local_device(&client, 3000, "/on").await;
+
+        let response = put_form(&client, &format!("/device/{id}"), "").await;
+        assert_ne!(response.status(), Status::TooManyRequests);
+        let response = put_form(&client, &format!("/device/{id}"), "").await;
+        assert_eq!(response.status(), Status::TooManyRequests);
+
+        // Unknown devices are still not found.
+        let response = put_form(&client, "/device/9999", "").await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    // Send a batch of control changes, returning the outcome of each one.
+    async fn send_batch(client: &Client, batch: Value) -> Vec<Value> {
+        let response = with_csrf(client.put("/devices/batch"))
+            .header(ContentType::JSON)
+            .body(batch.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn batches_report_each_change() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, "/on").await;
+
+        let outcomes = send_batch(
+            &client,
+            json!([
+                { "device_id": id, "route": "/on" },
+                { "device_id": id, "route": "/off" },
+                { "device_id": 9999, "route": "/on" },
+                { "device_id": id, "route": "/on", "value": { "missing": true } },
+            ]),
+        )
+        .await;
+
+        assert_eq!(received.await.unwrap(), "PUT /light/on HTTP/1.1");
+        assert_eq!(outcomes[0]["status"], 200);
+        assert_eq!(outcomes[0]["error"], Value::Null);
+        assert_eq!(outcomes[1]["error"], "Route not advertised by the device");
+        assert_eq!(outcomes[2]["error"], "Device not found");
+        assert_eq!(outcomes[3]["error"], "Invalid value for the route inputs");
+    }
+
+    #[rocket::async_test]
+    async fn batches_save_only_accepted_values() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        // Nothing listens on a port released right after being bound.
+        let dead_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let ids = [
+            local_device(&client, port, "/on/<state>").await,
+            local_device(&client, dead_port, "/on/<state>").await,
+        ];
+
+        let mut db = connection(&client).await;
+        let mut route_ids = Vec::new();
+        for id in ids {
+            let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+            insert_boolean_input(&mut db, "state", false, false, route_id)
+                .await
+                .unwrap();
+            route_ids.push(route_id);
+        }
+        drop(db);
+
+        let outcomes = send_batch(
+            &client,
+            json!([
+                { "device_id": ids[0], "route": "/on/<state>", "value": true },
+                { "device_id": ids[1], "route": "/on/<state>", "value": { "state": true } },
+            ]),
+        )
+        .await;
+        assert_eq!(received.await.unwrap(), "PUT /light/on/true HTTP/1.1");
+        assert_eq!(outcomes[0]["status"], 200);
+        assert_eq!(outcomes[1]["error"], "The device has not answered");
+
+        let mut db = connection(&client).await;
+        for (route_id, value) in route_ids.into_iter().zip(["true", "false"]) {
+            let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+            assert_eq!(inputs.values()["state"], value);
+        }
+    }
+
+    // Create a scene, returning the created scene.
+    async fn post_scene(client: &Client, scene: Value) -> Value {
+        let response = with_csrf(client.post("/scenes"))
+            .header(ContentType::JSON)
+            .body(scene.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        response.into_json().await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn scenes_are_created_and_listed() {
+        let client = client().await;
+        let id = local_device(&client, 3000, "/on").await;
+
+        let scene = post_scene(
+            &client,
+            json!({ "name": " Night ", "items": [{ "device_id": id, "route": "/on" }] }),
+        )
+        .await;
+        assert_eq!(scene["name"], "Night");
+
+        for invalid in [
+            json!({ "name": "Night", "items": [{ "device_id": id, "route": "/on" }] }),
+            json!({ "name": "Day", "items": [{ "device_id": id, "route": "/off" }] }),
+            json!({ "name": "Day", "items": [] }),
+            json!({ "name": " ", "items": [{ "device_id": id, "route": "/on" }] }),
+        ] {
+            let response = with_csrf(client.post("/scenes"))
+                .header(ContentType::JSON)
+                .body(invalid.to_string())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::BadRequest, "{invalid}");
+        }
+
+        let response = client.get("/scenes").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let scenes: Value = response.into_json().await.unwrap();
+        assert_eq!(
+            scenes,
+            json!([{
+                "id": scene["id"],
+                "name": "Night",
+                "items": [{ "device_id": id, "route": "/on", "value": null, "confirm": false }],
+            }])
+        );
+    }
+
+    #[rocket::async_test]
+    async fn applied_scenes_send_their_changes() {
+        let client = client().await;
+        let (port, received) = serve_once(200).await;
+        let id = local_device(&client, port, "/on/<state>").await;
+
+        let mut db = connection(&client).await;
+        let route_id = select_device_routes_by_id(&mut db, id).await.unwrap()[0].id;
+        insert_boolean_input(&mut db, "state", false, false, route_id)
+            .await
+            .unwrap();
+        drop(db);
+
+        let scene = post_scene(
+            &client,
+            json!({
+                "name": "Day",
+                "items": [{ "device_id": id, "route": "/on/<state>", "value": true }],
+            }),
+        )
+        .await;
+
+        let response = with_csrf(client.put(format!("/scenes/{}/apply", scene["id"])))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let outcomes: Vec<Value> = response.into_json().await.unwrap();
+        assert_eq!(outcomes[0]["status"], 200);
+        assert_eq!(received.await.unwrap(), "PUT /light/on/true HTTP/1.1");
+
+        let mut db = connection(&client).await;
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        assert_eq!(inputs.values()["state"], "true");
+        drop(db);
+
+        let response = with_csrf(client.put("/scenes/9999/apply")).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn index_contacts_only_the_shown_page() {
+        let client = client().await;
+        let mut received = Vec::new();
+        for _ in 0..5 {
+            let (port, handle) = serve_once(200).await;
+            local_device(&client, port, "/on").await;
+            received.push(handle);
+        }
+
+        let response = client.get("/?page=2&per_page=2").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        for (index, handle) in received.into_iter().enumerate() {
+            if (2..4).contains(&index) {
+                handle.await.unwrap();
+            } else {
+                // Devices of the other pages are never contacted.
+                assert!(!handle.is_finished(), "device {index}");
+                handle.abort();
+            }
+        }
+    }
+
+    #[rocket::async_test]
+    async fn waiting_for_events_leaves_the_runtime_free() {
+        let config = DiscoveryConfig::default();
+        let (sender, receiver) = flume::unbounded();
+
+        // Tests run on a single worker, hence the event can be sent only
+        // while the search is awaiting it.
+        rocket::tokio::spawn(async move {
+            rocket::tokio::time::sleep(Duration::from_millis(50)).await;
+            sender
+                .send(ServiceEvent::SearchStarted(SERVICE_TYPE.into()))
+                .unwrap();
+        });
+
+        let event = next_event(&receiver, &config, Instant::now()).await;
+        assert!(matches!(event, Some(ServiceEvent::SearchStarted(_))));
+    }
+
+    #[rocket::async_test]
+    async fn deadline_covers_every_service_type() {
+        let config: DiscoveryConfig = json::from_value(json!({
+            "service_types": [SERVICE_TYPE, "_ascot-secure._tcp.local."],
+            "timeout": 1000,
+            "deadline": 200,
+        }))
+        .unwrap();
+        // Senders are kept, so that receivers wait for events.
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..2).map(|_| flume::unbounded()).unzip();
+
+        let start = Instant::now();
+        let discovery = search_devices(receivers, &config, &LogBuffer::default()).await;
+
+        assert!(start.elapsed() < Duration::from_millis(350));
+        assert!(discovery.resolved.is_empty());
+        drop(senders);
+    }
+
+    #[rocket::async_test]
+    async fn debug_schema_reports_the_latest_migration() {
+        let client = configured_client(|figment| figment.merge(("gateway.debug", true))).await;
+
+        let response = client.get("/api/debug/schema").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let schema: Value = response.into_json().await.unwrap();
+
+        let latest = sqlx::migrate!("db/migrations")
+            .iter()
+            .map(|migration| migration.version)
+            .max();
+        assert_eq!(schema["version"], json!(latest));
+        let devices = schema["tables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|table| table["name"] == "devices")
+            .unwrap();
+        assert_eq!(devices["columns"][0], "id");
+    }
+
+    #[rocket::async_test]
+    async fn debug_schema_needs_the_debug_option() {
+        let client = client().await;
+
+        let response = client.get("/api/debug/schema").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn clearing_devices_empties_the_database() {
+        let client = client().await;
+        local_device(&client, 3000, "/on").await;
+
+        let response = submit_form(client.delete("/devices"), "confirm=false").await;
+        assert_eq!(response.status(), Status::BadRequest);
+
+        let response = submit_form(client.delete("/devices"), "confirm=true").await;
+        assert_eq!(response.status(), Status::SeeOther);
+
+        let mut db = connection(&client).await;
+        assert_eq!(count_devices(&mut db).await.unwrap(), 0);
+    }
+
+    #[rocket::async_test]
+    async fn changes_need_a_logged_in_user() {
+        let client = auth_client(false).await;
+
+        // Pages are shown, but nothing can be changed.
+        assert_eq!(client.get("/").dispatch().await.status(), Status::Ok);
+        let response = submit_form(client.delete("/devices"), "confirm=true").await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client.put("/?mode=merge").dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .post("/login")
+            .header(ContentType::Form)
+            .body("username=admin&password=secret")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = post_form(&client, "/login", "username=admin&password=wrong").await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = submit_form(client.delete("/devices"), "confirm=true").await;
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = post_form(&client, "/login", "username=admin&password=secret").await;
+        assert_eq!(response.status(), Status::SeeOther);
+        let response = submit_form(client.delete("/devices"), "confirm=true").await;
+        assert_eq!(response.status(), Status::SeeOther);
+
+        submit_form(client.post("/logout"), "").await;
+        let response = submit_form(client.delete("/devices"), "confirm=true").await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    async fn basic_authentication_is_accepted() {
+        let client = auth_client(false).await;
+
+        for (credentials, status) in [
+            // admin:secret
+            ("YWRtaW46c2VjcmV0", Status::SeeOther),
+            // admin:wrong
+            ("YWRtaW46d3Jvbmc=", Status::Unauthorized),
+        ] {
+            let request = client
+                .delete("/devices")
+                .header(Header::new("Authorization", format!("Basic {credentials}")));
+            let response = submit_form(request, "confirm=true").await;
+            assert_eq!(response.status(), status);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn pages_can_need_a_logged_in_user() {
+        let client = auth_client(true).await;
+
+        assert_eq!(
+            client.get("/").dispatch().await.status(),
+            Status::Unauthorized
+        );
+        assert_eq!(client.get("/login").dispatch().await.status(), Status::Ok);
+
+        post_form(&client, "/login", "username=admin&password=secret").await;
+        assert_eq!(client.get("/").dispatch().await.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn changes_need_the_csrf_token() {
+        let client = client().await;
+
+        let response = client
+            .delete("/devices")
+            .header(ContentType::Form)
+            .body("confirm=true")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        // Pages issue the token, which forms send back as a hidden field.
+        let body = client
+            .get("/")
+            .dispatch()
+            .await
+            .into_string()
+            .await
+            .unwrap();
+        let token = client
+            .cookies()
+            .get_private(CSRF_COOKIE)
+            .unwrap()
+            .value()
+            .to_owned();
+        assert!(body.contains(&format!(r#"name="csrf" value="{token}""#)));
+        assert!(!body.contains("?csrf="));
+
+        let response = client
+            .post("/devices")
+            .header(ContentType::Form)
+            .body("_method=delete&csrf=invalid&confirm=true")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        // Tokens in query strings are ignored.
+        let response = client
+            .delete(format!("/devices?csrf={token}"))
+            .header(ContentType::Form)
+            .body("confirm=true")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .post("/devices")
+            .header(ContentType::Form)
+            .body(format!("_method=delete&csrf={token}&confirm=true"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::SeeOther);
+    }
+
+    #[rocket::async_test]
+    async fn everything_is_allowed_without_authentication() {
+        let client = client().await;
+
+        let response = submit_form(client.delete("/devices"), "confirm=true").await;
+        assert_eq!(response.status(), Status::SeeOther);
+    }
+
+    #[rocket::async_test]
+    async fn unreachable_devices_are_deleted_after_retries() {
+        let client = client().await;
+        // Nothing listens on a port released right after being bound.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        local_device(&client, port, "/on").await;
+
+        let mut db = connection(&client).await;
+        let device_client = DeviceClient::new(&HttpConfig::default()).unwrap();
+        let retry = RetryConfig {
+            max_retries: 2,
+            backoff: 0,
+        };
+
+        for remaining in [1, 0] {
+            let devices = Device::search_for_devices(
+                &mut db,
+                &device_client,
+                &retry,
+                &Metrics::default(),
+                &LogBuffer::default(),
+                &DeviceFilter::default(),
+                None,
+                Sort::Id,
+            )
+            .await
+            .unwrap();
+            assert!(devices.is_empty());
+            assert_eq!(count_devices(&mut db).await.unwrap(), remaining);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn devices_sending_invalid_data_are_reported() {
+        let client = client().await;
+        let (port, _) = serve_body("application/json", b"{\"kind\":".to_vec()).await;
+        let id = local_device(&client, port, "/on").await;
+
+        let mut db = connection(&client).await;
+        let device_client = DeviceClient::new(&HttpConfig::default()).unwrap();
+        let retry = RetryConfig {
+            max_retries: 1,
+            backoff: 0,
+        };
+        let logs = LogBuffer::default();
+        let devices = Device::search_for_devices(
+            &mut db,
+            &device_client,
+            &retry,
+            &Metrics::default(),
+            &logs,
+            &DeviceFilter::default(),
+            None,
+            Sort::Id,
+        )
+        .await
+        .unwrap();
+
+        // The device is running, hence it is kept.
+        assert!(devices.is_empty());
+        assert_eq!(count_devices(&mut db).await.unwrap(), 1);
+        let metadata = select_device_metadata_by_id(&mut db, id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(metadata
+            .retrieval_error
+            .is_some_and(|error| error.starts_with("Invalid device data")));
+        assert!(logs.entries()[0].message.contains("invalid data"));
+        drop(db);
+
+        let body = client
+            .get(format!("/device/{id}"))
+            .dispatch()
+            .await
+            .into_string()
+            .await
+            .unwrap();
+        assert!(body.contains("Invalid device data"));
+    }
 }
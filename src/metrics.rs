@@ -0,0 +1,117 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Counters of the gateway activity, exposed in the Prometheus text format.
+//
+// Clones share the same counters.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Metrics(Arc<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    // Devices found by discoveries.
+    devices_discovered: AtomicU64,
+    // Completed discoveries.
+    discovery_runs: AtomicU64,
+    // Requests sent to devices.
+    device_requests: AtomicU64,
+    // Requests to devices which have failed.
+    device_request_failures: AtomicU64,
+    // Devices deleted after being unreachable for too long.
+    devices_deleted: AtomicU64,
+}
+
+impl Metrics {
+    // Count a completed discovery, together with the devices it has found.
+    pub(crate) fn discovery(&self, devices: usize) {
+        self.0.discovery_runs.fetch_add(1, Ordering::Relaxed);
+        self.0
+            .devices_discovered
+            .fetch_add(devices as u64, Ordering::Relaxed);
+    }
+
+    // Count a request sent to a device, and whether it has failed.
+    pub(crate) fn device_request(&self, failed: bool) {
+        self.0.device_requests.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.0
+                .device_request_failures
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Count a device deleted after being unreachable for too long.
+    pub(crate) fn device_deleted(&self) {
+        self.0.devices_deleted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Render the counters in the Prometheus text format.
+    pub(crate) fn render(&self) -> String {
+        let counters = [
+            (
+                "devices_discovered",
+                "Devices found by discoveries",
+                &self.0.devices_discovered,
+            ),
+            (
+                "discovery_runs",
+                "Completed discoveries",
+                &self.0.discovery_runs,
+            ),
+            (
+                "device_requests",
+                "Requests sent to devices",
+                &self.0.device_requests,
+            ),
+            (
+                "device_request_failures",
+                "Requests to devices which have failed",
+                &self.0.device_request_failures,
+            ),
+            (
+                "devices_deleted",
+                "Devices deleted after being unreachable for too long",
+                &self.0.devices_deleted,
+            ),
+        ];
+
+        let mut text = String::new();
+        for (name, help, counter) in counters {
+            // Writing to a string cannot fail.
+            let _ = write!(
+                text,
+                "# HELP ascot_{name}_total {help}.\n\
+                 # TYPE ascot_{name}_total counter\n\
+                 ascot_{name}_total {}\n",
+                counter.load(Ordering::Relaxed)
+            );
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_rendered_as_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.clone().discovery(3);
+        metrics.device_request(false);
+        metrics.device_request(true);
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE ascot_devices_discovered_total counter",
+            "ascot_devices_discovered_total 3",
+            "ascot_discovery_runs_total 1",
+            "ascot_device_requests_total 2",
+            "ascot_device_request_failures_total 1",
+            "ascot_devices_deleted_total 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,693 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Method, RequestBuilder, Response, StatusCode, Url};
+
+use tracing::debug;
+
+use crate::config::{Credentials, HttpConfig};
+use crate::database::{Address, Metadata};
+
+// Characters escaped in a route input value: all but the unreserved ones,
+// so that a value always stays within its path segment.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// Errors building the HTTP client shared among all requests to devices.
+#[derive(Debug)]
+pub(crate) enum ClientError {
+    // The trusted certificate cannot be read.
+    Certificate(std::io::Error),
+    // A configured header has an invalid name or value.
+    Header(String),
+    // Credentials are configured for something other than `scheme://host`.
+    Credentials(String),
+    // The client cannot be built, or the trusted certificate is invalid.
+    Http(reqwest::Error),
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Certificate(e) => write!(f, "cannot read the trusted certificate: {}", e),
+            Self::Header(name) => write!(f, "invalid header {}", name),
+            Self::Credentials(key) => {
+                write!(f, "credentials not keyed by scheme://host: {}", key)
+            }
+            Self::Http(e) => e.fmt(f),
+        }
+    }
+}
+
+// HTTP client shared among all requests to devices.
+#[derive(Clone)]
+pub(crate) struct DeviceClient {
+    // Client.
+    client: Client,
+    // Time a device has to answer a request.
+    timeout: Duration,
+    // Credentials of protected devices, by `scheme://host`.
+    credentials: Arc<HashMap<String, Credentials>>,
+}
+
+impl DeviceClient {
+    pub(crate) fn new(config: &HttpConfig) -> Result<Self, ClientError> {
+        let mut builder = Client::builder()
+            .connect_timeout(config.connect_timeout())
+            .danger_accept_invalid_certs(config.accept_invalid_certs)
+            .user_agent(config.user_agent.as_str())
+            .default_headers(Self::headers(config)?);
+
+        if let Some(path) = &config.ca_certificate {
+            let pem = std::fs::read(path).map_err(ClientError::Certificate)?;
+            builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        }
+
+        let client = builder.build()?;
+
+        // Keys are compared with the scheme and host of each request.
+        if let Some(key) = config
+            .credentials
+            .keys()
+            .find(|key| Self::credentials_key(key).as_deref() != Some(key.as_str()))
+        {
+            return Err(ClientError::Credentials(key.clone()));
+        }
+
+        Ok(Self {
+            client,
+            timeout: config.timeout(),
+            credentials: Arc::new(config.credentials.clone()),
+        })
+    }
+
+    // Key of the credentials of the device at the given URL.
+    fn credentials_key(url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        Some(format!("{}://{}", url.scheme(), url.host_str()?))
+    }
+
+    // Headers sent with every request.
+    //
+    // Values are marked as sensitive, since they can contain secrets.
+    fn headers(config: &HttpConfig) -> Result<HeaderMap, ClientError> {
+        config
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let header = HeaderName::from_bytes(name.as_bytes()).ok();
+                let mut value = HeaderValue::from_str(value).ok();
+                if let Some(value) = value.as_mut() {
+                    value.set_sensitive(true);
+                }
+                header
+                    .zip(value)
+                    .ok_or_else(|| ClientError::Header(name.clone()))
+            })
+            .collect()
+    }
+
+    // Build a request which fails when a device does not answer in time.
+    #[inline]
+    pub(crate) fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        self.authenticate(self.client.request(method, url), url)
+            .timeout(self.timeout)
+    }
+
+    // Build a request to a stream.
+    //
+    // Once connected, a stream lasts until one of the two sides closes it,
+    // hence only the connection is timed out.
+    #[inline]
+    fn stream(&self, url: &str) -> RequestBuilder {
+        self.authenticate(self.client.get(url), url)
+    }
+
+    // Add the credentials of the device at the given URL, if any.
+    //
+    // Credentials are looked up by scheme and host, so that they are sent
+    // only to the configured devices.
+    fn authenticate(&self, builder: RequestBuilder, url: &str) -> RequestBuilder {
+        let credentials = Self::credentials_key(url).and_then(|key| self.credentials.get(&key));
+
+        match credentials {
+            Some(Credentials::Basic { username, password }) => {
+                builder.basic_auth(username, password.as_ref())
+            }
+            Some(Credentials::Bearer(token)) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+}
+
+// Device endpoint.
+//
+// It contains the information needed to contact a device.
+pub(crate) struct DeviceEndpoint {
+    // Client used to contact the device.
+    pub(crate) client: DeviceClient,
+    // Metadata.
+    pub(crate) metadata: Metadata,
+    // Addresses.
+    pub(crate) addresses: Vec<Address>,
+    // Main route.
+    pub(crate) main_route: String,
+}
+
+impl DeviceEndpoint {
+    // Build a request to a device route.
+    //
+    // Each route input is replaced by its value. Addresses which have
+    // answered the last time are tried first, in order of priority.
+    pub(crate) fn request(&self, route: &str, values: &HashMap<&str, String>) -> DeviceRequest {
+        let route = DeviceRequest::fill_route(route, values);
+        let targets = self
+            .addresses
+            .iter()
+            .filter(|a| a.reachable)
+            .chain(self.addresses.iter().filter(|a| !a.reachable))
+            .filter_map(|a| Some((a, a.address.parse::<IpAddr>().ok()?)))
+            .map(|(a, address)| RequestTarget {
+                address: a.address.clone(),
+                url: format!(
+                    "{}://{}{}{}",
+                    self.metadata.scheme,
+                    SocketAddr::new(address, self.metadata.port),
+                    self.main_route,
+                    route
+                ),
+                reachable: None,
+            })
+            .collect();
+
+        DeviceRequest {
+            client: self.client.clone(),
+            targets,
+        }
+    }
+}
+
+// Errors of a request to a device.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RequestError {
+    // No device address has answered.
+    Unreachable,
+    // The device has refused the gateway credentials.
+    Unauthorized,
+    // The device has answered with an error status.
+    Status(StatusCode),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unreachable => write!(f, "The device has not answered"),
+            Self::Unauthorized => write!(f, "The device has refused the gateway credentials"),
+            Self::Status(status) => {
+                write!(f, "The device has answered with status {}", status.as_u16())
+            }
+        }
+    }
+}
+
+// Device address a request is sent to.
+struct RequestTarget {
+    // Address.
+    address: String,
+    // Request URL.
+    url: String,
+    // Whether the address has answered, if it has been contacted.
+    reachable: Option<bool>,
+}
+
+// A REST request to a device route.
+pub(crate) struct DeviceRequest {
+    // Client used to send the request.
+    client: DeviceClient,
+    // Request targets, one for each device address.
+    targets: Vec<RequestTarget>,
+}
+
+impl DeviceRequest {
+    // Send the request to change a device state, through the HTTP method
+    // of the route.
+    #[inline]
+    pub(crate) async fn send(&mut self, method: Method) -> Result<Response, RequestError> {
+        self.request(|client, url| client.request(method.clone(), url))
+            .await
+    }
+
+    // Open a device stream.
+    #[inline]
+    pub(crate) async fn open(&mut self) -> Result<Response, RequestError> {
+        self.request(|client, url| client.stream(url)).await
+    }
+
+    // Contacted addresses, together with whether they have answered.
+    pub(crate) fn attempts(&self) -> impl Iterator<Item = (&str, bool)> {
+        self.targets
+            .iter()
+            .filter_map(|target| Some((target.address.as_str(), target.reachable?)))
+    }
+
+    // Perform the request trying each device address in order, until one
+    // of them answers.
+    //
+    // Returns the response of the first address which has answered, or the
+    // error status it has answered with. Each contacted address is marked
+    // as reachable or not.
+    //
+    // Only connection failures move to the next address: an answer comes
+    // from the device, which may have already acted on the request, and
+    // other addresses lead to the same device.
+    async fn request(
+        &mut self,
+        build: impl Fn(&DeviceClient, &str) -> RequestBuilder,
+    ) -> Result<Response, RequestError> {
+        for target in self.targets.iter_mut() {
+            let response = build(&self.client, &target.url).send().await;
+            target.reachable = Some(response.is_ok());
+            match response {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) if response.status() == StatusCode::UNAUTHORIZED => {
+                    debug!("Request {} unauthorized", target.url);
+                    return Err(RequestError::Unauthorized);
+                }
+                Ok(response) => {
+                    debug!("Request {} failed with {}", target.url, response.status());
+                    return Err(RequestError::Status(response.status()));
+                }
+                Err(e) => debug!("Request {} failed: {}", target.url, e),
+            }
+        }
+        Err(RequestError::Unreachable)
+    }
+
+    // Replace each route input, written as `<name>`, with its value.
+    //
+    // Values are percent-encoded, so that they cannot add path segments, a
+    // query or a fragment to the route.
+    #[inline]
+    fn fill_route(route: &str, values: &HashMap<&str, String>) -> String {
+        route
+            .split('/')
+            .map(|segment| {
+                segment
+                    .strip_prefix('<')
+                    .and_then(|name| name.strip_suffix('>'))
+                    .and_then(|name| values.get(name))
+                    .map_or(Cow::Borrowed(segment), |value| {
+                        utf8_percent_encode(value, PATH_SEGMENT).into()
+                    })
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+// Answer a single request with the given status, returning the port
+// listened on and the request line received.
+#[cfg(test)]
+pub(crate) async fn serve_once(status: u16) -> (u16, rocket::tokio::task::JoinHandle<String>) {
+    serve(format!("HTTP/1.1 {status} Status\r\n"), Vec::new()).await
+}
+
+// Answer a single request with the given body, returning the port listened
+// on and the request line received.
+#[cfg(test)]
+pub(crate) async fn serve_body(
+    content_type: &str,
+    body: Vec<u8>,
+) -> (u16, rocket::tokio::task::JoinHandle<String>) {
+    serve(
+        format!("HTTP/1.1 200 OK\r\ncontent-type: {content_type}\r\n"),
+        body,
+    )
+    .await
+}
+
+#[cfg(test)]
+async fn serve(head: String, body: Vec<u8>) -> (u16, rocket::tokio::task::JoinHandle<String>) {
+    use rocket::tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = rocket::tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = rocket::tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = vec![0; 1024];
+        let read = stream.read(&mut request).await.unwrap();
+        let head = format!(
+            "{head}content-length: {}\r\nconnection: close\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(&body).await.unwrap();
+        String::from_utf8_lossy(&request[..read])
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned()
+    });
+    (port, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::database::query::{
+        insert_boolean_input, insert_device, insert_route, select_route, select_route_inputs,
+    };
+    use crate::database::{test_connection, RouteMethod};
+
+    fn endpoint(port: u16, addresses: &[&str]) -> DeviceEndpoint {
+        DeviceEndpoint {
+            client: DeviceClient::new(&HttpConfig::default()).unwrap(),
+            metadata: Metadata {
+                id: 1,
+                port,
+                scheme: "http".into(),
+                path: "/".into(),
+                name: None,
+                last_retrieved: None,
+                last_seen: None,
+                next_retry: None,
+                retrieval_error: None,
+            },
+            addresses: addresses
+                .iter()
+                .map(|address| Address {
+                    address: (*address).into(),
+                    reachable: true,
+                    latency: None,
+                })
+                .collect(),
+            main_route: "/light".into(),
+        }
+    }
+
+    #[test]
+    fn unreadable_certificates_are_reported() {
+        let mut config = HttpConfig::default();
+        config.ca_certificate = Some("missing/devices-ca.pem".into());
+
+        assert!(matches!(
+            DeviceClient::new(&config),
+            Err(ClientError::Certificate(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_headers_are_reported() {
+        let mut config = HttpConfig::default();
+        config.headers.insert("x api key".into(), "secret".into());
+
+        assert!(matches!(
+            DeviceClient::new(&config),
+            Err(ClientError::Header(name)) if name == "x api key"
+        ));
+    }
+
+    #[rocket::async_test]
+    async fn configured_headers_are_sent() {
+        use rocket::tokio::io::AsyncReadExt;
+
+        let listener = rocket::tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut config = HttpConfig::default();
+        config.headers.insert("x-api-key".into(), "secret".into());
+        let client = DeviceClient::new(&config).unwrap();
+
+        // No answer is needed, only the request is read.
+        let request = rocket::tokio::spawn(async move {
+            let _ = client
+                .request(Method::GET, &format!("http://127.0.0.1:{port}/"))
+                .send()
+                .await;
+        });
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut received = vec![0; 1024];
+        let read = stream.read(&mut received).await.unwrap();
+        drop(stream);
+        request.await.unwrap();
+
+        let received = String::from_utf8_lossy(&received[..read]).to_lowercase();
+        assert!(received.contains(&format!(
+            "user-agent: ascot-gateway/{}\r\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(received.contains("x-api-key: secret\r\n"));
+    }
+
+    #[test]
+    fn credentials_are_sent_by_host() {
+        let mut config = HttpConfig::default();
+        config.credentials.insert(
+            "http://127.0.0.1".into(),
+            Credentials::Bearer("token".into()),
+        );
+        config.credentials.insert(
+            "https://127.0.0.2".into(),
+            Credentials::Basic {
+                username: "admin".into(),
+                password: Some("secret".into()),
+            },
+        );
+        let client = DeviceClient::new(&config).unwrap();
+
+        for (url, authorization) in [
+            ("http://127.0.0.1:3000/light", Some("Bearer token")),
+            (
+                "https://127.0.0.2:3000/light",
+                Some("Basic YWRtaW46c2VjcmV0"),
+            ),
+            // Other hosts and schemes receive nothing.
+            ("http://127.0.0.2:3000/light", None),
+            ("https://127.0.0.1:3000/light", None),
+            ("http://127.0.0.3:3000/light", None),
+        ] {
+            let request = client.request(Method::GET, url).build().unwrap();
+            assert_eq!(
+                request
+                    .headers()
+                    .get(reqwest::header::AUTHORIZATION)
+                    .map(|value| value.to_str().unwrap()),
+                authorization,
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn credentials_need_a_scheme_and_host() {
+        for key in [
+            "*",
+            "192.168.1.10",
+            "http://192.168.1.10/light",
+            "http://192.168.1.10:80",
+        ] {
+            let mut config = HttpConfig::default();
+            config
+                .credentials
+                .insert(key.into(), Credentials::Bearer("token".into()));
+
+            assert!(
+                matches!(DeviceClient::new(&config), Err(ClientError::Credentials(k)) if k == key),
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn no_credentials_are_sent_by_default() {
+        let client = DeviceClient::new(&HttpConfig::default()).unwrap();
+
+        let request = client
+            .request(Method::GET, "http://127.0.0.1:3000/light")
+            .build()
+            .unwrap();
+        assert!(!request
+            .headers()
+            .contains_key(reqwest::header::AUTHORIZATION));
+    }
+
+    #[rocket::async_test]
+    async fn refused_credentials_stop_requests() {
+        let (port, received) = serve_once(401).await;
+
+        let mut request =
+            endpoint(port, &["127.0.0.1", "127.0.0.2"]).request("/on", &HashMap::new());
+
+        assert_eq!(
+            request.send(Method::PUT).await.unwrap_err(),
+            RequestError::Unauthorized
+        );
+        received.await.unwrap();
+        assert_eq!(
+            request.attempts().collect::<Vec<_>>(),
+            [("127.0.0.1", true)]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn streams_are_opened_through_get() {
+        let (port, received) = serve_once(200).await;
+
+        let mut request = endpoint(port, &["127.0.0.1"]).request("/logs", &HashMap::new());
+
+        assert!(request.open().await.is_ok());
+        assert_eq!(received.await.unwrap(), "GET /light/logs HTTP/1.1");
+    }
+
+    #[rocket::async_test]
+    async fn stored_values_are_sent_again() {
+        let (port, received) = serve_once(200).await;
+        let (_client, mut db) = test_connection().await;
+        let device_id = insert_device(&mut db, "light", 3000, "http", "/")
+            .await
+            .unwrap();
+        let route_id = insert_route(
+            &mut db,
+            "/on/<on>",
+            RouteMethod::Put,
+            None,
+            false,
+            device_id,
+        )
+        .await
+        .unwrap();
+        insert_boolean_input(&mut db, "on", false, true, route_id)
+            .await
+            .unwrap();
+
+        let route = select_route(&mut db, route_id, device_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let inputs = select_route_inputs(&mut db, route_id).await.unwrap();
+        let mut request = endpoint(port, &["127.0.0.1"]).request(&route.route, &inputs.values());
+
+        assert!(request.send(route.rest_kind.method()).await.is_ok());
+        assert_eq!(received.await.unwrap(), "PUT /light/on/true HTTP/1.1");
+    }
+
+    #[rocket::async_test]
+    async fn routes_are_sent_through_their_method() {
+        for (method, line) in [
+            (RouteMethod::Get, "GET /light/on HTTP/1.1"),
+            (RouteMethod::Put, "PUT /light/on HTTP/1.1"),
+            (RouteMethod::Post, "POST /light/on HTTP/1.1"),
+        ] {
+            let (port, received) = serve_once(200).await;
+            let (_client, mut db) = test_connection().await;
+            let device_id = insert_device(&mut db, "light", 3000, "http", "/")
+                .await
+                .unwrap();
+            let route_id = insert_route(&mut db, "/on", method, None, false, device_id)
+                .await
+                .unwrap();
+
+            let route = select_route(&mut db, route_id, device_id)
+                .await
+                .unwrap()
+                .unwrap();
+            let mut request = endpoint(port, &["127.0.0.1"]).request(&route.route, &HashMap::new());
+
+            assert!(request.send(route.rest_kind.method()).await.is_ok());
+            assert_eq!(received.await.unwrap(), line);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn dead_addresses_are_skipped() {
+        let (port, received) = serve_once(200).await;
+
+        // Only the first loopback address is listened on, hence connections
+        // to the second one are refused.
+        let mut request =
+            endpoint(port, &["127.0.0.2", "127.0.0.1"]).request("/on", &HashMap::new());
+
+        assert!(request.send(Method::PUT).await.is_ok());
+        assert_eq!(received.await.unwrap(), "PUT /light/on HTTP/1.1");
+        assert_eq!(
+            request.attempts().collect::<Vec<_>>(),
+            [("127.0.0.2", false), ("127.0.0.1", true)]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn error_statuses_stop_requests() {
+        let (port, received) = serve_once(500).await;
+        // The second address listens too, but it must not be contacted.
+        let second = rocket::tokio::net::TcpListener::bind(("127.0.0.2", port))
+            .await
+            .unwrap();
+
+        let mut request =
+            endpoint(port, &["127.0.0.1", "127.0.0.2"]).request("/on", &HashMap::new());
+
+        assert_eq!(
+            request.send(Method::PUT).await.unwrap_err(),
+            RequestError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert_eq!(received.await.unwrap(), "PUT /light/on HTTP/1.1");
+        assert_eq!(
+            request.attempts().collect::<Vec<_>>(),
+            [("127.0.0.1", true)]
+        );
+        assert!(rocket::tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            second.accept()
+        )
+        .await
+        .is_err());
+    }
+
+    #[rocket::async_test]
+    async fn unreachable_addresses_are_tried_last() {
+        let (port, received) = serve_once(200).await;
+
+        let mut endpoint = endpoint(port, &["127.0.0.2", "127.0.0.1"]);
+        endpoint.addresses[0].reachable = false;
+        let mut request = endpoint.request("/on", &HashMap::new());
+
+        assert!(request.send(Method::PUT).await.is_ok());
+        received.await.unwrap();
+        assert_eq!(
+            request.attempts().collect::<Vec<_>>(),
+            [("127.0.0.1", true)]
+        );
+    }
+
+    #[test]
+    fn input_values_stay_within_their_segment() {
+        let values = HashMap::from([("name", "../reboot?now#x".to_string())]);
+
+        assert_eq!(
+            DeviceRequest::fill_route("/name/<name>", &values),
+            "/name/..%2Freboot%3Fnow%23x"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::{Arc, RwLock};
+
+use mdns_sd::{IfKind, Receiver, ServiceDaemon, ServiceEvent};
+
+use crate::logs::LogBuffer;
+
+// Consecutive browse failures after which the daemon is recreated.
+const MAX_BROWSE_FAILURES: usize = 3;
+
+// Service state.
+//
+// The mDNS daemon is recreated when it repeatedly fails to browse, for
+// example after a network interface has gone down. Clones share the same
+// daemon.
+#[derive(Clone)]
+pub(crate) struct ServiceState {
+    daemon: Arc<RwLock<ServiceDaemon>>,
+    // Network interface the daemon is restricted to, all when missing.
+    interface: Option<String>,
+}
+
+impl ServiceState {
+    // Create the service state with a new daemon, restricted to a network
+    // interface when given.
+    pub(crate) fn new(interface: Option<String>) -> Result<Self, mdns_sd::Error> {
+        Ok(Self {
+            daemon: Arc::new(RwLock::new(create_daemon(interface.as_deref())?)),
+            interface,
+        })
+    }
+
+    // Browse a service type.
+    //
+    // When browsing keeps failing, the daemon is recreated and the service
+    // type is browsed again through the new daemon.
+    pub(crate) fn browse(
+        &self,
+        service_type: &str,
+        logs: &LogBuffer,
+    ) -> Result<Receiver<ServiceEvent>, mdns_sd::Error> {
+        let mut failures = 0;
+        let error = loop {
+            match self.daemon().browse(service_type) {
+                Ok(receiver) => return Ok(receiver),
+                Err(e) => {
+                    logs.error(format!("Failed to browse {}: {}", service_type, e));
+                    failures += 1;
+                    if failures == MAX_BROWSE_FAILURES {
+                        break e;
+                    }
+                }
+            }
+        };
+
+        let daemon = create_daemon(self.interface.as_deref()).map_err(|e| {
+            logs.error(format!("Failed to recreate the mDNS daemon: {}", e));
+            e
+        })?;
+        let receiver = daemon.browse(service_type).map_err(|e| {
+            logs.error(format!(
+                "Failed to browse {} after recreating the mDNS daemon: {}",
+                service_type, e
+            ));
+            e
+        })?;
+
+        // Replace the failed daemon, stopping its thread if still running.
+        let old = std::mem::replace(&mut *self.lock(), daemon);
+        let _ = old.shutdown();
+        logs.warn(format!(
+            "mDNS daemon recreated after failing to browse {}: {}",
+            service_type, error
+        ));
+        Ok(receiver)
+    }
+
+    // Return the current daemon.
+    pub(crate) fn daemon(&self) -> ServiceDaemon {
+        self.daemon
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    // A panic while holding the lock cannot leave the daemon inconsistent,
+    // hence a poisoned lock is still used.
+    fn lock(&self) -> std::sync::RwLockWriteGuard<'_, ServiceDaemon> {
+        self.daemon
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Create a daemon, restricted to a network interface when given.
+fn create_daemon(interface: Option<&str>) -> Result<ServiceDaemon, mdns_sd::Error> {
+    let daemon = ServiceDaemon::new()?;
+    if let Some(interface) = interface {
+        daemon.disable_interface(IfKind::All)?;
+        daemon.enable_interface(IfKind::Name(interface.into()))?;
+    }
+    Ok(daemon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::logs::Level;
+    use crate::SERVICE_TYPE;
+
+    #[test]
+    fn failed_daemons_are_recreated() {
+        let state = ServiceState::new(None).unwrap();
+        let logs = LogBuffer::default();
+
+        // Wait for the daemon thread to stop.
+        let daemon = state.daemon();
+        let _ = daemon.shutdown();
+        let mut attempts = 0;
+        while daemon.browse(SERVICE_TYPE).is_ok() {
+            attempts += 1;
+            assert!(attempts < 100, "daemon not stopped");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(state.browse(SERVICE_TYPE, &logs).is_ok());
+        assert!(state.daemon().browse(SERVICE_TYPE).is_ok());
+
+        let entries = logs.entries();
+        assert_eq!(entries[0].level, Level::Warning);
+        assert!(entries[0].message.starts_with("mDNS daemon recreated"));
+    }
+
+    #[test]
+    fn recreated_daemons_keep_their_interface() {
+        let state = ServiceState::new(Some("lo".into())).unwrap();
+        let _ = state.daemon().shutdown();
+
+        let clone = state.clone();
+        assert_eq!(clone.interface.as_deref(), Some("lo"));
+        assert!(clone.browse(SERVICE_TYPE, &LogBuffer::default()).is_ok());
+    }
+}
@@ -9,9 +9,10 @@ use rocket_db_pools::Connection;
 
 use crate::database::controls::StateControls;
 use crate::database::device::Device;
-use crate::database::query::{clear_database, insert_address, insert_device};
+use crate::database::query::clear_database;
 use crate::database::{Devices, Metadata};
-use crate::error::{query_error, InternalError};
+use crate::error::{query_error, GatewayError};
+use crate::time::now;
 
 fn device1() -> Device {
     let mut routes = Routes::init();
@@ -84,14 +85,23 @@ fn device1() -> Device {
             port: 8080,
             scheme: "http".into(),
             path: "here".into(),
+            name: None,
+            last_retrieved: Some(now()),
+            last_seen: Some(now()),
+            next_retry: None,
+            retrieval_error: None,
         },
         addresses: Vec::new(),
+        properties: Vec::new(),
         data: DeviceData {
             kind: DeviceKind::Light,
             main_route: MiniString::new("/light").unwrap(),
             routes,
         },
         state_controls: StateControls::default(),
+        stale: false,
+        reachable: true,
+        last_seen: None,
     }
 }
 
@@ -169,50 +179,40 @@ fn device2() -> Device {
             port: 8085,
             scheme: "https".into(),
             path: "second".into(),
+            name: None,
+            last_retrieved: Some(now()),
+            last_seen: Some(now()),
+            next_retry: None,
+            retrieval_error: None,
         },
 
         addresses: Vec::new(),
+        properties: Vec::new(),
         data: DeviceData {
             kind: DeviceKind::Light,
             main_route: MiniString::new("/light").unwrap(),
             routes,
         },
         state_controls: StateControls::default(),
+        stale: false,
+        reachable: true,
+        last_seen: None,
     }
 }
 
 pub(crate) async fn generate_devices_and_init_db(
-    mut db: Connection<Devices>,
+    db: &mut Connection<Devices>,
     uri: &Origin<'_>,
-) -> Result<Vec<Device>, InternalError> {
+) -> Result<Vec<Device>, GatewayError> {
     let mut devices = vec![device1(), device2()];
 
     // Clear the database.
-    query_error(clear_database(&mut db), uri).await?;
+    query_error(clear_database(db), uri).await?;
 
     // Insert device data into the database.
     for device in devices.iter_mut() {
-        let id = query_error(
-            insert_device(
-                &mut db,
-                device.metadata.port,
-                &device.metadata.scheme,
-                &device.metadata.path,
-            ),
-            uri,
-        )
-        .await?;
-
-        // Save addresses
-        for address in device.addresses.iter() {
-            query_error(
-                insert_address(&mut db, address.address.to_string(), id),
-                uri,
-            )
-            .await?;
-        }
-
-        query_error(device.insert_routes(&mut db), uri).await?;
+        let fullname = format!("device{}.{}", device.metadata.id, crate::SERVICE_TYPE);
+        query_error(device.insert(db, &fullname), uri).await?;
     }
 
     Ok(devices)
@@ -0,0 +1,105 @@
+use rocket::figment::Figment;
+use rocket::http::{ContentType, Cookie, Header};
+use rocket::local::asynchronous::{Client, LocalRequest, LocalResponse};
+use rocket::request::FromRequest;
+
+use rocket_db_pools::Connection;
+
+use crate::csrf::{CSRF_COOKIE, CSRF_HEADER};
+use crate::database::query::{insert_address, insert_device, insert_main_route, insert_route};
+use crate::database::{Devices, RouteMethod};
+use crate::gateway;
+
+// Start a gateway backed by an in-memory database.
+pub(crate) async fn client() -> Client {
+    configured_client(|figment| figment).await
+}
+
+// Start a gateway backed by an in-memory database, used by the `admin` user
+// with the `secret` password.
+pub(crate) async fn auth_client(protect_reads: bool) -> Client {
+    configured_client(|figment| {
+        figment
+            .merge(("gateway.auth.username", "admin"))
+            .merge(("gateway.auth.password", "secret"))
+            .merge(("gateway.auth.protect_reads", protect_reads))
+    })
+    .await
+}
+
+// Start a gateway backed by an in-memory database, with a custom
+// configuration.
+pub(crate) async fn configured_client(configure: impl FnOnce(Figment) -> Figment) -> Client {
+    let rocket = gateway();
+    let figment = rocket
+        .figment()
+        .clone()
+        .merge(("databases.devices.url", "sqlite::memory:"))
+        .merge(("databases.devices.max_connections", 1));
+    Client::tracked(rocket.configure(configure(figment)))
+        .await
+        .unwrap()
+}
+
+// Submit a form through `POST`.
+pub(crate) async fn post_form<'c>(client: &'c Client, uri: &str, body: &str) -> LocalResponse<'c> {
+    submit_form(client.post(uri.to_owned()), body).await
+}
+
+// Submit a form through `PUT`.
+pub(crate) async fn put_form<'c>(client: &'c Client, uri: &str, body: &str) -> LocalResponse<'c> {
+    submit_form(client.put(uri.to_owned()), body).await
+}
+
+// Send the CSRF token of the client with a request, issuing one when the
+// client has not received it yet.
+pub(crate) fn with_csrf(request: LocalRequest<'_>) -> LocalRequest<'_> {
+    let token = request
+        .inner()
+        .cookies()
+        .get_private(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_owned());
+    match token {
+        Some(token) => request.header(Header::new(CSRF_HEADER, token)),
+        None => request
+            .private_cookie(Cookie::new(CSRF_COOKIE, "token"))
+            .header(Header::new(CSRF_HEADER, "token")),
+    }
+}
+
+// Submit a form, together with the CSRF token.
+pub(crate) async fn submit_form<'c>(request: LocalRequest<'c>, body: &str) -> LocalResponse<'c> {
+    with_csrf(request)
+        .header(ContentType::Form)
+        .body(body)
+        .dispatch()
+        .await
+}
+
+// Open a connection to the database of a gateway.
+//
+// The pool has a single connection, hence it must be dropped before
+// dispatching a request which uses the database.
+pub(crate) async fn connection(client: &Client) -> Connection<Devices> {
+    Connection::<Devices>::from_request(client.get("/").inner())
+        .await
+        .succeeded()
+        .unwrap()
+}
+
+// Store a device listening on the given local port with a single route,
+// returning the device identifier.
+pub(crate) async fn local_device(client: &Client, port: u16, route: &str) -> u16 {
+    let mut db = connection(client).await;
+    let id = insert_device(&mut db, "light", port, "http", "/")
+        .await
+        .unwrap();
+    insert_address(&mut db, "127.0.0.1".into(), id)
+        .await
+        .unwrap();
+    insert_main_route(&mut db, "/light", id).await.unwrap();
+    insert_route(&mut db, route, RouteMethod::Put, None, false, id)
+        .await
+        .unwrap();
+    id
+}
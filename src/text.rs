@@ -0,0 +1,87 @@
+use std::borrow::Cow;
+
+use serde::Deserialize;
+
+// Indicator appended to truncated texts.
+const ELLIPSIS: char = '…';
+
+// Display limits, in characters, for texts supplied by devices.
+//
+// They are distinct from the hard limits of the ascot library.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct TextLimits {
+    // Maximum length of names.
+    pub(crate) name: usize,
+    // Maximum length of descriptions.
+    pub(crate) description: usize,
+    // Maximum length of device responses.
+    pub(crate) response: usize,
+}
+
+impl Default for TextLimits {
+    fn default() -> Self {
+        Self {
+            name: 64,
+            description: 256,
+            response: 1024,
+        }
+    }
+}
+
+impl TextLimits {
+    // Truncate a name.
+    #[inline]
+    pub(crate) fn name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        truncate(name, self.name)
+    }
+
+    // Truncate a description.
+    #[inline]
+    pub(crate) fn description<'a>(&self, description: &'a str) -> Cow<'a, str> {
+        truncate(description, self.description)
+    }
+
+    // Truncate a device response.
+    #[inline]
+    pub(crate) fn response<'a>(&self, response: &'a str) -> Cow<'a, str> {
+        truncate(response, self.response)
+    }
+}
+
+// Truncate a text to at most `max` characters.
+//
+// A truncated text ends with an ellipsis.
+fn truncate(text: &str, max: usize) -> Cow<'_, str> {
+    if text.chars().nth(max).is_none() {
+        return Cow::Borrowed(text);
+    }
+
+    let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+    truncated.push(ELLIPSIS);
+    Cow::Owned(truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_texts_are_kept() {
+        assert!(matches!(truncate("light", 5), Cow::Borrowed("light")));
+    }
+
+    #[test]
+    fn long_texts_end_with_an_ellipsis() {
+        let truncated = truncate("kitchen light", 8);
+
+        assert_eq!(truncated, "kitchen…");
+        assert_eq!(truncated.chars().count(), 8);
+    }
+
+    #[test]
+    fn characters_are_counted_instead_of_bytes() {
+        assert_eq!(truncate("àèìòù", 5), "àèìòù");
+        assert_eq!(truncate("àèìòù", 3), "àè…");
+    }
+}
@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use serde::Deserialize;
+
+// Timestamps format.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S %Z";
+
+// Returns the current UTC epoch, in seconds.
+#[inline]
+pub(crate) fn now() -> i64 {
+    Utc::now().timestamp()
+}
+
+// Timezone used to display timestamps.
+//
+// Timestamps are always stored as UTC epochs, they are converted into this
+// timezone only when rendered.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Timezone(Tz);
+
+impl Default for Timezone {
+    fn default() -> Self {
+        Self(Tz::UTC)
+    }
+}
+
+impl Timezone {
+    // Format a UTC epoch, expressed in seconds, in this timezone.
+    //
+    // Returns `None` when the epoch is out of range.
+    pub(crate) fn format(&self, epoch: i64) -> Option<String> {
+        DateTime::from_timestamp(epoch, 0).map(|utc| {
+            utc.with_timezone(&self.0)
+                .format(TIMESTAMP_FORMAT)
+                .to_string()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Start of the daylight saving time in Europe, on 2024-03-31 01:00 UTC.
+    const DST_START: i64 = 1_711_846_800;
+
+    // End of the daylight saving time in Europe, on 2024-10-27 01:00 UTC.
+    const DST_END: i64 = 1_729_990_800;
+
+    fn rome() -> Timezone {
+        serde_json::from_str("\"Europe/Rome\"").unwrap()
+    }
+
+    #[test]
+    fn default_timezone_is_utc() {
+        assert_eq!(
+            Timezone::default().format(DST_START).unwrap(),
+            "2024-03-31 01:00:00 UTC"
+        );
+    }
+
+    #[test]
+    fn clocks_move_forward_at_dst_start() {
+        let rome = rome();
+
+        assert_eq!(
+            rome.format(DST_START - 1).unwrap(),
+            "2024-03-31 01:59:59 CET"
+        );
+        assert_eq!(rome.format(DST_START).unwrap(), "2024-03-31 03:00:00 CEST");
+    }
+
+    #[test]
+    fn clocks_move_back_at_dst_end() {
+        let rome = rome();
+
+        assert_eq!(
+            rome.format(DST_END - 1).unwrap(),
+            "2024-10-27 02:59:59 CEST"
+        );
+        assert_eq!(rome.format(DST_END).unwrap(), "2024-10-27 02:00:00 CET");
+    }
+
+    #[test]
+    fn unknown_timezone_is_rejected() {
+        assert!(serde_json::from_str::<Timezone>("\"Europe/Atlantis\"").is_err());
+    }
+
+    #[test]
+    fn out_of_range_epoch_is_not_formatted() {
+        assert_eq!(Timezone::default().format(i64::MAX), None);
+    }
+}